@@ -0,0 +1,132 @@
+use crate::merkle_tree::MerkleTree;
+use crate::state::{
+    CommitmentQueueAccount, MerkleTreeAccount, COMMITMENT_BATCH_SIZE, COMMITMENT_QUEUE_CAPACITY,
+};
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use light_hasher::Hasher;
+
+pub struct CommitmentQueue;
+
+impl CommitmentQueue {
+    /// Adds a pair of output commitments to `tree_account`, returning the leaf index of
+    /// the first and the Merkle path shared by both above the pair. Trees with the queue
+    /// enabled only assign the indices here and leave the hashing, and so the path, to
+    /// `insert_commitment_batch`.
+    pub fn append_pair<H: Hasher>(
+        leaves: [[u8; 32]; 2],
+        tree_account: &mut MerkleTreeAccount,
+        queue: Option<&AccountLoader<CommitmentQueueAccount>>,
+    ) -> Result<(u64, Vec<[u8; 32]>)> {
+        let first_index = tree_account.next_index;
+        if tree_account.queue_enabled == 0 {
+            let [mut path, _] = MerkleTree::append_pair::<H>(leaves, tree_account)?;
+            return Ok((first_index, path.split_off(1)));
+        }
+
+        let max_capacity = 1u64 << tree_account.height;
+        let next_index = first_index
+            .checked_add(2)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(next_index <= max_capacity, ErrorCode::MerkleTreeFull);
+
+        let queue = &mut queue
+            .ok_or(ErrorCode::CommitmentQueueRequired)?
+            .load_mut()?;
+        for leaf in leaves {
+            Self::push(queue, leaf)?;
+        }
+
+        tree_account.next_index = next_index;
+        Ok((first_index, Vec::new()))
+    }
+
+    pub fn push(queue: &mut CommitmentQueueAccount, leaf: [u8; 32]) -> Result<()> {
+        require!(
+            (queue.count as usize) < COMMITMENT_QUEUE_CAPACITY,
+            ErrorCode::CommitmentQueueFull
+        );
+
+        let position = (queue.head as usize + queue.count as usize) % COMMITMENT_QUEUE_CAPACITY;
+        queue.leaves[position] = leaf;
+        queue.count += 1;
+        Ok(())
+    }
+
+    /// The first `count` queued leaves, padded with zeroes to a full batch.
+    pub fn peek_batch(queue: &CommitmentQueueAccount, count: usize) -> [[u8; 32]; COMMITMENT_BATCH_SIZE] {
+        let mut batch = [[0u8; 32]; COMMITMENT_BATCH_SIZE];
+        for (offset, leaf) in batch.iter_mut().take(count).enumerate() {
+            *leaf = queue.leaves[(queue.head as usize + offset) % COMMITMENT_QUEUE_CAPACITY];
+        }
+        batch
+    }
+
+    /// Drops the first `count` leaves once they have been inserted into the tree.
+    pub fn pop_batch(queue: &mut CommitmentQueueAccount, count: usize) -> Result<()> {
+        require!(
+            count <= queue.count as usize,
+            ErrorCode::InvalidCommitmentBatch
+        );
+
+        queue.head = ((queue.head as usize + count) % COMMITMENT_QUEUE_CAPACITY) as u64;
+        queue.count -= count as u64;
+        queue.next_insert_index = queue
+            .next_insert_index
+            .checked_add(count as u64)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(index: u64) -> [u8; 32] {
+        let mut leaf = [0u8; 32];
+        leaf[24..].copy_from_slice(&(index + 1).to_be_bytes());
+        leaf
+    }
+
+    #[test]
+    fn batches_come_out_in_queue_order_across_the_wrap() {
+        let mut queue: Box<CommitmentQueueAccount> = Box::new(bytemuck::Zeroable::zeroed());
+        let mut pushed = 0u64;
+        let mut inserted = 0u64;
+
+        // keep the queue nearly full so its front runs past the end of `leaves` twice
+        for _ in 0..3 * COMMITMENT_QUEUE_CAPACITY / COMMITMENT_BATCH_SIZE {
+            while (queue.count as usize) < COMMITMENT_QUEUE_CAPACITY {
+                CommitmentQueue::push(&mut queue, leaf(pushed)).unwrap();
+                pushed += 1;
+            }
+            assert!(CommitmentQueue::push(&mut queue, leaf(pushed)).is_err());
+
+            let batch = CommitmentQueue::peek_batch(&queue, COMMITMENT_BATCH_SIZE);
+            for (offset, queued) in batch.iter().enumerate() {
+                assert_eq!(*queued, leaf(inserted + offset as u64));
+            }
+            CommitmentQueue::pop_batch(&mut queue, COMMITMENT_BATCH_SIZE).unwrap();
+            inserted += COMMITMENT_BATCH_SIZE as u64;
+            assert_eq!(queue.next_insert_index, inserted);
+        }
+    }
+
+    #[test]
+    fn partial_batches_are_padded_and_pops_are_bounded() {
+        let mut queue: Box<CommitmentQueueAccount> = Box::new(bytemuck::Zeroable::zeroed());
+        for index in 0..3 {
+            CommitmentQueue::push(&mut queue, leaf(index)).unwrap();
+        }
+
+        let batch = CommitmentQueue::peek_batch(&queue, 3);
+        assert_eq!(&batch[..3], &[leaf(0), leaf(1), leaf(2)]);
+        assert!(batch[3..].iter().all(|padding| *padding == [0u8; 32]));
+
+        assert!(CommitmentQueue::pop_batch(&mut queue, 4).is_err());
+        CommitmentQueue::pop_batch(&mut queue, 3).unwrap();
+        assert_eq!(queue.count, 0);
+        assert_eq!(queue.next_insert_index, 3);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::state::DENYLIST_TREE_HEIGHT;
+use crate::{DenylistProof, ErrorCode};
+use anchor_lang::prelude::*;
+use solana_program::hash::hashv;
+
+// Domain separation between leaves and inner nodes
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Bounds of the outermost gaps: the list always starts after `MIN_ADDRESS` and ends
+/// before `MAX_ADDRESS`, so every address falls into exactly one gap.
+pub const MIN_ADDRESS: Pubkey = Pubkey::new_from_array([0u8; 32]);
+pub const MAX_ADDRESS: Pubkey = Pubkey::new_from_array([0xff; 32]);
+
+/// Deposit screening denylist, kept off-chain by the screening authority and committed
+/// on-chain as a Merkle root.
+///
+/// The denied addresses are sorted, and leaf `i` is the gap between the `i`th address
+/// and the next one: `hash(0 || low || high)`. An address that is strictly inside a
+/// gap under the current root is not on the list, so lists of any size are checked
+/// with one Merkle path.
+pub struct Denylist;
+
+impl Denylist {
+    pub fn hash_leaf(low: &Pubkey, high: &Pubkey) -> [u8; 32] {
+        hashv(&[LEAF_PREFIX, low.as_ref(), high.as_ref()]).to_bytes()
+    }
+
+    pub fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        hashv(&[NODE_PREFIX, left, right]).to_bytes()
+    }
+
+    /// Root of the empty list: a single gap from `MIN_ADDRESS` to `MAX_ADDRESS`.
+    pub fn empty_root() -> [u8; 32] {
+        let mut node = Self::hash_leaf(&MIN_ADDRESS, &MAX_ADDRESS);
+        let mut zero = [0u8; 32];
+        for _ in 0..DENYLIST_TREE_HEIGHT {
+            node = Self::hash_node(&node, &zero);
+            zero = Self::hash_node(&zero, &zero);
+        }
+        node
+    }
+
+    /// Checks that `address` is not on the list with root `root`, failing with
+    /// `DepositorDenied` if the proof's gap is bounded by the address itself.
+    pub fn check_not_denied(root: &[u8; 32], address: &Pubkey, proof: &DenylistProof) -> Result<()> {
+        require!(
+            proof.low != *address && proof.high != *address,
+            ErrorCode::DepositorDenied
+        );
+        require!(
+            proof.low < *address && *address < proof.high,
+            ErrorCode::InvalidDenylistProof
+        );
+        require!(
+            proof.leaf_index < 1u64 << DENYLIST_TREE_HEIGHT,
+            ErrorCode::InvalidDenylistProof
+        );
+
+        let mut node = Self::hash_leaf(&proof.low, &proof.high);
+        for (level, sibling) in proof.path.iter().enumerate() {
+            node = if (proof.leaf_index >> level) & 1 == 0 {
+                Self::hash_node(&node, sibling)
+            } else {
+                Self::hash_node(sibling, &node)
+            };
+        }
+        require!(node == *root, ErrorCode::InvalidDenylistProof);
+        Ok(())
+    }
+
+    /// Checks that a clearance was recorded against the current root; one proven
+    /// before the last update is stale and has to be renewed with `clear_depositor`.
+    pub fn check_clearance(clearance_root: &[u8; 32], root: &[u8; 32]) -> Result<()> {
+        require!(clearance_root == root, ErrorCode::StaleDepositorClearance);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The screening authority's copy of the list, as it publishes it for depositors.
+    struct OffChainList {
+        addresses: Vec<Pubkey>,
+    }
+
+    impl OffChainList {
+        fn new(addresses: &[Pubkey]) -> Self {
+            let mut addresses = addresses.to_vec();
+            addresses.sort();
+            addresses.dedup();
+            Self { addresses }
+        }
+
+        fn add(&mut self, address: Pubkey) {
+            if let Err(position) = self.addresses.binary_search(&address) {
+                self.addresses.insert(position, address);
+            }
+        }
+
+        fn remove(&mut self, address: &Pubkey) {
+            self.addresses.retain(|denied| denied != address);
+        }
+
+        fn gaps(&self) -> Vec<(Pubkey, Pubkey)> {
+            let mut bounds = vec![MIN_ADDRESS];
+            bounds.extend_from_slice(&self.addresses);
+            bounds.push(MAX_ADDRESS);
+            bounds.windows(2).map(|pair| (pair[0], pair[1])).collect()
+        }
+
+        fn layers(&self) -> Vec<Vec<[u8; 32]>> {
+            let mut layers = vec![self
+                .gaps()
+                .iter()
+                .map(|(low, high)| Denylist::hash_leaf(low, high))
+                .collect::<Vec<_>>()];
+            let mut zero = [0u8; 32];
+            for level in 0..DENYLIST_TREE_HEIGHT {
+                let next = layers[level]
+                    .chunks(2)
+                    .map(|pair| Denylist::hash_node(&pair[0], pair.get(1).unwrap_or(&zero)))
+                    .collect();
+                layers.push(next);
+                zero = Denylist::hash_node(&zero, &zero);
+            }
+            layers
+        }
+
+        fn root(&self) -> [u8; 32] {
+            self.layers()[DENYLIST_TREE_HEIGHT][0]
+        }
+
+        /// Proof for the gap containing `address`, or for the gap it bounds if denied.
+        fn proof(&self, address: &Pubkey) -> DenylistProof {
+            let gaps = self.gaps();
+            let leaf_index = gaps
+                .iter()
+                .position(|(low, high)| low <= address && address < high)
+                .unwrap();
+
+            let layers = self.layers();
+            let mut zero = [0u8; 32];
+            let mut path = [[0u8; 32]; DENYLIST_TREE_HEIGHT];
+            for (level, sibling) in path.iter_mut().enumerate() {
+                *sibling = *layers[level].get((leaf_index >> level) ^ 1).unwrap_or(&zero);
+                zero = Denylist::hash_node(&zero, &zero);
+            }
+
+            DenylistProof {
+                low: gaps[leaf_index].0,
+                high: gaps[leaf_index].1,
+                leaf_index: leaf_index as u64,
+                path,
+            }
+        }
+    }
+
+    fn address(seed: u32) -> Pubkey {
+        Pubkey::new_from_array(hashv(&[&seed.to_le_bytes()]).to_bytes())
+    }
+
+    fn check(list: &OffChainList, root: &[u8; 32], address: &Pubkey) -> Result<()> {
+        Denylist::check_not_denied(root, address, &list.proof(address))
+    }
+
+    #[test]
+    fn empty_list_denies_nobody() {
+        let list = OffChainList::new(&[]);
+        assert_eq!(list.root(), Denylist::empty_root());
+        for seed in 0..20 {
+            assert!(check(&list, &Denylist::empty_root(), &address(seed)).is_ok());
+        }
+    }
+
+    #[test]
+    fn large_list_is_checked_with_one_path() {
+        let denied: Vec<Pubkey> = (0..2_000).map(address).collect();
+        let list = OffChainList::new(&denied);
+        let root = list.root();
+
+        for address in denied.iter().step_by(97) {
+            assert_eq!(
+                check(&list, &root, address).unwrap_err(),
+                error!(ErrorCode::DepositorDenied)
+            );
+        }
+        for seed in 2_000..2_100 {
+            assert!(check(&list, &root, &address(seed)).is_ok());
+        }
+    }
+
+    #[test]
+    fn updates_change_who_is_denied() {
+        let mut list = OffChainList::new(&(0..50).map(address).collect::<Vec<_>>());
+        let old_root = list.root();
+        let newcomer = address(100);
+        let old_proof = list.proof(&newcomer);
+        assert!(Denylist::check_not_denied(&old_root, &newcomer, &old_proof).is_ok());
+
+        // once added, the newcomer is denied, and its old proof is stale
+        list.add(newcomer);
+        let new_root = list.root();
+        assert_eq!(
+            check(&list, &new_root, &newcomer).unwrap_err(),
+            error!(ErrorCode::DepositorDenied)
+        );
+        assert!(Denylist::check_not_denied(&new_root, &newcomer, &old_proof).is_err());
+
+        // clearances recorded before the update have to be renewed
+        assert!(Denylist::check_clearance(&new_root, &new_root).is_ok());
+        assert_eq!(
+            Denylist::check_clearance(&old_root, &new_root).unwrap_err(),
+            error!(ErrorCode::StaleDepositorClearance)
+        );
+
+        // removing an address clears it again
+        let removed = address(7);
+        list.remove(&removed);
+        assert!(check(&list, &list.root(), &removed).is_ok());
+        assert!(check(&list, &list.root(), &newcomer).is_err());
+    }
+
+    #[test]
+    fn rejects_forged_gaps() {
+        let list = OffChainList::new(&(0..10).map(address).collect::<Vec<_>>());
+        let root = list.root();
+        let denied = address(3);
+
+        // a gap widened over a denied address is not a leaf of the list
+        let mut proof = list.proof(&address(11));
+        let widened = OffChainList::new(&[]).proof(&denied);
+        proof.low = widened.low;
+        proof.high = widened.high;
+        assert_eq!(
+            Denylist::check_not_denied(&root, &denied, &proof).unwrap_err(),
+            error!(ErrorCode::InvalidDenylistProof)
+        );
+
+        // nor is a path to a leaf index that doesn't fit the tree
+        let mut proof = list.proof(&address(11));
+        proof.leaf_index += 1 << DENYLIST_TREE_HEIGHT;
+        assert!(Denylist::check_not_denied(&root, &address(11), &proof).is_err());
+    }
+}
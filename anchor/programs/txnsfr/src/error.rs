@@ -42,4 +42,78 @@ pub enum ErrorCode {
     InvalidMintAddress,
     #[msg("Invalid token account mint address")]
     InvalidTokenAccountMintAddress,
+    #[msg("Withdrawal exceeds the pool outflow limit for the current window")]
+    OutflowLimitExceeded,
+    #[msg("Invalid outflow limit: rate must be at most 10000 basis points and the window non-empty")]
+    InvalidOutflowLimit,
+    #[msg("Pool is in withdraw-only mode: deposits and shielded transfers are disabled")]
+    WithdrawOnlyMode,
+    #[msg("Invalid withdraw-only duration: must be between 1 and MAX_WITHDRAW_ONLY_SLOTS")]
+    InvalidWithdrawOnlyDuration,
+    #[msg("Authority transfer timelock has not elapsed yet")]
+    AuthorityTimelockActive,
+    #[msg("Insufficient unclaimed fees in the fee vault")]
+    InsufficientFeesToClaim,
+    #[msg("Fee recipient is not a registered, active relayer")]
+    RelayerNotRegistered,
+    #[msg("Relayer is jailed")]
+    RelayerJailed,
+    #[msg("Relayer stake is below the configured minimum")]
+    InsufficientRelayerStake,
+    #[msg("Relayer endpoint is too long")]
+    InvalidRelayerEndpoint,
+    #[msg("Relayer is not unbonding or its unbonding period has not elapsed")]
+    RelayerUnbondingActive,
+    #[msg("Slash amount exceeds the relayer's stake")]
+    InvalidSlashAmount,
+    #[msg("Note archive page does not match the leaf index")]
+    InvalidNoteArchivePage,
+    #[msg("Encrypted output exceeds the maximum encrypted output length")]
+    EncryptedOutputTooLarge,
+    #[msg("Note archive page is not full or still within its retention period")]
+    NoteArchiveRetentionActive,
+    #[msg("Encrypted output length is not one of the allowed lengths")]
+    InvalidEncryptedOutputLength,
+    #[msg("Depositor is on the deposit denylist")]
+    DepositorDenied,
+    #[msg("Deposits need the denylist account and the signer's clearance while screening is enabled")]
+    DepositorClearanceRequired,
+    #[msg("Denylist proof is invalid")]
+    InvalidDenylistProof,
+    #[msg("Verifying key has too many public inputs")]
+    InvalidVerifyingKey,
+    #[msg("File listing must have a non-zero price and a seller key inside the field")]
+    InvalidFileListing,
+    #[msg("Verifying key account is required for this circuit")]
+    VerifyingKeyRequired,
+    #[msg("Current slot is outside the proof's timelock window")]
+    TimelockWindowMismatch,
+    #[msg("Withdrawal payouts must be non-zero, match the remaining accounts and sum to the withdrawal")]
+    InvalidWithdrawalPayouts,
+    #[msg("Proof buffer write is out of bounds")]
+    InvalidProofBufferWrite,
+    #[msg("Proof buffer does not hold valid transact arguments")]
+    InvalidProofBuffer,
+    #[msg("Merkle tree pair append must start at an even leaf index")]
+    MerkleTreeMisaligned,
+    #[msg("Tree shard ID is out of range")]
+    InvalidTreeShard,
+    #[msg("This tree queues its outputs: the commitment queue account is required")]
+    CommitmentQueueRequired,
+    #[msg("Commitment queue is full: insert a batch first")]
+    CommitmentQueueFull,
+    #[msg("Invalid commitment batch")]
+    InvalidCommitmentBatch,
+    #[msg("Main tree spends go through the nullifier tree: use execute_from_buffer_with_nullifier_tree")]
+    NullifierTreeEnabled,
+    #[msg("Nullifier tree proof is invalid, out of date, or for a nullifier that was already spent")]
+    InvalidNullifierProof,
+    #[msg("This circuit needs the association set account")]
+    AssociationSetRequired,
+    #[msg("Association root is not known in the association set")]
+    UnknownAssociationRoot,
+    #[msg("Relayer fee exceeds the relayer's registered fee rate")]
+    RelayerFeeTooHigh,
+    #[msg("Depositor clearance is for an older denylist; clear the depositor again")]
+    StaleDepositorClearance,
 }
@@ -5,16 +5,25 @@ use ark_ff::PrimeField;
 use light_hasher::Poseidon;
 use solana_security_txt::security_txt;
 
+pub mod commitment_queue;
+pub mod denylist;
 pub mod error;
 pub mod errors;
 pub mod groth16;
 pub mod merkle_tree;
+pub mod note_archive;
+pub mod nullifier_tree;
 pub mod state;
 pub mod utils;
 
+use commitment_queue::CommitmentQueue;
+use denylist::Denylist;
 use error::ErrorCode;
 use merkle_tree::MerkleTree;
+use note_archive::NoteArchive;
+use nullifier_tree::NullifierTree;
 use state::*;
+use utils::{verify_proof, VERIFYING_KEY};
 
 declare_id!("HV9pDozXQxZKE4CeaA5joAp4Mv9wyayEFh2gJVR9hJ9a");
 
@@ -31,15 +40,29 @@ security_txt! {
 
 // Constants
 const MERKLE_TREE_HEIGHT: u8 = 26;
-
+const MAX_WITHDRAW_ONLY_SLOTS: u64 = 1_512_000; // ~7 days at 400ms slots
+const MAX_RELAYER_ENDPOINT_LEN: usize = 128;
+const RELAYER_UNBONDING_SLOTS: u64 = 432_000; // ~2 days at 400ms slots
+const MAX_WITHDRAWAL_PAYOUTS: usize = 20;
+
+// When ADMIN_PUBKEY is None, `initialize` must be signed by the program's upgrade authority,
+// so it has to run before the program is made immutable with `--final`.
+// Localnet and devnet builds can pin the initializer instead by setting
+// TXNSFR_ADMIN_PUBKEY at build time.
 #[cfg(any(feature = "localnet", feature = "localnet-mint-checked", test))]
-pub const ADMIN_PUBKEY: Option<Pubkey> = None;
+pub const ADMIN_PUBKEY: Option<Pubkey> = match option_env!("TXNSFR_ADMIN_PUBKEY") {
+    Some(key) => Some(Pubkey::from_str_const(key)),
+    None => None,
+};
 
 #[cfg(all(
     feature = "devnet",
     not(any(feature = "localnet", feature = "localnet-mint-checked", test))
 ))]
-pub const ADMIN_PUBKEY: Option<Pubkey> = None; // No admin required for devnet
+pub const ADMIN_PUBKEY: Option<Pubkey> = match option_env!("TXNSFR_ADMIN_PUBKEY") {
+    Some(key) => Some(Pubkey::from_str_const(key)),
+    None => None,
+};
 
 #[cfg(not(any(
     feature = "localnet",
@@ -47,21 +70,20 @@ pub const ADMIN_PUBKEY: Option<Pubkey> = None; // No admin required for devnet
     feature = "devnet",
     test
 )))]
-pub const ADMIN_PUBKEY: Option<Pubkey> = None; // No admin required for mainnet
+pub const ADMIN_PUBKEY: Option<Pubkey> = None; // Mainnet is initialized by the upgrade authority
 
 #[program]
 pub mod txnsfr {
-    use crate::utils::{verify_proof, VERIFYING_KEY};
-
     use super::*;
 
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        if let Some(admin_key) = ADMIN_PUBKEY {
-            require!(
-                ctx.accounts.authority.key().eq(&admin_key),
-                ErrorCode::Unauthorized
-            );
-        }
+        // Without this check anyone could front-run a fresh deployment and
+        // become the authority of the tree, vault and config PDAs.
+        utils::check_initializer(
+            &ctx.accounts.authority.key(),
+            ADMIN_PUBKEY,
+            ctx.accounts.program_data.upgrade_authority_address,
+        )?;
 
         let tree_account = &mut ctx.accounts.tree_account.load_init()?;
         tree_account.authority = ctx.accounts.authority.key();
@@ -85,6 +107,28 @@ pub mod txnsfr {
         global_config.withdrawal_fee_rate = 25; // 0.25% (25 basis points)
         global_config.fee_error_margin = 500; // 5% (500 basis points)
         global_config.bump = ctx.bumps.global_config;
+        global_config.max_outflow_rate = 2500; // 25% of the pool per window
+        global_config.outflow_window_slots = 9_000; // ~1 hour at 400ms slots
+        global_config.outflow_window = OutflowWindow::default();
+        global_config.guardian = Pubkey::default();
+        global_config.withdraw_only_until = 0;
+        global_config.pending_authority = Pubkey::default();
+        global_config.pending_authority_after = 0;
+        global_config.require_registered_relayer = false;
+        global_config.min_relayer_stake = 10_000_000_000; // 10 SOL
+        global_config.max_relayer_transfer_fee = 10_000_000; // 0.01 SOL
+        global_config.note_archive_retention_slots = 0; // archive pages are kept forever
+        global_config.encrypted_output_lengths = [state::DEFAULT_ENCRYPTED_OUTPUT_LEN, 0, 0, 0]; // one fixed length
+        global_config.screening_authority = ctx.accounts.authority.key();
+        global_config.deposit_screening_enabled = false;
+        global_config.nullifier_tree_enabled = false;
+        global_config.file_registration_fee = 10_000_000; // 0.01 SOL
+
+        // Initialize protocol fee vault
+        let fee_vault = &mut ctx.accounts.fee_vault;
+        fee_vault.total_accrued = 0;
+        fee_vault.total_claimed = 0;
+        fee_vault.bump = ctx.bumps.fee_vault;
 
         msg!("Sparse Merkle Tree initialized successfully with height: {}, root history size: {}, deposit limit: {} lamports, 
             deposit fee rate: {}, withdrawal fee rate: {}, fee error margin: {}",
@@ -93,297 +137,3159 @@ pub mod txnsfr {
     }
 
     /**
-     * Users deposit or withdraw SOL from the program.
+     * Updates the deposit and withdrawal fee rates and the accepted fee error margin.
      *
-     * Reentrant attacks are not possible, because nullifier creation is checked by anchor first.
+     * Like every config-changing instruction, this is gated on `global_config.authority`,
+     * which can be handed to an SPL Governance PDA through `propose_authority`.
      */
-    pub fn transact(
-        ctx: Context<Transact>,
-        proof: Proof,
-        ext_data_minified: ExtDataMinified,
-        encrypted_output1: Vec<u8>,
-        encrypted_output2: Vec<u8>,
+    pub fn update_fee_config(
+        ctx: Context<UpdateGlobalConfig>,
+        deposit_fee_rate: u16,
+        withdrawal_fee_rate: u16,
+        fee_error_margin: u16,
     ) -> Result<()> {
-        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
-        let global_config = &ctx.accounts.global_config;
-
-        // Reconstruct full ExtData from minified version and context accounts
-        let ext_data = ExtData::from_minified(&ctx, ext_data_minified);
-
-        // check if proof.root is in the tree_account's proof history
         require!(
-            MerkleTree::is_known_root(&tree_account, proof.root),
-            ErrorCode::UnknownRoot
+            deposit_fee_rate <= 10000 && withdrawal_fee_rate <= 10000 && fee_error_margin <= 10000,
+            ErrorCode::InvalidFeeRate
         );
 
-        // check if the ext_data hashes to the same ext_data in the proof
-        let calculated_ext_data_hash = utils::calculate_complete_ext_data_hash(
-            ext_data.recipient,
-            ext_data.ext_amount,
-            &encrypted_output1,
-            &encrypted_output2,
-            ext_data.fee,
-            ext_data.fee_recipient,
-            ext_data.mint_address,
-        )?;
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.deposit_fee_rate = deposit_fee_rate;
+        global_config.withdrawal_fee_rate = withdrawal_fee_rate;
+        global_config.fee_error_margin = fee_error_margin;
+
+        emit!(FeeConfigUpdated {
+            deposit_fee_rate,
+            withdrawal_fee_rate,
+            fee_error_margin,
+        });
+        Ok(())
+    }
 
-        require!(
-            Fr::from_le_bytes_mod_order(&calculated_ext_data_hash)
-                == Fr::from_be_bytes_mod_order(&proof.ext_data_hash),
-            ErrorCode::ExtDataHashMismatch
-        );
+    /**
+     * Updates the maximum amount accepted by a single deposit.
+     */
+    pub fn update_deposit_limit(ctx: Context<UpdateDepositLimit>, max_deposit_amount: u64) -> Result<()> {
+        ctx.accounts.tree_account.load_mut()?.max_deposit_amount = max_deposit_amount;
+
+        emit!(DepositLimitUpdated { max_deposit_amount });
+        Ok(())
+    }
 
+    /**
+     * Updates the pool-wide outflow circuit breaker.
+     *
+     * A new rate applies right away. A new window length starts each pool's window
+     * afresh on its next outflow.
+     */
+    pub fn update_outflow_limit(
+        ctx: Context<UpdateGlobalConfig>,
+        max_outflow_rate: u16,
+        outflow_window_slots: u64,
+    ) -> Result<()> {
         require!(
-            utils::check_public_amount(ext_data.ext_amount, ext_data.fee, proof.public_amount),
-            ErrorCode::InvalidPublicAmountData
+            max_outflow_rate <= 10000 && outflow_window_slots > 0,
+            ErrorCode::InvalidOutflowLimit
         );
 
-        let ext_amount = ext_data.ext_amount;
-        let fee = ext_data.fee;
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.max_outflow_rate = max_outflow_rate;
+        global_config.outflow_window_slots = outflow_window_slots;
 
-        // Validate fee calculation using utility function
-        utils::validate_fee(
-            ext_amount,
-            fee,
-            global_config.deposit_fee_rate,
-            global_config.withdrawal_fee_rate,
-            global_config.fee_error_margin,
-        )?;
+        emit!(OutflowLimitUpdated {
+            max_outflow_rate,
+            outflow_window_slots,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the guardian key that may toggle withdraw-only mode alongside the authority.
+     */
+    pub fn set_guardian(ctx: Context<UpdateGlobalConfig>, guardian: Pubkey) -> Result<()> {
+        ctx.accounts.global_config.guardian = guardian;
+
+        emit!(GuardianUpdated { guardian });
+        Ok(())
+    }
 
-        // verify the proof
+    /**
+     * Puts the pool into withdraw-only mode for `duration_slots` slots.
+     *
+     * While active, any transact with a non-negative ext_amount is rejected so no new value
+     * enters the pool, but users can always withdraw. The mode expires on its own so a lost
+     * key cannot freeze deposits forever.
+     */
+    pub fn enable_withdraw_only(ctx: Context<EmergencyAction>, duration_slots: u64) -> Result<()> {
         require!(
-            verify_proof(proof.clone(), VERIFYING_KEY),
-            ErrorCode::InvalidProof
+            duration_slots > 0 && duration_slots <= MAX_WITHDRAW_ONLY_SLOTS,
+            ErrorCode::InvalidWithdrawOnlyDuration
         );
 
-        let tree_token_account_info = ctx.accounts.tree_token_account.to_account_info();
-        let rent = Rent::get()?;
-        let rent_exempt_minimum = rent.minimum_balance(tree_token_account_info.data_len());
+        let until_slot = Clock::get()?
+            .slot
+            .checked_add(duration_slots)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        ctx.accounts.global_config.withdraw_only_until = until_slot;
 
-        if ext_amount > 0 {
-            // Check deposit limit for deposits
-            let deposit_amount = ext_amount as u64;
-            require!(
-                deposit_amount <= tree_account.max_deposit_amount,
-                ErrorCode::DepositLimitExceeded
-            );
+        emit!(WithdrawOnlyEnabled {
+            enabled_by: ctx.accounts.signer.key(),
+            until_slot,
+        });
+        Ok(())
+    }
 
-            // If it's a deposit, transfer the SOL to the tree token account.
-            anchor_lang::system_program::transfer(
-                CpiContext::new(
-                    ctx.accounts.system_program.to_account_info(),
-                    anchor_lang::system_program::Transfer {
-                        from: ctx.accounts.signer.to_account_info(),
-                        to: ctx.accounts.tree_token_account.to_account_info(),
-                    },
-                ),
-                ext_amount as u64,
-            )?;
-        } else if ext_amount < 0 {
-            // PDA can't directly sign transactions, so we need to transfer SOL via try_borrow_mut_lamports
-            // No limit on withdrawals
-            let recipient_account_info = ctx.accounts.recipient.to_account_info();
-
-            let ext_amount_abs: u64 = ext_amount
-                .checked_neg()
-                .ok_or(ErrorCode::ArithmeticOverflow)?
-                .try_into()
-                .map_err(|_| ErrorCode::InvalidExtAmount)?;
-
-            let total_required = ext_amount_abs
-                .checked_add(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?
-                .checked_add(rent_exempt_minimum)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
+    /**
+     * Lifts withdraw-only mode before it expires.
+     */
+    pub fn disable_withdraw_only(ctx: Context<EmergencyAction>) -> Result<()> {
+        ctx.accounts.global_config.withdraw_only_until = 0;
 
-            require!(
-                tree_token_account_info.lamports() >= total_required,
-                ErrorCode::InsufficientFundsForWithdrawal
-            );
+        emit!(WithdrawOnlyDisabled {
+            disabled_by: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
 
-            let tree_token_balance = tree_token_account_info.lamports();
-            let recipient_balance = recipient_account_info.lamports();
+    /**
+     * First step of an authority transfer: records `new_authority` as pending for the
+     * tree, vault and config accounts. It can accept once `delay_slots` have passed.
+     *
+     * Proposing `Pubkey::default()` cancels a pending transfer.
+     */
+    pub fn propose_authority(
+        ctx: Context<UpdateGlobalConfig>,
+        new_authority: Pubkey,
+        delay_slots: u64,
+    ) -> Result<()> {
+        let accept_after = Clock::get()?
+            .slot
+            .checked_add(delay_slots)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-            let new_tree_token_balance = tree_token_balance
-                .checked_sub(ext_amount_abs)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            let new_recipient_balance = recipient_balance
-                .checked_add(ext_amount_abs)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.pending_authority = new_authority;
+        global_config.pending_authority_after = accept_after;
 
-            **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
-            **recipient_account_info.try_borrow_mut_lamports()? = new_recipient_balance;
-        }
+        emit!(AuthorityProposed {
+            authority: global_config.authority,
+            pending_authority: new_authority,
+            accept_after,
+        });
+        Ok(())
+    }
+
+    /**
+     * Second step of an authority transfer: the pending authority signs to take over
+     * the tree, vault and config accounts at once.
+     */
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        require!(
+            Clock::get()?.slot >= global_config.pending_authority_after,
+            ErrorCode::AuthorityTimelockActive
+        );
 
-        if fee > 0 {
-            let fee_recipient_account_info = ctx.accounts.fee_recipient_account.to_account_info();
+        let previous_authority = global_config.authority;
+        let new_authority = ctx.accounts.new_authority.key();
 
-            if ext_amount >= 0 {
-                let total_required = fee
-                    .checked_add(rent_exempt_minimum)
-                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+        global_config.authority = new_authority;
+        global_config.pending_authority = Pubkey::default();
+        global_config.pending_authority_after = 0;
+        ctx.accounts.tree_account.load_mut()?.authority = new_authority;
+        ctx.accounts.tree_token_account.authority = new_authority;
 
-                require!(
-                    tree_token_account_info.lamports() >= total_required,
-                    ErrorCode::InsufficientFundsForFee
-                );
-            }
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority,
+        });
+        Ok(())
+    }
 
-            let tree_token_balance = tree_token_account_info.lamports();
-            let fee_recipient_balance = fee_recipient_account_info.lamports();
+    /**
+     * Pays out accrued protocol fees from the fee vault to `recipient`.
+     *
+     * The vault always keeps its rent-exempt minimum.
+     */
+    pub fn claim_fees(ctx: Context<ClaimFees>, amount: u64) -> Result<()> {
+        let fee_vault_info = ctx.accounts.fee_vault.to_account_info();
+        let recipient_info = ctx.accounts.recipient.to_account_info();
 
-            let new_tree_token_balance = tree_token_balance
-                .checked_sub(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
-            let new_fee_recipient_balance = fee_recipient_balance
-                .checked_add(fee)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(fee_vault_info.data_len());
+        let claimable = fee_vault_info.lamports().saturating_sub(rent_exempt_minimum);
+        require!(amount <= claimable, ErrorCode::InsufficientFeesToClaim);
 
-            **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
-            **fee_recipient_account_info.try_borrow_mut_lamports()? = new_fee_recipient_balance;
-        }
+        let new_fee_vault_balance = fee_vault_info
+            .lamports()
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let new_recipient_balance = recipient_info
+            .lamports()
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-        let next_index_to_insert = tree_account.next_index;
-        MerkleTree::append::<Poseidon>(proof.output_commitments[0], tree_account)?;
-        MerkleTree::append::<Poseidon>(proof.output_commitments[1], tree_account)?;
+        **fee_vault_info.try_borrow_mut_lamports()? = new_fee_vault_balance;
+        **recipient_info.try_borrow_mut_lamports()? = new_recipient_balance;
 
-        let second_index = next_index_to_insert
-            .checked_add(1)
+        let fee_vault = &mut ctx.accounts.fee_vault;
+        fee_vault.total_claimed = fee_vault
+            .total_claimed
+            .checked_add(amount)
             .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-        emit!(CommitmentData {
-            index: next_index_to_insert,
-            commitment: proof.output_commitments[0],
-            encrypted_output: encrypted_output1.to_vec(),
+        emit!(FeesClaimed {
+            recipient: ctx.accounts.recipient.key(),
+            amount,
+            total_claimed: fee_vault.total_claimed,
         });
+        Ok(())
+    }
 
-        emit!(CommitmentData {
-            index: second_index,
-            commitment: proof.output_commitments[1],
-            encrypted_output: encrypted_output2.to_vec(),
+    /**
+     * Configures the relayer registry: the minimum stake to register and relay, whether
+     * transact fees may only be paid to registered, active relayers, and the flat fee they
+     * may charge on transfers.
+     */
+    pub fn update_relayer_config(
+        ctx: Context<UpdateGlobalConfig>,
+        min_relayer_stake: u64,
+        require_registered_relayer: bool,
+        max_relayer_transfer_fee: u64,
+    ) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.min_relayer_stake = min_relayer_stake;
+        global_config.require_registered_relayer = require_registered_relayer;
+        global_config.max_relayer_transfer_fee = max_relayer_transfer_fee;
+
+        emit!(RelayerConfigUpdated {
+            min_relayer_stake,
+            require_registered_relayer,
+            max_relayer_transfer_fee,
         });
-
         Ok(())
     }
-}
 
-#[event]
-pub struct CommitmentData {
-    pub index: u64,
-    pub commitment: [u8; 32],
-    pub encrypted_output: Vec<u8>,
-}
+    /**
+     * Registers the signer as a relayer, staking `stake` lamports in its registry account.
+     */
+    pub fn register_relayer(
+        ctx: Context<RegisterRelayer>,
+        endpoint: String,
+        fee_rate: u16,
+        stake: u64,
+    ) -> Result<()> {
+        require!(
+            endpoint.len() <= MAX_RELAYER_ENDPOINT_LEN,
+            ErrorCode::InvalidRelayerEndpoint
+        );
+        require!(fee_rate <= 10000, ErrorCode::InvalidFeeRate);
+        require!(
+            stake >= ctx.accounts.global_config.min_relayer_stake,
+            ErrorCode::InsufficientRelayerStake
+        );
 
-// all public inputs needs to be in big endian format
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Proof {
-    pub proof_a: [u8; 64],
-    pub proof_b: [u8; 128],
-    pub proof_c: [u8; 64],
-    pub root: [u8; 32],
-    pub public_amount: [u8; 32],
-    pub ext_data_hash: [u8; 32],
-    pub input_nullifiers: [[u8; 32]; 2],
-    pub output_commitments: [[u8; 32]; 2],
-}
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.authority.to_account_info(),
+                    to: ctx.accounts.relayer.to_account_info(),
+                },
+            ),
+            stake,
+        )?;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ExtData {
-    pub recipient: Pubkey,
-    pub ext_amount: i64,
-    pub fee: u64,
-    pub fee_recipient: Pubkey,
-    pub mint_address: Pubkey,
-}
+        let relayer = &mut ctx.accounts.relayer;
+        relayer.authority = ctx.accounts.authority.key();
+        relayer.stake = stake;
+        relayer.fee_rate = fee_rate;
+        relayer.endpoint = endpoint;
+        relayer.jailed = false;
+        relayer.unbond_after = 0;
+        relayer.bump = ctx.bumps.relayer;
+
+        emit!(RelayerRegistered {
+            relayer: relayer.authority,
+            stake,
+            fee_rate,
+            endpoint: relayer.endpoint.clone(),
+        });
+        Ok(())
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ExtDataMinified {
-    pub ext_amount: i64,
-    pub fee: u64,
-}
+    /**
+     * Updates a relayer's advertised endpoint and fee rate.
+     */
+    pub fn update_relayer(ctx: Context<UpdateRelayer>, endpoint: String, fee_rate: u16) -> Result<()> {
+        require!(
+            endpoint.len() <= MAX_RELAYER_ENDPOINT_LEN,
+            ErrorCode::InvalidRelayerEndpoint
+        );
+        require!(fee_rate <= 10000, ErrorCode::InvalidFeeRate);
 
-impl ExtData {
-    fn from_minified(ctx: &Context<Transact>, minified: ExtDataMinified) -> Self {
-        Self {
-            recipient: ctx.accounts.recipient.key(),
-            ext_amount: minified.ext_amount,
-            fee: minified.fee,
-            fee_recipient: ctx.accounts.fee_recipient_account.key(),
-            mint_address: utils::SOL_ADDRESS,
-        }
+        let relayer = &mut ctx.accounts.relayer;
+        relayer.endpoint = endpoint;
+        relayer.fee_rate = fee_rate;
+
+        emit!(RelayerUpdated {
+            relayer: relayer.authority,
+            fee_rate,
+            endpoint: relayer.endpoint.clone(),
+        });
+        Ok(())
     }
-}
 
-#[derive(Accounts)]
-#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>)]
-pub struct Transact<'info> {
-    #[account(
-        mut,
-        seeds = [b"merkle_tree"],
-        bump = tree_account.load()?.bump
-    )]
-    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+    /**
+     * Stops a relayer from relaying and starts the unbonding period, during which
+     * it can still be slashed for past misbehavior.
+     */
+    pub fn begin_relayer_unbond(ctx: Context<UpdateRelayer>) -> Result<()> {
+        let unbond_after = Clock::get()?
+            .slot
+            .checked_add(RELAYER_UNBONDING_SLOTS)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-    /// Nullifier account to mark the first input as spent.
-    /// Using `init` without `init_if_needed` ensures that the transaction
-    /// will automatically fail with a system program error if this nullifier
-    /// has already been used (i.e., if the account already exists).
-    #[account(
-        init,
-        payer = signer,
-        space = 8 + std::mem::size_of::<NullifierAccount>(),
-        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
-        bump
-    )]
-    pub nullifier0: Account<'info, NullifierAccount>,
+        let relayer = &mut ctx.accounts.relayer;
+        relayer.unbond_after = unbond_after;
 
-    /// Nullifier account to mark the second input as spent.
-    /// Using `init` without `init_if_needed` ensures that the transaction
-    /// will automatically fail with a system program error if this nullifier
-    /// has already been used (i.e., if the account already exists).
-    #[account(
-        init,
-        payer = signer,
-        space = 8 + std::mem::size_of::<NullifierAccount>(),
-        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
-        bump
-    )]
-    pub nullifier1: Account<'info, NullifierAccount>,
+        emit!(RelayerUnbonding {
+            relayer: relayer.authority,
+            unbond_after,
+        });
+        Ok(())
+    }
 
-    #[account(
-        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
-        bump
-    )]
-    pub nullifier2: SystemAccount<'info>,
+    /**
+     * Closes an unbonded relayer account, returning its stake and rent to the relayer.
+     */
+    pub fn withdraw_relayer_stake(ctx: Context<WithdrawRelayerStake>) -> Result<()> {
+        let relayer = &ctx.accounts.relayer;
+        require!(!relayer.jailed, ErrorCode::RelayerJailed);
+        require!(
+            relayer.unbond_after != 0 && Clock::get()?.slot >= relayer.unbond_after,
+            ErrorCode::RelayerUnbondingActive
+        );
 
-    #[account(
-        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
-        bump
-    )]
-    pub nullifier3: SystemAccount<'info>,
+        emit!(RelayerWithdrawn {
+            relayer: relayer.authority,
+            stake: relayer.stake,
+        });
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        seeds = [b"tree_token"],
-        bump = tree_token_account.bump
-    )]
-    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    /**
+     * Slashes `amount` of a relayer's stake into the protocol fee vault and jails it.
+     */
+    pub fn slash_relayer(ctx: Context<SlashRelayer>, amount: u64) -> Result<()> {
+        require!(
+            amount <= ctx.accounts.relayer.stake,
+            ErrorCode::InvalidSlashAmount
+        );
 
-    #[account(
-        seeds = [b"global_config"],
-        bump = global_config.bump
-    )]
-    pub global_config: Account<'info, GlobalConfig>,
+        let relayer_info = ctx.accounts.relayer.to_account_info();
+        let fee_vault_info = ctx.accounts.fee_vault.to_account_info();
 
-    #[account(mut)]
-    /// CHECK: user should be able to send funds to any types of accounts
-    pub recipient: UncheckedAccount<'info>,
+        let new_relayer_balance = relayer_info
+            .lamports()
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let new_fee_vault_balance = fee_vault_info
+            .lamports()
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-    #[account(mut)]
+        **relayer_info.try_borrow_mut_lamports()? = new_relayer_balance;
+        **fee_vault_info.try_borrow_mut_lamports()? = new_fee_vault_balance;
+
+        let relayer = &mut ctx.accounts.relayer;
+        relayer.stake = relayer
+            .stake
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        relayer.jailed = true;
+
+        emit!(RelayerSlashed {
+            relayer: relayer.authority,
+            amount,
+            remaining_stake: relayer.stake,
+        });
+        Ok(())
+    }
+
+    /**
+     * Releases a relayer from jail once governance is satisfied.
+     */
+    pub fn unjail_relayer(ctx: Context<SlashRelayer>) -> Result<()> {
+        let relayer = &mut ctx.accounts.relayer;
+        relayer.jailed = false;
+
+        emit!(RelayerUnjailed {
+            relayer: relayer.authority,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the ciphertext lengths `transact` accepts for encrypted outputs.
+     * Unused entries are zero, and at least one length must be set.
+     * Lengths above `MAX_ARCHIVED_NOTE_LEN` are allowed, but their notes are left out of
+     * the note archive.
+     */
+    pub fn update_encrypted_output_lengths(
+        ctx: Context<UpdateGlobalConfig>,
+        encrypted_output_lengths: [u16; state::MAX_ENCRYPTED_OUTPUT_LENGTHS],
+    ) -> Result<()> {
+        require!(
+            encrypted_output_lengths.iter().any(|&len| len != 0),
+            ErrorCode::InvalidEncryptedOutputLength
+        );
+        require!(
+            encrypted_output_lengths
+                .iter()
+                .all(|&len| len as usize <= state::MAX_ENCRYPTED_OUTPUT_LEN),
+            ErrorCode::EncryptedOutputTooLarge
+        );
+
+        ctx.accounts.global_config.encrypted_output_lengths = encrypted_output_lengths;
+
+        emit!(EncryptedOutputLengthsUpdated {
+            encrypted_output_lengths,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets how long a full note archive page must be kept before it can be closed.
+     * Zero keeps pages forever.
+     */
+    pub fn update_note_archive_retention(
+        ctx: Context<UpdateGlobalConfig>,
+        note_archive_retention_slots: u64,
+    ) -> Result<()> {
+        ctx.accounts.global_config.note_archive_retention_slots = note_archive_retention_slots;
+
+        emit!(NoteArchiveRetentionUpdated {
+            note_archive_retention_slots,
+        });
+        Ok(())
+    }
+
+    /**
+     * Closes a full note archive page after the retention period, refunding its rent to
+     * the account that paid for it. Wallets must have restored from the page before then.
+     */
+    pub fn close_note_archive_page(ctx: Context<CloseNoteArchivePage>) -> Result<()> {
+        let retention_slots = ctx.accounts.global_config.note_archive_retention_slots;
+        let page = ctx.accounts.note_archive.load()?;
+
+        require!(
+            retention_slots != 0
+                && page.count == state::NOTES_PER_ARCHIVE_PAGE
+                && Clock::get()?.slot >= page.filled_at.saturating_add(retention_slots),
+            ErrorCode::NoteArchiveRetentionActive
+        );
+
+        emit!(NoteArchivePageClosed {
+            page_index: page.page_index,
+        });
+        Ok(())
+    }
+
+    /**
+     * Creates the association set: a Merkle tree of curator-approved deposit labels. An
+     * associated deposit is labelled with its first input nullifier, and every associated
+     * note it funds carries that label, so proofs show their inputs descend from an
+     * approved deposit.
+     */
+    pub fn initialize_association_set(
+        ctx: Context<InitializeAssociationSet>,
+        curator: Pubkey,
+    ) -> Result<()> {
+        let association_set = &mut ctx.accounts.association_set.load_init()?;
+        association_set.authority = curator;
+        association_set.next_index = 0;
+        association_set.root_index = 0;
+        association_set.bump = ctx.bumps.association_set;
+        association_set.max_deposit_amount = 0; // the association set never holds deposits
+        association_set.height = MERKLE_TREE_HEIGHT;
+        association_set.root_history_size = 100;
+
+        MerkleTree::initialize::<Poseidon>(association_set)?;
+
+        emit!(AssociationCuratorUpdated { curator });
+        Ok(())
+    }
+
+    /**
+     * Replaces the curator allowed to add labels to the association set.
+     */
+    pub fn set_association_curator(
+        ctx: Context<SetAssociationCurator>,
+        curator: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.association_set.load_mut()?.authority = curator;
+
+        emit!(AssociationCuratorUpdated { curator });
+        Ok(())
+    }
+
+    /**
+     * Appends the labels of approved deposits to the association set. Each append records
+     * a new root in the set's root history.
+     */
+    pub fn add_association_labels(
+        ctx: Context<AddAssociationLabels>,
+        labels: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let association_set = &mut ctx.accounts.association_set.load_mut()?;
+
+        for label in labels {
+            let index = association_set.next_index;
+            MerkleTree::append::<Poseidon>(label, association_set)?;
+
+            emit!(AssociationLabelAdded { index, label });
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Sets the deposit screening authority and turns denylist checks on deposits on or off.
+     */
+    pub fn update_screening_config(
+        ctx: Context<UpdateGlobalConfig>,
+        screening_authority: Pubkey,
+        deposit_screening_enabled: bool,
+    ) -> Result<()> {
+        let global_config = &mut ctx.accounts.global_config;
+        global_config.screening_authority = screening_authority;
+        global_config.deposit_screening_enabled = deposit_screening_enabled;
+
+        emit!(ScreeningConfigUpdated {
+            screening_authority,
+            deposit_screening_enabled,
+        });
+        Ok(())
+    }
+
+    /**
+     * Creates the deposit denylist, empty. Until it exists, screening denies nobody.
+     */
+    pub fn initialize_denylist(ctx: Context<InitializeDenylist>) -> Result<()> {
+        let denylist = &mut ctx.accounts.denylist;
+        denylist.root = Denylist::empty_root();
+        denylist.count = 0;
+        denylist.bump = ctx.bumps.denylist;
+
+        emit!(DenylistUpdated {
+            root: denylist.root,
+            count: 0,
+        });
+        Ok(())
+    }
+
+    /**
+     * Replaces the denylist with the list of `count` addresses under `root`, which the
+     * screening authority publishes so depositors can prove they are not on it. Every
+     * clearance against the previous root stops being accepted.
+     */
+    pub fn update_denylist(ctx: Context<UpdateDenylist>, root: [u8; 32], count: u64) -> Result<()> {
+        let denylist = &mut ctx.accounts.denylist;
+        denylist.root = root;
+        denylist.count = count;
+
+        emit!(DenylistUpdated { root, count });
+        Ok(())
+    }
+
+    /**
+     * Proves the signer is not on the current denylist and records it in the signer's
+     * clearance, which deposits check while screening is enabled. The proof is a Merkle
+     * path to the gap of the sorted list the signer's address falls into; it is made once
+     * per denylist update rather than sent with every deposit.
+     */
+    pub fn clear_depositor(ctx: Context<ClearDepositor>, proof: DenylistProof) -> Result<()> {
+        let denylist_root = ctx.accounts.denylist.root;
+        let depositor = ctx.accounts.signer.key();
+        Denylist::check_not_denied(&denylist_root, &depositor, &proof)?;
+
+        let clearance = &mut ctx.accounts.deposit_clearance;
+        clearance.depositor = depositor;
+        clearance.denylist_root = denylist_root;
+        clearance.bump = ctx.bumps.deposit_clearance;
+
+        emit!(DepositorCleared {
+            depositor,
+            denylist_root,
+        });
+        Ok(())
+    }
+
+    /**
+     * Registers the Groth16 verifying key of a circuit that is verified against an
+     * on-chain key rather than one compiled into the program. Each key can be set once.
+     */
+    pub fn set_verifying_key(
+        ctx: Context<SetVerifyingKey>,
+        circuit: u8,
+        vk_alpha_g1: [u8; 64],
+        vk_beta_g2: [u8; 128],
+        vk_gamma_g2: [u8; 128],
+        vk_delta_g2: [u8; 128],
+        vk_ic: Vec<[u8; 64]>,
+    ) -> Result<()> {
+        require!(
+            vk_ic.len() >= 2 && vk_ic.len() <= state::MAX_VERIFYING_KEY_IC_LEN,
+            ErrorCode::InvalidVerifyingKey
+        );
+
+        let verifying_key = &mut ctx.accounts.verifying_key;
+        verifying_key.circuit = circuit;
+        verifying_key.vk_alpha_g1 = vk_alpha_g1;
+        verifying_key.vk_beta_g2 = vk_beta_g2;
+        verifying_key.vk_gamma_g2 = vk_gamma_g2;
+        verifying_key.vk_delta_g2 = vk_delta_g2;
+        verifying_key.vk_ic = vk_ic;
+        verifying_key.bump = ctx.bumps.verifying_key;
+
+        emit!(VerifyingKeySet {
+            circuit,
+            nr_pubinputs: verifying_key.vk_ic.len() as u8 - 1,
+        });
+        Ok(())
+    }
+
+    /**
+     * Creates the file registry: a Merkle tree of recipient commitments for files
+     * stored encrypted on Irys.
+     */
+    pub fn initialize_file_registry(ctx: Context<InitializeFileRegistry>) -> Result<()> {
+        let file_registry = &mut ctx.accounts.file_registry.load_init()?;
+        file_registry.authority = ctx.accounts.authority.key();
+        file_registry.next_index = 0;
+        file_registry.root_index = 0;
+        file_registry.bump = ctx.bumps.file_registry;
+        file_registry.max_deposit_amount = 0; // the file registry never holds deposits
+        file_registry.height = MERKLE_TREE_HEIGHT;
+        file_registry.root_history_size = 100;
+
+        MerkleTree::initialize::<Poseidon>(file_registry)?;
+        Ok(())
+    }
+
+    /**
+     * Registers an encrypted file uploaded to Irys for a private recipient.
+     *
+     * recipient_commitment = Poseidon(fileHash, recipientPubkey, blinding), where fileHash is
+     * content_hash reduced into the BN254 field. For chunked files irys_tx_id is the encrypted
+     * manifest and content_hash is the manifest root. Only the recipient can later prove knowledge
+     * of the key behind the commitment in `claim_file`, without revealing which file it claims.
+     *
+     * Every registration pushes a root out of the registry's 100-root history, invalidating
+     * claim proofs made against it, so the signer pays `file_registration_fee` into the
+     * fee vault to make flooding the registry costly.
+     */
+    pub fn register_file(
+        ctx: Context<RegisterFile>,
+        irys_tx_id: [u8; 32],
+        content_hash: [u8; 32],
+        recipient_commitment: [u8; 32],
+    ) -> Result<()> {
+        let registration_fee = ctx.accounts.global_config.file_registration_fee;
+        if registration_fee > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.signer.to_account_info(),
+                        to: ctx.accounts.fee_vault.to_account_info(),
+                    },
+                ),
+                registration_fee,
+            )?;
+
+            let fee_vault = &mut ctx.accounts.fee_vault;
+            fee_vault.total_accrued = fee_vault
+                .total_accrued
+                .checked_add(registration_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+
+        let file_registry = &mut ctx.accounts.file_registry.load_mut()?;
+        let index = file_registry.next_index;
+        MerkleTree::append::<Poseidon>(recipient_commitment, file_registry)?;
+
+        emit!(FileRegistered {
+            index,
+            irys_tx_id,
+            content_hash,
+            recipient_commitment,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the lamports `register_file` pays into the fee vault.
+     */
+    pub fn update_file_registration_fee(
+        ctx: Context<UpdateGlobalConfig>,
+        file_registration_fee: u64,
+    ) -> Result<()> {
+        ctx.accounts.global_config.file_registration_fee = file_registration_fee;
+
+        emit!(FileRegistrationFeeUpdated {
+            file_registration_fee,
+        });
+        Ok(())
+    }
+
+    /**
+     * Claims a registered file with a proof of knowing the recipient key behind one of the
+     * registry's commitments. The nullifier PDA makes each file claimable only once, and
+     * the proof is bound to the signer, so only the wallet it was made for can submit it.
+     */
+    pub fn claim_file(ctx: Context<ClaimFile>, proof: FileClaimProof) -> Result<()> {
+        let file_registry = ctx.accounts.file_registry.load()?;
+        require!(
+            MerkleTree::is_known_root(&file_registry, proof.root),
+            ErrorCode::UnknownRoot
+        );
+
+        let verifying_key = utils::groth16_verifying_key(&ctx.accounts.verifying_key);
+        require!(
+            utils::verify_groth16(
+                &proof.proof_a,
+                &proof.proof_b,
+                &proof.proof_c,
+                &[
+                    proof.root,
+                    proof.nullifier,
+                    utils::reduce_to_field(&ctx.accounts.signer.key().to_bytes()),
+                ],
+                &verifying_key,
+            ),
+            ErrorCode::InvalidProof
+        );
+
+        emit!(FileClaimed {
+            nullifier: proof.nullifier,
+            claimer: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
+
+    /**
+     * Lists a file on Irys for sale. Buyers pay `price` into a shielded note for
+     * `seller_pubkey`, so the listing names no seller wallet; the signer only pays rent
+     * and should not be linked to the seller. The listing's address is derived from the
+     * signer and `irys_tx_id`, so buyers look it up by the lister key the seller shares
+     * along with the file.
+     */
+    pub fn list_file(
+        ctx: Context<ListFile>,
+        irys_tx_id: [u8; 32],
+        content_hash: [u8; 32],
+        seller_pubkey: [u8; 32],
+        price: u64,
+    ) -> Result<()> {
+        require!(
+            price > 0 && utils::reduce_to_field(&seller_pubkey) == seller_pubkey,
+            ErrorCode::InvalidFileListing
+        );
+
+        let file_listing = &mut ctx.accounts.file_listing;
+        file_listing.lister = ctx.accounts.signer.key();
+        file_listing.irys_tx_id = irys_tx_id;
+        file_listing.content_hash = content_hash;
+        file_listing.seller_pubkey = seller_pubkey;
+        file_listing.price = price;
+        file_listing.bump = ctx.bumps.file_listing;
+
+        emit!(FileListed {
+            lister: file_listing.lister,
+            irys_tx_id,
+            content_hash,
+            seller_pubkey,
+            price,
+        });
+        Ok(())
+    }
+
+    /**
+     * Buys access to a listed file from the buyer's shielded balance in one instruction.
+     *
+     * The proof is a `transact` JoinSplit with no deposit or withdrawal whose first output
+     * pays the listed price to the seller's shielded key. It also commits to a file note for
+     * the buyer, claim_commitment = Poseidon(fileHash, claimPubkey, blinding), which is
+     * appended to the file registry so the purchase can later be proven with `claim_file`.
+     * The signer only pays for the nullifier accounts and can be a relayer, so neither
+     * party's wallet appears. Nothing is paid out, but as in `transact` the ext data hash
+     * commits to the `recipient` account, which can be any writable account.
+     *
+     * The seller finds the sale through the payment note and delivers the file key to the
+     * buyer as a file note; a reply key for that can travel inside the payment note.
+     */
+    pub fn purchase_file<'info>(
+        ctx: Context<'_, '_, '_, 'info, PurchaseFile<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+        claim_commitment: [u8; 32],
+    ) -> Result<TransactResult> {
+        // Only the fees leave the pool: public_amount = -fee
+        require!(ext_data_minified.ext_amount == 0, ErrorCode::InvalidExtAmount);
+
+        let irys_tx_id = ctx.accounts.file_listing.irys_tx_id;
+        let accounts = &mut *ctx.accounts;
+        let circuit = TransactCircuit::FilePurchase {
+            verifying_key: &accounts.verifying_key,
+            listing_inputs: [
+                accounts.file_listing.seller_pubkey,
+                utils::u64_to_field(accounts.file_listing.price),
+                utils::reduce_to_field(&accounts.file_listing.content_hash),
+                claim_commitment,
+            ],
+        };
+        let (commitments, result) = execute_transact(
+            accounts.pool.transact_accounts(
+                &accounts.note_archive,
+                ctx.bumps.note_archive,
+                &accounts.parties,
+                &accounts.system_program,
+                &[],
+            ),
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            circuit,
+            Vec::new(),
+        )?;
+
+        // Release the buyer's claim in the same instruction as the payment
+        let claim_index = {
+            let file_registry = &mut ctx.accounts.file_registry.load_mut()?;
+            let claim_index = file_registry.next_index;
+            MerkleTree::append::<Poseidon>(claim_commitment, file_registry)?;
+            claim_index
+        };
+
+        for commitment_data in commitments {
+            emit_cpi!(commitment_data);
+        }
+
+        emit_cpi!(FilePurchased {
+            irys_tx_id,
+            claim_index,
+            claim_commitment,
+        });
+
+        Ok(result)
+    }
+
+    /**
+     * Users deposit or withdraw SOL from the program.
+     *
+     * Reentrant attacks are not possible, because nullifier creation is checked by anchor first.
+     */
+    pub fn transact<'info>(
+        ctx: Context<'_, '_, '_, 'info, Transact<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+    ) -> Result<TransactResult> {
+        process_transact(
+            ctx,
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            TransactCircuit::Transaction,
+            Vec::new(),
+        )
+    }
+
+    /**
+     * Same as `transact`, but the proof is for the timelock circuit, which can also spend
+     * time-locked notes: the recipient before the note's expiry slot, the sender from then on.
+     *
+     * The proof shows its claims hold for every slot in `timelock_window`, and the window must
+     * contain the current slot, so a proof cannot be replayed on the wrong side of an expiry.
+     */
+    pub fn transact_timelocked<'info>(
+        ctx: Context<'_, '_, '_, 'info, Transact<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+        timelock_window: TimelockWindow,
+    ) -> Result<TransactResult> {
+        process_transact(
+            ctx,
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            TransactCircuit::Timelock(timelock_window),
+            Vec::new(),
+        )
+    }
+
+    /**
+     * Same as `transact`, but the proof is for the association circuit, whose notes carry
+     * the label of the deposit they descend from. A deposit with no inputs starts a new
+     * label, its first input nullifier; any other proof shows its inputs' label is in the
+     * curated association set under `association_root`, and adds no funds to the lineage.
+     * The root must be one of the set's recent roots, so a withdrawal can show its funds
+     * descend from an approved deposit and are not linked to flagged ones.
+     */
+    pub fn transact_associated<'info>(
+        ctx: Context<'_, '_, '_, 'info, Transact<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+        association_root: [u8; 32],
+    ) -> Result<TransactResult> {
+        process_transact(
+            ctx,
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            TransactCircuit::Association(association_root),
+            Vec::new(),
+        )
+    }
+
+    /**
+     * Same as `transact`, but the withdrawal is split between several recipients, e.g. for
+     * a payroll run. Recipients are passed as writable remaining accounts, in the same order
+     * as `payout_amounts`, and the ext data hash commits to every (recipient, amount) pair
+     * in place of the single `recipient`. The amounts must add up to |ext_amount|.
+     */
+    pub fn transact_multi_withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, Transact<'info>>,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+        payout_amounts: Vec<u64>,
+    ) -> Result<TransactResult> {
+        require!(!payout_amounts.is_empty(), ErrorCode::InvalidWithdrawalPayouts);
+        process_transact(
+            ctx,
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            TransactCircuit::Transaction,
+            payout_amounts,
+        )
+    }
+
+    /**
+     * Creates a zeroed proof buffer of `len` bytes for staging transact arguments that do
+     * not fit in one transaction. A signer can hold several buffers, told apart by `buffer_id`.
+     */
+    pub fn init_proof_buffer(ctx: Context<InitProofBuffer>, _buffer_id: u64, len: u16) -> Result<()> {
+        require!(
+            len as usize <= state::MAX_PROOF_BUFFER_LEN,
+            ErrorCode::InvalidProofBufferWrite
+        );
+
+        let proof_buffer = &mut ctx.accounts.proof_buffer;
+        proof_buffer.authority = ctx.accounts.signer.key();
+        proof_buffer.data = vec![0u8; len as usize];
+        proof_buffer.bump = ctx.bumps.proof_buffer;
+        Ok(())
+    }
+
+    /**
+     * Writes `bytes` into the buffer at `offset`. Writes can arrive in any order.
+     */
+    pub fn write_proof_buffer(
+        ctx: Context<UpdateProofBuffer>,
+        _buffer_id: u64,
+        offset: u16,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let data = &mut ctx.accounts.proof_buffer.data;
+        let end = (offset as usize)
+            .checked_add(bytes.len())
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(end <= data.len(), ErrorCode::InvalidProofBufferWrite);
+
+        data[offset as usize..end].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /**
+     * Closes an unused proof buffer and refunds its rent.
+     */
+    pub fn close_proof_buffer(ctx: Context<UpdateProofBuffer>, _buffer_id: u64) -> Result<()> {
+        let signer = ctx.accounts.signer.to_account_info();
+        ctx.accounts.proof_buffer.close(signer)
+    }
+
+    /**
+     * Runs the transaction staged in a proof buffer, then closes the buffer and refunds its
+     * rent to the signer. A staged `timelock_window` runs it as `transact_timelocked`, and
+     * staged `payout_amounts` as `transact_multi_withdraw`, with the payout recipients
+     * passed as remaining accounts.
+     */
+    pub fn execute_from_buffer<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteFromBuffer<'info>>,
+        _buffer_id: u64,
+    ) -> Result<TransactResult> {
+        let staged = BufferedTransact::try_from_slice(&ctx.accounts.proof_buffer.data)
+            .map_err(|_| ErrorCode::InvalidProofBuffer)?;
+
+        let (commitments, result) = execute_transact(
+            ctx.accounts
+                .transact_accounts(ctx.bumps.note_archive, ctx.remaining_accounts),
+            staged.proof,
+            staged.ext_data_minified,
+            staged.encrypted_output1,
+            staged.encrypted_output2,
+            staged
+                .timelock_window
+                .map_or(TransactCircuit::Transaction, TransactCircuit::Timelock),
+            staged.payout_amounts,
+        )?;
+
+        for commitment_data in commitments {
+            emit_cpi!(commitment_data);
+        }
+
+        let signer = ctx.accounts.parties.signer.to_account_info();
+        ctx.accounts.proof_buffer.close(signer)?;
+        Ok(result)
+    }
+
+    /**
+     * Like `execute_from_buffer`, but records both input nullifiers in the nullifier
+     * tree instead of creating a PDA for each, so spends pay no rent for them. The buffer
+     * also holds an insertion proof per input, each against the tree as the one before
+     * it left it. This is the only way to spend main tree notes once the tree is enabled.
+     */
+    pub fn execute_from_buffer_with_nullifier_tree<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteFromBufferWithNullifierTree<'info>>,
+        _buffer_id: u64,
+    ) -> Result<TransactResult> {
+        let staged = BufferedNullifierTreeTransact::try_from_slice(&ctx.accounts.proof_buffer.data)
+            .map_err(|_| ErrorCode::InvalidProofBuffer)?;
+
+        {
+            let nullifier_tree = &mut ctx.accounts.nullifier_tree.load_mut()?;
+            for (nullifier, insert_proof) in staged
+                .transact
+                .proof
+                .input_nullifiers
+                .iter()
+                .zip(&staged.nullifier_proofs)
+            {
+                NullifierTree::insert::<Poseidon>(nullifier_tree, *nullifier, insert_proof)?;
+            }
+        }
+
+        let transact = staged.transact;
+        let (commitments, result) = execute_transact(
+            ctx.accounts
+                .transact_accounts(ctx.bumps.note_archive, ctx.remaining_accounts),
+            transact.proof,
+            transact.ext_data_minified,
+            transact.encrypted_output1,
+            transact.encrypted_output2,
+            transact
+                .timelock_window
+                .map_or(TransactCircuit::Transaction, TransactCircuit::Timelock),
+            transact.payout_amounts,
+        )?;
+
+        for commitment_data in commitments {
+            emit_cpi!(commitment_data);
+        }
+
+        let signer = ctx.accounts.parties.signer.to_account_info();
+        ctx.accounts.proof_buffer.close(signer)?;
+        Ok(result)
+    }
+
+    /**
+     * Creates tree shard `shard_id`: an independent tree and vault that transactions can
+     * use in parallel with the main tree. Notes live in the tree they were created in and
+     * are spent against its roots; nullifiers are shared, so a note can't be spent twice.
+     */
+    pub fn initialize_tree_shard(ctx: Context<InitializeTreeShard>, shard_id: u8) -> Result<()> {
+        require!(
+            (1..=state::MAX_TREE_SHARDS).contains(&shard_id),
+            ErrorCode::InvalidTreeShard
+        );
+
+        let shard_tree = &mut ctx.accounts.shard_tree.load_init()?;
+        shard_tree.authority = ctx.accounts.authority.key();
+        shard_tree.next_index = 0;
+        shard_tree.root_index = 0;
+        shard_tree.bump = ctx.bumps.shard_tree;
+        shard_tree.max_deposit_amount = ctx.accounts.tree_account.load()?.max_deposit_amount;
+        shard_tree.height = MERKLE_TREE_HEIGHT;
+        shard_tree.root_history_size = 100;
+
+        MerkleTree::initialize::<Poseidon>(shard_tree)?;
+
+        let shard_vault = &mut ctx.accounts.shard_vault;
+        shard_vault.shard_id = shard_id;
+        shard_vault.outflow_window = OutflowWindow::default();
+        shard_vault.pending_protocol_fees = 0;
+        shard_vault.bump = ctx.bumps.shard_vault;
+
+        emit!(TreeShardInitialized {
+            shard_id,
+            shard_tree: ctx.accounts.shard_tree.key(),
+            shard_vault: shard_vault.key(),
+        });
+        Ok(())
+    }
+
+    /**
+     * Updates the maximum amount accepted by a single deposit into a tree shard.
+     */
+    pub fn update_shard_deposit_limit(
+        ctx: Context<UpdateShardDepositLimit>,
+        shard_id: u8,
+        max_deposit_amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.shard_tree.load_mut()?.max_deposit_amount = max_deposit_amount;
+
+        emit!(ShardDepositLimitUpdated {
+            shard_id,
+            max_deposit_amount,
+        });
+        Ok(())
+    }
+
+    /**
+     * Same as `transact`, against tree shard `shard_id`. The proof's root must be a root of
+     * that shard, and the outputs are appended to it. Clients pick the shard, e.g. at random
+     * for deposits, and wallets track notes per shard.
+     *
+     * Shard spends keep recording their nullifiers in PDAs once the nullifier tree is
+     * enabled: the tree only takes spends of main tree notes, whose insertion proofs are
+     * staged through the proof buffer.
+     */
+    pub fn transact_sharded<'info>(
+        ctx: Context<'_, '_, '_, 'info, TransactSharded<'info>>,
+        shard_id: u8,
+        proof: Proof,
+        ext_data_minified: ExtDataMinified,
+        encrypted_output1: Vec<u8>,
+        encrypted_output2: Vec<u8>,
+    ) -> Result<TransactResult> {
+        let (commitments, result) = execute_transact(
+            ctx.accounts
+                .transact_accounts(shard_id, ctx.bumps.note_archive),
+            proof,
+            ext_data_minified,
+            encrypted_output1,
+            encrypted_output2,
+            TransactCircuit::Transaction,
+            Vec::new(),
+        )?;
+
+        for commitment_data in commitments {
+            emit_cpi!(ShardCommitmentData {
+                shard_id,
+                index: commitment_data.index,
+                commitment: commitment_data.commitment,
+                encrypted_output: commitment_data.encrypted_output,
+            });
+        }
+        Ok(result)
+    }
+
+    /**
+     * Moves the protocol fees collected by a tree shard into the fee vault. Anyone may
+     * call this; it only ever moves lamports that belong to the fee vault.
+     */
+    pub fn sweep_shard_fees(ctx: Context<SweepShardFees>, shard_id: u8) -> Result<()> {
+        let amount = ctx.accounts.shard_vault.pending_protocol_fees;
+        if amount > 0 {
+            utils::transfer_lamports(
+                &ctx.accounts.shard_vault.to_account_info(),
+                &ctx.accounts.fee_vault.to_account_info(),
+                amount,
+            )?;
+        }
+
+        ctx.accounts.shard_vault.pending_protocol_fees = 0;
+        let fee_vault = &mut ctx.accounts.fee_vault;
+        fee_vault.total_accrued = fee_vault
+            .total_accrued
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ShardFeesSwept {
+            shard_id,
+            amount,
+            total_accrued: fee_vault.total_accrued,
+        });
+        Ok(())
+    }
+
+    /**
+     * Switches a tree (0 for the main tree, or a shard) to queued insertion: from now on
+     * transactions only push their outputs into the commitment queue, and the outputs are
+     * hashed into the tree in batches by `insert_commitment_batch`. This can't be undone.
+     */
+    pub fn initialize_commitment_queue(
+        ctx: Context<InitializeCommitmentQueue>,
+        shard_id: u8,
+    ) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        tree_account.queue_enabled = 1;
+
+        let commitment_queue = &mut ctx.accounts.commitment_queue.load_init()?;
+        commitment_queue.next_insert_index = tree_account.next_index;
+        commitment_queue.head = 0;
+        commitment_queue.count = 0;
+        commitment_queue.shard_id = shard_id;
+        commitment_queue.bump = ctx.bumps.commitment_queue;
+
+        emit!(CommitmentQueueInitialized {
+            shard_id,
+            next_insert_index: tree_account.next_index,
+        });
+        Ok(())
+    }
+
+    /**
+     * Moves main tree spends from per-nullifier PDAs to the nullifier tree. From now on
+     * they only go through `execute_from_buffer_with_nullifier_tree`, which also checks
+     * that no PDA exists for its nullifiers. This can't be undone.
+     */
+    pub fn initialize_nullifier_tree(ctx: Context<InitializeNullifierTree>) -> Result<()> {
+        let nullifier_tree = &mut ctx.accounts.nullifier_tree.load_init()?;
+        NullifierTree::initialize::<Poseidon>(nullifier_tree)?;
+        nullifier_tree.bump = ctx.bumps.nullifier_tree;
+
+        ctx.accounts.global_config.nullifier_tree_enabled = true;
+
+        emit!(NullifierTreeInitialized {
+            root: nullifier_tree.root,
+        });
+        Ok(())
+    }
+
+    /**
+     * Inserts the first `count` queued commitments into their tree with a single proof
+     * that the tree moved from its current root to `proof.new_root`. Anyone may crank
+     * this; notes can only be spent once their batch is in.
+     */
+    pub fn insert_commitment_batch(
+        ctx: Context<InsertCommitmentBatch>,
+        shard_id: u8,
+        proof: CommitmentBatchProof,
+        count: u8,
+    ) -> Result<()> {
+        let tree_account = &mut ctx.accounts.tree_account.load_mut()?;
+        let commitment_queue = &mut ctx.accounts.commitment_queue.load_mut()?;
+
+        let count = count as usize;
+        require!(
+            count > 0
+                && count <= state::COMMITMENT_BATCH_SIZE
+                && count <= commitment_queue.count as usize,
+            ErrorCode::InvalidCommitmentBatch
+        );
+
+        // Public inputs: oldRoot, newRoot, startIndex, count, leaves (zero past count)
+        let start_index = commitment_queue.next_insert_index;
+        let leaves = CommitmentQueue::peek_batch(commitment_queue, count);
+        let mut public_inputs = [[0u8; 32]; 4 + state::COMMITMENT_BATCH_SIZE];
+        public_inputs[0] = tree_account.root;
+        public_inputs[1] = proof.new_root;
+        public_inputs[2] = utils::u64_to_field(start_index);
+        public_inputs[3] = utils::u64_to_field(count as u64);
+        public_inputs[4..].copy_from_slice(&leaves);
+
+        let verifying_key = utils::groth16_verifying_key(&ctx.accounts.verifying_key);
+        require!(
+            utils::verify_groth16(
+                &proof.proof_a,
+                &proof.proof_b,
+                &proof.proof_c,
+                &public_inputs,
+                &verifying_key,
+            ),
+            ErrorCode::InvalidProof
+        );
+
+        CommitmentQueue::pop_batch(commitment_queue, count)?;
+        MerkleTree::push_root(tree_account, proof.new_root)?;
+
+        emit!(CommitmentBatchInserted {
+            shard_id,
+            start_index,
+            count: count as u8,
+            root: proof.new_root,
+        });
+        Ok(())
+    }
+}
+
+/**
+ * Runs a transact instruction, emits its commitments and returns where they were
+ * inserted. `circuit` selects the circuit the proof is for, and non-empty
+ * `payout_amounts` split the withdrawal across the remaining accounts.
+ */
+fn process_transact<'info>(
+    ctx: Context<'_, '_, '_, 'info, Transact<'info>>,
+    proof: Proof,
+    ext_data_minified: ExtDataMinified,
+    encrypted_output1: Vec<u8>,
+    encrypted_output2: Vec<u8>,
+    circuit: TransactCircuit<'_, 'info>,
+    payout_amounts: Vec<u64>,
+) -> Result<TransactResult> {
+    let (commitments, result) = execute_transact(
+        ctx.accounts
+            .transact_accounts(ctx.bumps.note_archive, ctx.remaining_accounts),
+        proof,
+        ext_data_minified,
+        encrypted_output1,
+        encrypted_output2,
+        circuit,
+        payout_amounts,
+    )?;
+
+    // Emitted through a self-CPI so the ciphertext lands in inner-instruction data,
+    // which RPC nodes keep in full, instead of program logs that get truncated.
+    for commitment_data in commitments {
+        emit_cpi!(commitment_data);
+    }
+    Ok(result)
+}
+
+/// Accounts the transact logic runs on, borrowed from `Transact`, `ExecuteFromBuffer`
+/// or `TransactSharded`.
+struct TransactAccounts<'a, 'info> {
+    shard_id: u8,
+    tree_account: &'a AccountLoader<'info, MerkleTreeAccount>,
+    commitment_queue: Option<&'a AccountLoader<'info, CommitmentQueueAccount>>,
+    tree_token_account: AccountInfo<'info>,
+    pool: TransactPool<'a, 'info>,
+    note_archive: &'a AccountLoader<'info, NoteArchivePage>,
+    note_archive_bump: u8,
+    parties: &'a TransactParties<'info>,
+    system_program: &'a Program<'info, System>,
+    remaining_accounts: &'a [AccountInfo<'info>],
+}
+
+/// The circuit a transaction's proof is for, with the public inputs it adds to those of
+/// the transaction circuit.
+enum TransactCircuit<'a, 'info> {
+    Transaction,
+    /// Proven for every slot in the window; the verifying key comes from `TransactParties`.
+    Timelock(TimelockWindow),
+    /// Also proves the inputs descend from a deposit whose label is in the association
+    /// set under this root; the set and the verifying key come from `TransactParties`.
+    Association([u8; 32]),
+    /// Pays a file listing: seller pubkey, price, content hash and the buyer's claim commitment.
+    FilePurchase {
+        verifying_key: &'a Account<'info, VerifyingKeyAccount>,
+        listing_inputs: [[u8; 32]; 4],
+    },
+}
+
+/// Where a transaction records its outflow and protocol fee.
+enum TransactPool<'a, 'info> {
+    /// The main tree: outflow window in the global config, protocol fees paid straight
+    /// into the fee vault.
+    Main {
+        global_config: &'a mut Account<'info, GlobalConfig>,
+        fee_vault: &'a mut Account<'info, FeeVaultAccount>,
+    },
+    /// A tree shard: the global config is only read, and the outflow window and
+    /// protocol fees stay on the shard vault.
+    Shard {
+        global_config: &'a Account<'info, GlobalConfig>,
+        shard_vault: &'a mut Account<'info, ShardVaultAccount>,
+    },
+}
+
+impl TransactPool<'_, '_> {
+    fn config(&self) -> &GlobalConfig {
+        match self {
+            TransactPool::Main { global_config, .. } => global_config,
+            TransactPool::Shard { global_config, .. } => global_config,
+        }
+    }
+
+    /// Lamports in the vault that withdrawals may not touch, on top of its rent.
+    fn reserved_lamports(&self) -> u64 {
+        match self {
+            TransactPool::Main { .. } => 0,
+            TransactPool::Shard { shard_vault, .. } => shard_vault.pending_protocol_fees,
+        }
+    }
+
+    fn check_outflow_limit(&mut self, outflow: u64, pool_balance: u64, current_slot: u64) -> Result<()> {
+        match self {
+            TransactPool::Main { global_config, .. } => {
+                utils::check_outflow_limit(global_config, outflow, pool_balance, current_slot)
+            }
+            TransactPool::Shard {
+                global_config,
+                shard_vault,
+            } => utils::check_outflow_window(
+                &mut shard_vault.outflow_window,
+                global_config.max_outflow_rate,
+                global_config.outflow_window_slots,
+                outflow,
+                pool_balance,
+                current_slot,
+            ),
+        }
+    }
+
+    fn collect_protocol_fee(&mut self, tree_token_account: &AccountInfo, protocol_fee: u64) -> Result<()> {
+        match self {
+            TransactPool::Main { fee_vault, .. } => {
+                // The protocol's share accrues in the fee vault until claimed
+                utils::transfer_lamports(tree_token_account, &fee_vault.to_account_info(), protocol_fee)?;
+                fee_vault.total_accrued = fee_vault
+                    .total_accrued
+                    .checked_add(protocol_fee)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+            TransactPool::Shard { shard_vault, .. } => {
+                // Left in the shard vault until swept, so shards never write the fee vault
+                shard_vault.pending_protocol_fees = shard_vault
+                    .pending_protocol_fees
+                    .checked_add(protocol_fee)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refunds `page_rent` to the signer who just created a note archive page, out of
+    /// protocol fees: the fee vault's claimable balance, or a shard's unswept fees.
+    /// Returns the fee vault, which then gets the rent back when the page is closed,
+    /// or None if there are not enough fees and the signer keeps paying.
+    fn fund_archive_page(&mut self, signer: &AccountInfo, page_rent: u64, rent: &Rent) -> Result<Option<Pubkey>> {
+        match self {
+            TransactPool::Main { fee_vault, .. } => {
+                let fee_vault_info = fee_vault.to_account_info();
+                let claimable = fee_vault_info
+                    .lamports()
+                    .saturating_sub(rent.minimum_balance(fee_vault_info.data_len()));
+                if claimable < page_rent {
+                    return Ok(None);
+                }
+
+                utils::transfer_lamports(&fee_vault_info, signer, page_rent)?;
+                Ok(Some(fee_vault.key()))
+            }
+            TransactPool::Shard { shard_vault, .. } => {
+                if shard_vault.pending_protocol_fees < page_rent {
+                    return Ok(None);
+                }
+
+                shard_vault.pending_protocol_fees -= page_rent;
+                utils::transfer_lamports(&shard_vault.to_account_info(), signer, page_rent)?;
+                Ok(Some(Pubkey::find_program_address(&[b"fee_vault"], &crate::ID).0))
+            }
+        }
+    }
+}
+
+/**
+ * The transact logic shared by every way of submitting a transaction. Returns the two
+ * commitments for the caller to emit, and the result for the caller to return.
+ */
+fn execute_transact(
+    mut accounts: TransactAccounts,
+    proof: Proof,
+    ext_data_minified: ExtDataMinified,
+    encrypted_output1: Vec<u8>,
+    encrypted_output2: Vec<u8>,
+    circuit: TransactCircuit,
+    payout_amounts: Vec<u64>,
+) -> Result<(Vec<CommitmentData>, TransactResult)> {
+    // Reconstruct full ExtData from minified version and context accounts
+    let ext_data = ExtData::from_minified(
+        accounts.parties.recipient.key(),
+        accounts.parties.fee_recipient_account.key(),
+        ext_data_minified,
+    );
+
+    // Pair split withdrawal amounts with their recipients; they must cover |ext_amount| exactly
+    let payouts = if payout_amounts.is_empty() {
+        Vec::new()
+    } else {
+        require!(
+            ext_data.ext_amount < 0
+                && payout_amounts.len() <= MAX_WITHDRAWAL_PAYOUTS
+                && payout_amounts.len() == accounts.remaining_accounts.len()
+                && payout_amounts.iter().all(|&amount| amount > 0),
+            ErrorCode::InvalidWithdrawalPayouts
+        );
+
+        let payout_total = payout_amounts
+            .iter()
+            .try_fold(0u64, |total, &amount| total.checked_add(amount))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            payout_total == ext_data.ext_amount.unsigned_abs(),
+            ErrorCode::InvalidWithdrawalPayouts
+        );
+
+        accounts.remaining_accounts
+            .iter()
+            .zip(payout_amounts)
+            .map(|(recipient, amount)| Payout {
+                recipient: recipient.key(),
+                amount,
+            })
+            .collect()
+    };
+
+    let tree_account = &mut accounts.tree_account.load_mut()?;
+    let current_slot = Clock::get()?.slot;
+
+    // Withdraw-only mode expires on its own; the main pool clears it on first use
+    // after expiry, while shards only read the config
+    let withdraw_only_until = accounts.pool.config().withdraw_only_until;
+    if withdraw_only_until != 0 {
+        if current_slot >= withdraw_only_until {
+            if let TransactPool::Main { global_config, .. } = &mut accounts.pool {
+                global_config.withdraw_only_until = 0;
+                emit!(WithdrawOnlyExpired { slot: current_slot });
+            }
+        } else {
+            require!(ext_data.ext_amount < 0, ErrorCode::WithdrawOnlyMode);
+        }
+    }
+
+    // check if proof.root is in the tree_account's proof history
+    require!(
+        MerkleTree::is_known_root(tree_account, proof.root),
+        ErrorCode::UnknownRoot
+    );
+
+    let global_config = accounts.pool.config();
+
+    // check the encrypted outputs have an allowed length before hashing them
+    require!(
+        utils::check_encrypted_output_length(
+            encrypted_output1.len(),
+            &global_config.encrypted_output_lengths
+        ) && utils::check_encrypted_output_length(
+            encrypted_output2.len(),
+            &global_config.encrypted_output_lengths
+        ),
+        ErrorCode::InvalidEncryptedOutputLength
+    );
+
+    // check if the ext_data hashes to the same ext_data in the proof
+    let calculated_ext_data_hash = utils::calculate_complete_ext_data_hash(
+        ext_data.recipient,
+        ext_data.ext_amount,
+        &encrypted_output1,
+        &encrypted_output2,
+        ext_data.fee,
+        ext_data.protocol_fee,
+        ext_data.fee_recipient,
+        ext_data.mint_address,
+        &payouts,
+    )?;
+
+    require!(
+        Fr::from_le_bytes_mod_order(&calculated_ext_data_hash)
+            == Fr::from_be_bytes_mod_order(&proof.ext_data_hash),
+        ErrorCode::ExtDataHashMismatch
+    );
+
+    let ext_amount = ext_data.ext_amount;
+    let fee = ext_data.fee;
+    let protocol_fee = ext_data.protocol_fee;
+    let total_fee = fee
+        .checked_add(protocol_fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    require!(
+        utils::check_public_amount(ext_amount, total_fee, proof.public_amount),
+        ErrorCode::InvalidPublicAmountData
+    );
+
+    // Validate the protocol's share against the configured fee rates
+    utils::validate_fee(
+        ext_amount,
+        protocol_fee,
+        global_config.deposit_fee_rate,
+        global_config.withdrawal_fee_rate,
+        global_config.fee_error_margin,
+    )?;
+
+    if global_config.require_registered_relayer && fee > 0 {
+        let relayer = accounts
+            .parties
+            .relayer
+            .as_ref()
+            .ok_or(ErrorCode::RelayerNotRegistered)?;
+        utils::check_relayer_active(relayer, global_config.min_relayer_stake)?;
+        utils::check_relayer_fee(
+            fee,
+            ext_amount,
+            relayer.fee_rate,
+            global_config.max_relayer_transfer_fee,
+        )?;
+    }
+
+    // verify the proof against the circuit it was made for
+    let proof_valid = match circuit {
+        TransactCircuit::Transaction => verify_proof(proof.clone(), VERIFYING_KEY),
+        TransactCircuit::Timelock(window) => {
+            require!(
+                utils::check_timelock_window(&window, current_slot),
+                ErrorCode::TimelockWindowMismatch
+            );
+
+            let verifying_key = utils::groth16_verifying_key(
+                accounts
+                    .parties
+                    .timelock_verifying_key
+                    .as_ref()
+                    .ok_or(ErrorCode::VerifyingKeyRequired)?,
+            );
+            utils::verify_groth16(
+                &proof.proof_a,
+                &proof.proof_b,
+                &proof.proof_c,
+                &[
+                    proof.root,
+                    proof.public_amount,
+                    proof.ext_data_hash,
+                    proof.input_nullifiers[0],
+                    proof.input_nullifiers[1],
+                    proof.output_commitments[0],
+                    proof.output_commitments[1],
+                    utils::u64_to_field(window.min_slot),
+                    utils::u64_to_field(window.max_slot),
+                ],
+                &verifying_key,
+            )
+        }
+        TransactCircuit::Association(association_root) => {
+            let association_set = accounts
+                .parties
+                .association_set
+                .as_ref()
+                .ok_or(ErrorCode::AssociationSetRequired)?
+                .load()?;
+            require!(
+                MerkleTree::is_known_root(&association_set, association_root),
+                ErrorCode::UnknownAssociationRoot
+            );
+
+            let verifying_key = utils::groth16_verifying_key(
+                accounts
+                    .parties
+                    .association_verifying_key
+                    .as_ref()
+                    .ok_or(ErrorCode::VerifyingKeyRequired)?,
+            );
+            utils::verify_groth16(
+                &proof.proof_a,
+                &proof.proof_b,
+                &proof.proof_c,
+                &[
+                    proof.root,
+                    proof.public_amount,
+                    proof.ext_data_hash,
+                    proof.input_nullifiers[0],
+                    proof.input_nullifiers[1],
+                    proof.output_commitments[0],
+                    proof.output_commitments[1],
+                    association_root,
+                ],
+                &verifying_key,
+            )
+        }
+        TransactCircuit::FilePurchase {
+            verifying_key,
+            listing_inputs,
+        } => utils::verify_groth16(
+            &proof.proof_a,
+            &proof.proof_b,
+            &proof.proof_c,
+            &[
+                proof.root,
+                proof.public_amount,
+                proof.ext_data_hash,
+                proof.input_nullifiers[0],
+                proof.input_nullifiers[1],
+                proof.output_commitments[0],
+                proof.output_commitments[1],
+                listing_inputs[0],
+                listing_inputs[1],
+                listing_inputs[2],
+                listing_inputs[3],
+            ],
+            &utils::groth16_verifying_key(verifying_key),
+        ),
+    };
+    require!(proof_valid, ErrorCode::InvalidProof);
+
+    let deposit_screening_enabled = global_config.deposit_screening_enabled;
+    let tree_token_account_info = accounts.tree_token_account.clone();
+    let rent = Rent::get()?;
+    let rent_exempt_minimum = rent
+        .minimum_balance(tree_token_account_info.data_len())
+        .checked_add(accounts.pool.reserved_lamports())
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    // Everything that leaves the vault counts against the rolling outflow window,
+    // so a proof-system bug cannot drain the pool in a single block
+    let outflow = utils::transact_outflow(ext_amount, fee)?;
+
+    if outflow > 0 {
+        accounts.pool.check_outflow_limit(
+            outflow,
+            tree_token_account_info.lamports().saturating_sub(rent_exempt_minimum),
+            current_slot,
+        )?;
+    }
+
+    if ext_amount > 0 {
+        // Check deposit limit for deposits
+        let deposit_amount = ext_amount as u64;
+        require!(
+            deposit_amount <= tree_account.max_deposit_amount,
+            ErrorCode::DepositLimitExceeded
+        );
+
+        // Screen the depositing signer: its clearance must be against the current
+        // denylist. A denylist that hasn't been created yet denies nobody
+        if deposit_screening_enabled {
+            let denylist_info = accounts
+                .parties
+                .denylist
+                .as_ref()
+                .ok_or(ErrorCode::DepositorClearanceRequired)?
+                .to_account_info();
+            if !denylist_info.data_is_empty() {
+                require_keys_eq!(*denylist_info.owner, crate::ID, ErrorCode::InvalidDenylistProof);
+                let denylist = DenylistAccount::try_deserialize(&mut &denylist_info.try_borrow_data()?[..])?;
+                let clearance = accounts
+                    .parties
+                    .deposit_clearance
+                    .as_ref()
+                    .ok_or(ErrorCode::DepositorClearanceRequired)?;
+                Denylist::check_clearance(&clearance.denylist_root, &denylist.root)?;
+            }
+        }
+
+        // If it's a deposit, transfer the SOL to the tree token account.
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: accounts.parties.signer.to_account_info(),
+                    to: tree_token_account_info.clone(),
+                },
+            ),
+            ext_amount as u64,
+        )?;
+    } else if ext_amount < 0 {
+        // PDA can't directly sign transactions, so we need to transfer SOL via try_borrow_mut_lamports
+        // Withdrawals are only limited by the outflow window checked above
+        let recipient_account_info = accounts.parties.recipient.to_account_info();
+
+        let ext_amount_abs: u64 = ext_amount
+            .checked_neg()
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::InvalidExtAmount)?;
+
+        let total_required = ext_amount_abs
+            .checked_add(total_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_add(rent_exempt_minimum)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        require!(
+            tree_token_account_info.lamports() >= total_required,
+            ErrorCode::InsufficientFundsForWithdrawal
+        );
+
+        if payouts.is_empty() {
+            let tree_token_balance = tree_token_account_info.lamports();
+            let recipient_balance = recipient_account_info.lamports();
+
+            let new_tree_token_balance = tree_token_balance
+                .checked_sub(ext_amount_abs)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            let new_recipient_balance = recipient_balance
+                .checked_add(ext_amount_abs)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            **tree_token_account_info.try_borrow_mut_lamports()? = new_tree_token_balance;
+            **recipient_account_info.try_borrow_mut_lamports()? = new_recipient_balance;
+        } else {
+            for (recipient, payout) in accounts.remaining_accounts.iter().zip(&payouts) {
+                utils::transfer_lamports(&tree_token_account_info, recipient, payout.amount)?;
+            }
+        }
+    }
+
+    if total_fee > 0 && ext_amount >= 0 {
+        let total_required = total_fee
+            .checked_add(rent_exempt_minimum)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        require!(
+            tree_token_account_info.lamports() >= total_required,
+            ErrorCode::InsufficientFundsForFee
+        );
+    }
+
+    if fee > 0 {
+        utils::transfer_lamports(
+            &tree_token_account_info,
+            &accounts.parties.fee_recipient_account.to_account_info(),
+            fee,
+        )?;
+    }
+
+    if protocol_fee > 0 {
+        accounts
+            .pool
+            .collect_protocol_fee(&tree_token_account_info, protocol_fee)?;
+    }
+
+    // A withdrawal that leaves only zero notes has nothing to insert. Queued trees drop
+    // its outputs, so the pool can still be exited while the queue is full
+    if utils::drops_outputs(tree_account.queue_enabled != 0, ext_amount, proof.output_commitments) {
+        // The archive page for next_index may have just been created for this
+        // instruction; initialize it so the outputs that do land on it can be recorded
+        NoteArchive::load_page(
+            accounts.note_archive,
+            accounts.shard_id,
+            tree_account.next_index,
+            accounts.parties.signer.key(),
+            accounts.note_archive_bump,
+        )?;
+        let result = TransactResult {
+            leaf_indices: None,
+            root: None,
+            path: Vec::new(),
+        };
+        return Ok((Vec::new(), result));
+    }
+
+    let (next_index_to_insert, path) = CommitmentQueue::append_pair::<Poseidon>(
+        proof.output_commitments,
+        tree_account,
+        accounts.commitment_queue,
+    )?;
+
+    let second_index = next_index_to_insert
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    // A new page is paid for out of protocol fees when there are enough of them, so
+    // the user whose outputs happen to start it does not carry the whole page's rent
+    let mut page_payer = accounts.parties.signer.key();
+    if NoteArchive::is_new_page(accounts.note_archive)? {
+        let page_rent = rent.minimum_balance(accounts.note_archive.as_ref().data_len());
+        if let Some(fee_vault) = accounts.pool.fund_archive_page(
+            &accounts.parties.signer.to_account_info(),
+            page_rent,
+            &rent,
+        )? {
+            page_payer = fee_vault;
+        }
+    }
+
+    // Archive both notes on-chain so wallets can restore without log history.
+    // Outputs are appended in pairs starting at an even index, so both share a page.
+    let note_archive = &mut NoteArchive::load_page(
+        accounts.note_archive,
+        accounts.shard_id,
+        next_index_to_insert,
+        page_payer,
+        accounts.note_archive_bump,
+    )?;
+    NoteArchive::record(
+        note_archive,
+        next_index_to_insert,
+        proof.output_commitments[0],
+        &encrypted_output1,
+        current_slot,
+    )?;
+    NoteArchive::record(
+        note_archive,
+        second_index,
+        proof.output_commitments[1],
+        &encrypted_output2,
+        current_slot,
+    )?;
+
+    let result = TransactResult {
+        leaf_indices: Some([next_index_to_insert, second_index]),
+        root: (!path.is_empty()).then_some(tree_account.root),
+        path,
+    };
+
+    Ok((
+        vec![
+            CommitmentData {
+                index: next_index_to_insert,
+                commitment: proof.output_commitments[0],
+                encrypted_output: encrypted_output1,
+            },
+            CommitmentData {
+                index: second_index,
+                commitment: proof.output_commitments[1],
+                encrypted_output: encrypted_output2,
+            },
+        ],
+        result,
+    ))
+}
+
+#[event]
+pub struct CommitmentData {
+    pub index: u64,
+    pub commitment: [u8; 32],
+    pub encrypted_output: Vec<u8>,
+}
+
+#[event]
+pub struct FeesClaimed {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+}
+
+#[event]
+pub struct RelayerConfigUpdated {
+    pub min_relayer_stake: u64,
+    pub require_registered_relayer: bool,
+    pub max_relayer_transfer_fee: u64,
+}
+
+#[event]
+pub struct RelayerRegistered {
+    pub relayer: Pubkey,
+    pub stake: u64,
+    pub fee_rate: u16,
+    pub endpoint: String,
+}
+
+#[event]
+pub struct RelayerUpdated {
+    pub relayer: Pubkey,
+    pub fee_rate: u16,
+    pub endpoint: String,
+}
+
+#[event]
+pub struct RelayerUnbonding {
+    pub relayer: Pubkey,
+    pub unbond_after: u64,
+}
+
+#[event]
+pub struct RelayerWithdrawn {
+    pub relayer: Pubkey,
+    pub stake: u64,
+}
+
+#[event]
+pub struct RelayerSlashed {
+    pub relayer: Pubkey,
+    pub amount: u64,
+    pub remaining_stake: u64,
+}
+
+#[event]
+pub struct RelayerUnjailed {
+    pub relayer: Pubkey,
+}
+
+#[event]
+pub struct EncryptedOutputLengthsUpdated {
+    pub encrypted_output_lengths: [u16; state::MAX_ENCRYPTED_OUTPUT_LENGTHS],
+}
+
+#[event]
+pub struct NoteArchiveRetentionUpdated {
+    pub note_archive_retention_slots: u64,
+}
+
+#[event]
+pub struct NoteArchivePageClosed {
+    pub page_index: u64,
+}
+
+#[event]
+pub struct AssociationCuratorUpdated {
+    pub curator: Pubkey,
+}
+
+#[event]
+pub struct AssociationLabelAdded {
+    pub index: u64,
+    pub label: [u8; 32],
+}
+
+#[event]
+pub struct ScreeningConfigUpdated {
+    pub screening_authority: Pubkey,
+    pub deposit_screening_enabled: bool,
+}
+
+#[event]
+pub struct DenylistUpdated {
+    pub root: [u8; 32],
+    pub count: u64,
+}
+
+#[event]
+pub struct DepositorCleared {
+    pub depositor: Pubkey,
+    pub denylist_root: [u8; 32],
+}
+
+#[event]
+pub struct VerifyingKeySet {
+    pub circuit: u8,
+    pub nr_pubinputs: u8,
+}
+
+#[event]
+pub struct FileRegistered {
+    pub index: u64,
+    pub irys_tx_id: [u8; 32],
+    pub content_hash: [u8; 32],
+    pub recipient_commitment: [u8; 32],
+}
+
+#[event]
+pub struct FileClaimed {
+    pub nullifier: [u8; 32],
+    pub claimer: Pubkey,
+}
+
+#[event]
+pub struct FileRegistrationFeeUpdated {
+    pub file_registration_fee: u64,
+}
+
+#[event]
+pub struct FileListed {
+    pub lister: Pubkey,
+    pub irys_tx_id: [u8; 32],
+    pub content_hash: [u8; 32],
+    pub seller_pubkey: [u8; 32],
+    pub price: u64,
+}
+
+#[event]
+pub struct FilePurchased {
+    pub irys_tx_id: [u8; 32],
+    pub claim_index: u64,
+    pub claim_commitment: [u8; 32],
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub accept_after: u64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct FeeConfigUpdated {
+    pub deposit_fee_rate: u16,
+    pub withdrawal_fee_rate: u16,
+    pub fee_error_margin: u16,
+}
+
+#[event]
+pub struct DepositLimitUpdated {
+    pub max_deposit_amount: u64,
+}
+
+#[event]
+pub struct ShardDepositLimitUpdated {
+    pub shard_id: u8,
+    pub max_deposit_amount: u64,
+}
+
+#[event]
+pub struct OutflowLimitUpdated {
+    pub max_outflow_rate: u16,
+    pub outflow_window_slots: u64,
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct WithdrawOnlyEnabled {
+    pub enabled_by: Pubkey,
+    pub until_slot: u64,
+}
+
+#[event]
+pub struct WithdrawOnlyDisabled {
+    pub disabled_by: Pubkey,
+}
+
+#[event]
+pub struct WithdrawOnlyExpired {
+    pub slot: u64,
+}
+
+#[event]
+pub struct TreeShardInitialized {
+    pub shard_id: u8,
+    pub shard_tree: Pubkey,
+    pub shard_vault: Pubkey,
+}
+
+/// `CommitmentData` for an output appended to a tree shard; `index` is its leaf index
+/// in that shard's tree.
+#[event]
+pub struct ShardCommitmentData {
+    pub shard_id: u8,
+    pub index: u64,
+    pub commitment: [u8; 32],
+    pub encrypted_output: Vec<u8>,
+}
+
+#[event]
+pub struct ShardFeesSwept {
+    pub shard_id: u8,
+    pub amount: u64,
+    pub total_accrued: u64,
+}
+
+#[event]
+pub struct NullifierTreeInitialized {
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct CommitmentQueueInitialized {
+    pub shard_id: u8,
+    pub next_insert_index: u64,
+}
+
+#[event]
+pub struct CommitmentBatchInserted {
+    pub shard_id: u8,
+    pub start_index: u64,
+    pub count: u8,
+    pub root: [u8; 32],
+}
+
+// all public inputs needs to be in big endian format
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Proof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub root: [u8; 32],
+    pub public_amount: [u8; 32],
+    pub ext_data_hash: [u8; 32],
+    pub input_nullifiers: [[u8; 32]; 2],
+    pub output_commitments: [[u8; 32]; 2],
+}
+
+/// Returned by the transact instructions through return data, so a wallet can spend a
+/// fresh output without an indexer. The outputs are siblings, so each one's Merkle path
+/// is the other output followed by `path`, the siblings above the pair, and is valid
+/// against `root`. When the outputs were queued instead, `root` is None and `path` is
+/// empty: the tree's root does not cover them yet, and they can only be spent once a
+/// batch inserts them. `leaf_indices` is None when a queued tree dropped two zero notes.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransactResult {
+    pub leaf_indices: Option<[u64; 2]>,
+    pub root: Option<[u8; 32]>,
+    pub path: Vec<[u8; 32]>,
+}
+
+/// Proof that inserting a batch of queued commitments moves a tree to `new_root`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CommitmentBatchProof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub new_root: [u8; 32],
+}
+
+/// Slots a timelock proof is valid for; `transact_timelocked` requires the current
+/// slot to be inside it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TimelockWindow {
+    pub min_slot: u64,
+    pub max_slot: u64,
+}
+
+/// Merkle path to the gap of the sorted denylist between `low` and `high`, proving
+/// every address strictly between them is not on the list.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct DenylistProof {
+    pub low: Pubkey,
+    pub high: Pubkey,
+    pub leaf_index: u64,
+    pub path: [[u8; 32]; DENYLIST_TREE_HEIGHT],
+}
+
+// all public inputs needs to be in big endian format; the claimer public input is not
+// passed but taken from the signer's key, reduced into the field
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FileClaimProof {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub root: [u8; 32],
+    pub nullifier: [u8; 32],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExtData {
+    pub recipient: Pubkey,
+    pub ext_amount: i64,
+    pub fee: u64,          // relayer share, paid to fee_recipient
+    pub protocol_fee: u64, // protocol share, accrued in the fee vault
+    pub fee_recipient: Pubkey,
+    pub mint_address: Pubkey,
+}
+
+/// One recipient of a split withdrawal, as committed to in the ext data hash.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Payout {
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExtDataMinified {
+    pub ext_amount: i64,
+    pub fee: u64,
+    pub protocol_fee: u64,
+}
+
+/// Transact arguments staged in a proof buffer, Borsh-encoded, for `execute_from_buffer`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BufferedTransact {
+    pub proof: Proof,
+    pub ext_data_minified: ExtDataMinified,
+    pub encrypted_output1: Vec<u8>,
+    pub encrypted_output2: Vec<u8>,
+    pub timelock_window: Option<TimelockWindow>,
+    pub payout_amounts: Vec<u64>,
+}
+
+/// A leaf of the nullifier tree.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NullifierLeaf {
+    pub value: [u8; 32],
+    pub next_index: u64,
+    pub next_value: [u8; 32], // zero if no larger nullifier has been spent
+}
+
+/// Proof that a nullifier is not in the nullifier tree, and the paths to insert it.
+/// `low_leaf` is the leaf that links over the nullifier, and `new_leaf_path` the path
+/// of the next empty leaf once the low leaf links to it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NullifierInsertProof {
+    pub low_leaf: NullifierLeaf,
+    pub low_leaf_index: u64,
+    pub low_leaf_path: [[u8; 32]; NULLIFIER_TREE_HEIGHT],
+    pub new_leaf_path: [[u8; 32]; NULLIFIER_TREE_HEIGHT],
+}
+
+/// Transact arguments staged for `execute_from_buffer_with_nullifier_tree`: a staged
+/// transaction followed by the nullifier tree insertion of each of its inputs.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BufferedNullifierTreeTransact {
+    pub transact: BufferedTransact,
+    pub nullifier_proofs: [NullifierInsertProof; 2],
+}
+
+impl ExtData {
+    fn from_minified(recipient: Pubkey, fee_recipient: Pubkey, minified: ExtDataMinified) -> Self {
+        Self {
+            recipient,
+            ext_amount: minified.ext_amount,
+            fee: minified.fee,
+            protocol_fee: minified.protocol_fee,
+            fee_recipient,
+            mint_address: utils::SOL_ADDRESS,
+        }
+    }
+}
+
+impl<'info> MainPool<'info> {
+    fn transact_accounts<'a>(
+        &'a mut self,
+        note_archive: &'a AccountLoader<'info, NoteArchivePage>,
+        note_archive_bump: u8,
+        parties: &'a TransactParties<'info>,
+        system_program: &'a Program<'info, System>,
+        remaining_accounts: &'a [AccountInfo<'info>],
+    ) -> TransactAccounts<'a, 'info> {
+        TransactAccounts {
+            shard_id: 0,
+            tree_account: &self.tree_account,
+            commitment_queue: self.commitment_queue.as_ref(),
+            tree_token_account: self.tree_token_account.to_account_info(),
+            pool: TransactPool::Main {
+                global_config: &mut self.global_config,
+                fee_vault: &mut self.fee_vault,
+            },
+            note_archive,
+            note_archive_bump,
+            parties,
+            system_program,
+            remaining_accounts,
+        }
+    }
+}
+
+impl<'info> Transact<'info> {
+    fn transact_accounts<'a>(
+        &'a mut self,
+        note_archive_bump: u8,
+        remaining_accounts: &'a [AccountInfo<'info>],
+    ) -> TransactAccounts<'a, 'info> {
+        self.pool.transact_accounts(
+            &self.note_archive,
+            note_archive_bump,
+            &self.parties,
+            &self.system_program,
+            remaining_accounts,
+        )
+    }
+}
+
+impl<'info> TransactSharded<'info> {
+    fn transact_accounts(&mut self, shard_id: u8, note_archive_bump: u8) -> TransactAccounts<'_, 'info> {
+        TransactAccounts {
+            shard_id,
+            tree_account: &self.tree_account,
+            commitment_queue: self.commitment_queue.as_ref(),
+            tree_token_account: self.shard_vault.to_account_info(),
+            pool: TransactPool::Shard {
+                global_config: &self.global_config,
+                shard_vault: &mut self.shard_vault,
+            },
+            note_archive: &self.note_archive,
+            note_archive_bump,
+            parties: &self.parties,
+            system_program: &self.system_program,
+            remaining_accounts: &[],
+        }
+    }
+}
+
+impl<'info> ExecuteFromBuffer<'info> {
+    fn transact_accounts<'a>(
+        &'a mut self,
+        note_archive_bump: u8,
+        remaining_accounts: &'a [AccountInfo<'info>],
+    ) -> TransactAccounts<'a, 'info> {
+        self.pool.transact_accounts(
+            &self.note_archive,
+            note_archive_bump,
+            &self.parties,
+            &self.system_program,
+            remaining_accounts,
+        )
+    }
+}
+
+impl<'info> ExecuteFromBufferWithNullifierTree<'info> {
+    fn transact_accounts<'a>(
+        &'a mut self,
+        note_archive_bump: u8,
+        remaining_accounts: &'a [AccountInfo<'info>],
+    ) -> TransactAccounts<'a, 'info> {
+        self.pool.transact_accounts(
+            &self.note_archive,
+            note_archive_bump,
+            &self.parties,
+            &self.system_program,
+            remaining_accounts,
+        )
+    }
+}
+
+/// The main tree and the vaults its transactions pay in and out of.
+#[derive(Accounts)]
+pub struct MainPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    /// Required once the tree queues its outputs.
+    #[account(
+        mut,
+        seeds = [b"commitment_queue"],
+        bump = commitment_queue.load()?.bump
+    )]
+    pub commitment_queue: Option<AccountLoader<'info, CommitmentQueueAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+}
+
+/// Accounts every transact variant takes the same way, whichever tree it runs on.
+#[derive(Accounts)]
+pub struct TransactParties<'info> {
+    #[account(mut)]
+    /// CHECK: user should be able to send funds to any types of accounts
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
     /// CHECK: user should be able to send fees to any types of accounts
     pub fee_recipient_account: UncheckedAccount<'info>,
 
-    /// The account that is signing the transaction
+    /// Registry entry of the fee recipient; only required when
+    /// `global_config.require_registered_relayer` is set.
+    #[account(
+        seeds = [b"relayer", fee_recipient_account.key().as_ref()],
+        bump = relayer.bump
+    )]
+    pub relayer: Option<Account<'info, RelayerAccount>>,
+
+    /// The account that is signing the transaction
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// Deposit denylist, possibly not created yet, and the signer's clearance against
+    /// it; only required for deposits when `global_config.deposit_screening_enabled` is set.
+    #[account(
+        seeds = [b"denylist"],
+        bump
+    )]
+    /// CHECK: address checked by seeds; an account that doesn't exist yet is an empty list
+    pub denylist: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"deposit_clearance", signer.key().as_ref()],
+        bump = deposit_clearance.bump
+    )]
+    pub deposit_clearance: Option<Account<'info, DepositorClearance>>,
+
+    /// Verifying key of the timelock circuit; only required by `transact_timelocked`.
+    #[account(
+        seeds = [b"verifying_key", [state::TIMELOCK_TRANSACTION_CIRCUIT].as_ref()],
+        bump = timelock_verifying_key.bump
+    )]
+    pub timelock_verifying_key: Option<Account<'info, VerifyingKeyAccount>>,
+
+    /// Association set and the verifying key of the association circuit; only
+    /// required by `transact_associated`.
+    #[account(
+        seeds = [b"association_set"],
+        bump = association_set.load()?.bump
+    )]
+    pub association_set: Option<AccountLoader<'info, MerkleTreeAccount>>,
+
+    #[account(
+        seeds = [b"verifying_key", [state::ASSOCIATION_TRANSACTION_CIRCUIT].as_ref()],
+        bump = association_verifying_key.bump
+    )]
+    pub association_verifying_key: Option<Account<'info, VerifyingKeyAccount>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(proof: Proof, ext_data_minified: ExtDataMinified, encrypted_output1: Vec<u8>, encrypted_output2: Vec<u8>)]
+pub struct Transact<'info> {
+    pub pool: MainPool<'info>,
+
+    /// Nullifier account to mark the first input as spent.
+    /// Using `init` without `init_if_needed` ensures that the transaction
+    /// will automatically fail with a system program error if this nullifier
+    /// has already been used (i.e., if the account already exists).
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump,
+        constraint = !pool.global_config.nullifier_tree_enabled @ ErrorCode::NullifierTreeEnabled
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+
+    /// Nullifier account to mark the second input as spent.
+    /// Using `init` without `init_if_needed` ensures that the transaction
+    /// will automatically fail with a system program error if this nullifier
+    /// has already been used (i.e., if the account already exists).
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    /// Archive page receiving both output notes; created by the signer when the
+    /// outputs start a new page.
+    #[account(
+        init_if_needed,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NoteArchivePage>(),
+        seeds = [
+            b"note_archive",
+            NoteArchive::page_index(pool.tree_account.load()?.next_index).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    pub parties: TransactParties<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
+        seeds = [b"merkle_tree"],
+        bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<TreeTokenAccount>(),
+        seeds = [b"tree_token"],
+        bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<GlobalConfig>(),
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<FeeVaultAccount>(),
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Txnsfr>,
+
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = signer.key() == global_config.authority
+            || signer.key() == global_config.guardian @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"tree_token"],
+        bump = tree_token_account.bump
+    )]
+    pub tree_token_account: Account<'info, TreeTokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        constraint = global_config.pending_authority == new_authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDepositLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = tree_account.load()?.bump,
+        constraint = tree_account.load()?.authority == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    /// CHECK: the authority may send protocol fees to any account, e.g. a treasury
+    pub recipient: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterRelayer<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + RelayerAccount::INIT_SPACE,
+        seeds = [b"relayer", authority.key().as_ref()],
+        bump
+    )]
+    pub relayer: Account<'info, RelayerAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRelayer<'info> {
+    #[account(
+        mut,
+        seeds = [b"relayer", authority.key().as_ref()],
+        bump = relayer.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub relayer: Account<'info, RelayerAccount>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawRelayerStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"relayer", authority.key().as_ref()],
+        bump = relayer.bump,
+        has_one = authority @ ErrorCode::Unauthorized,
+        close = authority
+    )]
+    pub relayer: Account<'info, RelayerAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SlashRelayer<'info> {
+    #[account(
+        mut,
+        seeds = [b"relayer", relayer.authority.as_ref()],
+        bump = relayer.bump
+    )]
+    pub relayer: Account<'info, RelayerAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseNoteArchivePage<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"note_archive",
+            note_archive.load()?.page_index.to_le_bytes().as_ref(),
+            utils::shard_seed(&note_archive.load()?.shard_id)
+        ],
+        bump = note_archive.load()?.bump,
+        constraint = note_archive.load()?.payer == payer.key() @ ErrorCode::Unauthorized,
+        close = payer
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    /// CHECK: original payer of the page, validated against the page; receives the rent refund
+    pub payer: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAssociationSet<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
+        seeds = [b"association_set"],
+        bump
+    )]
+    pub association_set: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetAssociationCurator<'info> {
+    #[account(
+        mut,
+        seeds = [b"association_set"],
+        bump = association_set.load()?.bump
+    )]
+    pub association_set: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddAssociationLabels<'info> {
+    #[account(
+        mut,
+        seeds = [b"association_set"],
+        bump = association_set.load()?.bump,
+        constraint = association_set.load()?.authority == curator.key() @ ErrorCode::Unauthorized
+    )]
+    pub association_set: AccountLoader<'info, MerkleTreeAccount>,
+
+    pub curator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeDenylist<'info> {
+    #[account(
+        init,
+        payer = screening_authority,
+        space = 8 + DenylistAccount::INIT_SPACE,
+        seeds = [b"denylist"],
+        bump
+    )]
+    pub denylist: Account<'info, DenylistAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = screening_authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub screening_authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDenylist<'info> {
+    #[account(
+        mut,
+        seeds = [b"denylist"],
+        bump = denylist.bump
+    )]
+    pub denylist: Account<'info, DenylistAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = screening_authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub screening_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClearDepositor<'info> {
+    #[account(
+        seeds = [b"denylist"],
+        bump = denylist.bump
+    )]
+    pub denylist: Account<'info, DenylistAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + DepositorClearance::INIT_SPACE,
+        seeds = [b"deposit_clearance", signer.key().as_ref()],
+        bump
+    )]
+    pub deposit_clearance: Account<'info, DepositorClearance>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(circuit: u8)]
+pub struct SetVerifyingKey<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + VerifyingKeyAccount::INIT_SPACE,
+        seeds = [b"verifying_key", [circuit].as_ref()],
+        bump
+    )]
+    pub verifying_key: Account<'info, VerifyingKeyAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeFileRegistry<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
+        seeds = [b"file_registry"],
+        bump
+    )]
+    pub file_registry: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterFile<'info> {
+    #[account(
+        mut,
+        seeds = [b"file_registry"],
+        bump = file_registry.load()?.bump
+    )]
+    pub file_registry: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Receives the registration fee.
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(proof: FileClaimProof)]
+pub struct ClaimFile<'info> {
+    #[account(
+        seeds = [b"file_registry"],
+        bump = file_registry.load()?.bump
+    )]
+    pub file_registry: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"verifying_key", [state::FILE_CLAIM_CIRCUIT].as_ref()],
+        bump = verifying_key.bump
+    )]
+    pub verifying_key: Account<'info, VerifyingKeyAccount>,
+
+    /// Marks the file claim as used; `init` fails if the nullifier was already claimed.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"file_nullifier", proof.nullifier.as_ref()],
+        bump
+    )]
+    pub file_nullifier: Account<'info, NullifierAccount>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(irys_tx_id: [u8; 32])]
+pub struct ListFile<'info> {
+    /// Seeded by the signer as well, so nobody can take a file's listing address by
+    /// listing it first.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + FileListing::INIT_SPACE,
+        seeds = [b"file_listing", signer.key().as_ref(), irys_tx_id.as_ref()],
+        bump
+    )]
+    pub file_listing: Account<'info, FileListing>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(proof: Proof)]
+pub struct PurchaseFile<'info> {
+    pub pool: MainPool<'info>,
+
+    /// Nullifier accounts shared with `transact`, so a note spent by a purchase
+    /// cannot be spent again by either instruction.
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump,
+        constraint = !pool.global_config.nullifier_tree_enabled @ ErrorCode::NullifierTreeEnabled
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NoteArchivePage>(),
+        seeds = [
+            b"note_archive",
+            NoteArchive::page_index(pool.tree_account.load()?.next_index).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    pub parties: TransactParties<'info>,
+
+    #[account(
+        seeds = [b"file_listing", file_listing.lister.as_ref(), file_listing.irys_tx_id.as_ref()],
+        bump = file_listing.bump
+    )]
+    pub file_listing: Account<'info, FileListing>,
+
+    #[account(
+        mut,
+        seeds = [b"file_registry"],
+        bump = file_registry.load()?.bump
+    )]
+    pub file_registry: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"verifying_key", [state::FILE_PURCHASE_CIRCUIT].as_ref()],
+        bump = verifying_key.bump
+    )]
+    pub verifying_key: Account<'info, VerifyingKeyAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(buffer_id: u64)]
+pub struct InitProofBuffer<'info> {
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + ProofBuffer::INIT_SPACE,
+        seeds = [b"proof_buffer", signer.key().as_ref(), buffer_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+
     #[account(mut)]
     pub signer: Signer<'info>,
 
@@ -391,32 +3297,365 @@ pub struct Transact<'info> {
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+#[instruction(buffer_id: u64)]
+pub struct UpdateProofBuffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"proof_buffer", signer.key().as_ref(), buffer_id.to_le_bytes().as_ref()],
+        bump = proof_buffer.bump
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+
+    /// Receives the rent refund when the buffer is closed.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(buffer_id: u64)]
+pub struct ExecuteFromBuffer<'info> {
+    /// Staged transact arguments; closed to the signer once executed.
+    #[account(
+        mut,
+        seeds = [b"proof_buffer", parties.signer.key().as_ref(), buffer_id.to_le_bytes().as_ref()],
+        bump = proof_buffer.bump
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+
+    pub pool: MainPool<'info>,
+
+    /// Same nullifier accounts as `Transact`, seeded from the staged proof.
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof_buffer.input_nullifier(0).as_ref()],
+        bump,
+        constraint = !pool.global_config.nullifier_tree_enabled @ ErrorCode::NullifierTreeEnabled
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof_buffer.input_nullifier(1).as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof_buffer.input_nullifier(1).as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof_buffer.input_nullifier(0).as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    /// Archive page receiving both output notes; created by the signer when the
+    /// outputs start a new page.
+    #[account(
+        init_if_needed,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NoteArchivePage>(),
+        seeds = [
+            b"note_archive",
+            NoteArchive::page_index(pool.tree_account.load()?.next_index).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    pub parties: TransactParties<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(buffer_id: u64)]
+pub struct ExecuteFromBufferWithNullifierTree<'info> {
+    /// Staged transact arguments and nullifier insertions; closed to the signer once executed.
+    #[account(
+        mut,
+        seeds = [b"proof_buffer", parties.signer.key().as_ref(), buffer_id.to_le_bytes().as_ref()],
+        bump = proof_buffer.bump
+    )]
+    pub proof_buffer: Account<'info, ProofBuffer>,
+
+    pub pool: MainPool<'info>,
+
+    /// Only exists once `initialize_nullifier_tree` has switched the pool over.
+    #[account(
+        mut,
+        seeds = [b"nullifier_tree"],
+        bump = nullifier_tree.load()?.bump
+    )]
+    pub nullifier_tree: AccountLoader<'info, NullifierTreeAccount>,
+
+    /// Nullifiers spent before the switch are PDAs. None may exist for either input,
+    /// under either seed, so a note spent before the switch can't be spent again.
+    #[account(
+        seeds = [b"nullifier0", proof_buffer.input_nullifier(0).as_ref()],
+        bump
+    )]
+    pub nullifier0: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof_buffer.input_nullifier(1).as_ref()],
+        bump
+    )]
+    pub nullifier1: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier0", proof_buffer.input_nullifier(1).as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof_buffer.input_nullifier(0).as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    /// Archive page receiving both output notes; created by the signer when the
+    /// outputs start a new page.
+    #[account(
+        init_if_needed,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NoteArchivePage>(),
+        seeds = [
+            b"note_archive",
+            NoteArchive::page_index(pool.tree_account.load()?.next_index).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    pub parties: TransactParties<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(shard_id: u8, proof: Proof)]
+pub struct TransactSharded<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", [shard_id].as_ref()],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    /// Required once the tree queues its outputs.
+    #[account(
+        mut,
+        seeds = [b"commitment_queue", [shard_id].as_ref()],
+        bump = commitment_queue.load()?.bump
+    )]
+    pub commitment_queue: Option<AccountLoader<'info, CommitmentQueueAccount>>,
+
+    /// Nullifiers are shared by all trees, so a note can only ever be spent once. Shard
+    /// spends use PDAs even with the nullifier tree enabled, which only main tree spends use.
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier0", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier0: Account<'info, NullifierAccount>,
+
+    #[account(
+        init,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NullifierAccount>(),
+        seeds = [b"nullifier1", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier1: Account<'info, NullifierAccount>,
+
+    #[account(
+        seeds = [b"nullifier0", proof.input_nullifiers[1].as_ref()],
+        bump
+    )]
+    pub nullifier2: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"nullifier1", proof.input_nullifiers[0].as_ref()],
+        bump
+    )]
+    pub nullifier3: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"shard_vault", [shard_id].as_ref()],
+        bump = shard_vault.bump
+    )]
+    pub shard_vault: Account<'info, ShardVaultAccount>,
+
+    /// Only read, so transactions on different shards do not contend on it.
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// Archive page receiving both output notes; created by the signer when the
+    /// outputs start a new page.
+    #[account(
+        init_if_needed,
+        payer = parties.signer,
+        space = 8 + std::mem::size_of::<NoteArchivePage>(),
+        seeds = [
+            b"note_archive",
+            NoteArchive::page_index(tree_account.load()?.next_index).to_le_bytes().as_ref(),
+            [shard_id].as_ref()
+        ],
+        bump
+    )]
+    pub note_archive: AccountLoader<'info, NoteArchivePage>,
+
+    pub parties: TransactParties<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u8)]
+pub struct InitializeTreeShard<'info> {
     #[account(
         init,
         payer = authority,
         space = 8 + std::mem::size_of::<MerkleTreeAccount>(),
-        seeds = [b"merkle_tree"],
+        seeds = [b"merkle_tree", [shard_id].as_ref()],
         bump
     )]
-    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+    pub shard_tree: AccountLoader<'info, MerkleTreeAccount>,
 
     #[account(
         init,
         payer = authority,
-        space = 8 + std::mem::size_of::<TreeTokenAccount>(),
-        seeds = [b"tree_token"],
+        space = 8 + ShardVaultAccount::INIT_SPACE,
+        seeds = [b"shard_vault", [shard_id].as_ref()],
         bump
     )]
-    pub tree_token_account: Account<'info, TreeTokenAccount>,
+    pub shard_vault: Account<'info, ShardVaultAccount>,
+
+    /// The main tree, whose deposit limit the shard starts with.
+    #[account(
+        seeds = [b"merkle_tree"],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u8)]
+pub struct UpdateShardDepositLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", [shard_id].as_ref()],
+        bump = shard_tree.load()?.bump
+    )]
+    pub shard_tree: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u8)]
+pub struct SweepShardFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"shard_vault", [shard_id].as_ref()],
+        bump = shard_vault.bump
+    )]
+    pub shard_vault: Account<'info, ShardVaultAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVaultAccount>,
+}
 
+#[derive(Accounts)]
+pub struct InitializeNullifierTree<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + std::mem::size_of::<GlobalConfig>(),
+        space = 8 + std::mem::size_of::<NullifierTreeAccount>(),
+        seeds = [b"nullifier_tree"],
+        bump
+    )]
+    pub nullifier_tree: AccountLoader<'info, NullifierTreeAccount>,
+
+    #[account(
+        mut,
         seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u8)]
+pub struct InitializeCommitmentQueue<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", utils::shard_seed(&shard_id)],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<CommitmentQueueAccount>(),
+        seeds = [b"commitment_queue", utils::shard_seed(&shard_id)],
         bump
     )]
+    pub commitment_queue: AccountLoader<'info, CommitmentQueueAccount>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump = global_config.bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
     pub global_config: Account<'info, GlobalConfig>,
 
     #[account(mut)]
@@ -424,3 +3663,27 @@ pub struct Initialize<'info> {
 
     pub system_program: Program<'info, System>,
 }
+
+#[derive(Accounts)]
+#[instruction(shard_id: u8)]
+pub struct InsertCommitmentBatch<'info> {
+    #[account(
+        mut,
+        seeds = [b"merkle_tree", utils::shard_seed(&shard_id)],
+        bump = tree_account.load()?.bump
+    )]
+    pub tree_account: AccountLoader<'info, MerkleTreeAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"commitment_queue", utils::shard_seed(&shard_id)],
+        bump = commitment_queue.load()?.bump
+    )]
+    pub commitment_queue: AccountLoader<'info, CommitmentQueueAccount>,
+
+    #[account(
+        seeds = [b"verifying_key", [state::BATCH_INSERT_CIRCUIT].as_ref()],
+        bump = verifying_key.bump
+    )]
+    pub verifying_key: Account<'info, VerifyingKeyAccount>,
+}
@@ -11,9 +11,7 @@ impl MerkleTree {
         
         // Initialize empty subtrees
         let zero_bytes = H::zero_bytes();
-        for i in 0..height {
-            tree_account.subtrees[i] = zero_bytes[i];
-        }
+        tree_account.subtrees[..height].copy_from_slice(&zero_bytes[..height]);
 
         // Set initial root
         let initial_root = H::zero_bytes()[height];
@@ -44,19 +42,19 @@ impl MerkleTree {
         let mut right;
         let mut proof: Vec<[u8; 32]> = vec![[0u8; 32]; height];
 
-        for i in 0..height {
+        for (i, sibling) in proof.iter_mut().enumerate() {
             let subtree = &mut tree_account.subtrees[i];
             let zero_byte = H::zero_bytes()[i];
             
-            if current_index % 2 == 0 {
+            if current_index & 1 == 0 {
                 left = current_level_hash;
                 right = zero_byte;
                 *subtree = current_level_hash;
-                proof[i] = right;
+                *sibling = right;
             } else {
                 left = *subtree;
                 right = current_level_hash;
-                proof[i] = left;
+                *sibling = left;
             }
             current_level_hash = H::hashv(&[&left, &right]).unwrap();
             current_index /= 2;
@@ -76,6 +74,85 @@ impl MerkleTree {
         Ok(proof)
     }
 
+    /// Appends two sibling leaves in one pass and records a single root.
+    /// `next_index` must be even, which holds for trees only ever filled in pairs.
+    /// Returns the Merkle path of each leaf.
+    pub fn append_pair<H: Hasher>(
+        leaves: [[u8; 32]; 2],
+        tree_account: &mut MerkleTreeAccount,
+    ) -> Result<[Vec<[u8; 32]>; 2]> {
+        let height = tree_account.height as usize;
+        let root_history_size = tree_account.root_history_size as usize;
+
+        let max_capacity = 1u64 << height;
+        require!(
+            tree_account.next_index & 1 == 0,
+            ErrorCode::MerkleTreeMisaligned
+        );
+        require!(
+            tree_account.next_index + 2 <= max_capacity,
+            ErrorCode::MerkleTreeFull
+        );
+
+        // The leaves are siblings, so the bottom level is a single hash and
+        // only the remaining levels are walked as in `append`.
+        tree_account.subtrees[0] = leaves[0];
+        let mut current_level_hash = H::hashv(&[&leaves[0], &leaves[1]]).unwrap();
+        let mut current_index = (tree_account.next_index / 2) as usize;
+        let mut left;
+        let mut right;
+        let mut proof: Vec<[u8; 32]> = vec![[0u8; 32]; height];
+        proof[0] = leaves[1];
+
+        for (i, sibling) in proof.iter_mut().enumerate().skip(1) {
+            let subtree = &mut tree_account.subtrees[i];
+            let zero_byte = H::zero_bytes()[i];
+
+            if current_index & 1 == 0 {
+                left = current_level_hash;
+                right = zero_byte;
+                *subtree = current_level_hash;
+                *sibling = right;
+            } else {
+                left = *subtree;
+                right = current_level_hash;
+                *sibling = left;
+            }
+            current_level_hash = H::hashv(&[&left, &right]).unwrap();
+            current_index /= 2;
+        }
+
+        tree_account.root = current_level_hash;
+        tree_account.next_index = tree_account.next_index
+            .checked_add(2)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let new_root_index = (tree_account.root_index as usize)
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)? % root_history_size;
+        tree_account.root_index = new_root_index as u64;
+        tree_account.root_history[new_root_index] = current_level_hash;
+
+        let mut second_proof = proof.clone();
+        second_proof[0] = leaves[0];
+        Ok([proof, second_proof])
+    }
+
+    /// Makes `root` the current root and adds it to the root history. Used for roots
+    /// computed off-chain, such as after a proven batch insertion.
+    pub fn push_root(tree_account: &mut MerkleTreeAccount, root: [u8; 32]) -> Result<()> {
+        let root_history_size = tree_account.root_history_size as usize;
+
+        tree_account.root = root;
+        let new_root_index = (tree_account.root_index as usize)
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)? % root_history_size;
+        tree_account.root_index = new_root_index as u64;
+        tree_account.root_history[new_root_index] = root;
+
+        Ok(())
+    }
+
     pub fn is_known_root(tree_account: &MerkleTreeAccount, root: [u8; 32]) -> bool {
         if root == [0u8; 32] {
             return false;
@@ -105,3 +182,118 @@ impl MerkleTree {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use light_hasher::Poseidon;
+
+    fn new_tree(height: u8) -> MerkleTreeAccount {
+        let mut tree: MerkleTreeAccount = bytemuck::Zeroable::zeroed();
+        tree.height = height;
+        tree.root_history_size = 100;
+        MerkleTree::initialize::<Poseidon>(&mut tree).unwrap();
+        tree
+    }
+
+    fn leaf(index: u64) -> [u8; 32] {
+        let mut leaf = [0u8; 32];
+        leaf[24..].copy_from_slice(&(index + 1).to_be_bytes());
+        leaf
+    }
+
+    fn root_from_path(leaf: [u8; 32], index: u64, path: &[[u8; 32]]) -> [u8; 32] {
+        let mut node = leaf;
+        for (level, sibling) in path.iter().enumerate() {
+            node = if (index >> level) & 1 == 0 {
+                Poseidon::hashv(&[&node, sibling]).unwrap()
+            } else {
+                Poseidon::hashv(&[sibling, &node]).unwrap()
+            };
+        }
+        node
+    }
+
+    fn check_append_pair_matches_append(height: u8, pairs: u64) {
+        let mut paired = new_tree(height);
+        let mut single = new_tree(height);
+
+        for pair in 0..pairs {
+            let index = pair * 2;
+            let leaves = [leaf(index), leaf(index + 1)];
+
+            let paths = MerkleTree::append_pair::<Poseidon>(leaves, &mut paired).unwrap();
+            let first = MerkleTree::append::<Poseidon>(leaves[0], &mut single).unwrap();
+            let second = MerkleTree::append::<Poseidon>(leaves[1], &mut single).unwrap();
+
+            assert_eq!(paired.root, single.root, "root after index {}", index);
+            assert_eq!(paired.subtrees, single.subtrees, "subtrees after index {}", index);
+            assert_eq!(paired.next_index, single.next_index);
+            assert!(MerkleTree::is_known_root(&paired, single.root));
+
+            // the first leaf's path from `append` still has a zero sibling in place of
+            // the second leaf, so only the levels above it carry over
+            assert_eq!(paths[0][0], leaves[1]);
+            assert_eq!(paths[0][1..], first[1..], "first path after index {}", index);
+            assert_eq!(paths[1], second, "second path after index {}", index);
+
+            assert_eq!(root_from_path(leaves[0], index, &paths[0]), paired.root);
+            assert_eq!(root_from_path(leaves[1], index + 1, &paths[1]), paired.root);
+        }
+    }
+
+    #[test]
+    fn append_pair_matches_append_until_full() {
+        for height in 1..=5 {
+            check_append_pair_matches_append(height, 1 << (height - 1));
+        }
+    }
+
+    #[test]
+    fn append_pair_matches_append_in_full_height_tree() {
+        check_append_pair_matches_append(26, 100);
+    }
+
+    #[test]
+    fn append_pair_records_one_root() {
+        let mut tree = new_tree(26);
+        MerkleTree::append_pair::<Poseidon>([leaf(0), leaf(1)], &mut tree).unwrap();
+        assert_eq!(tree.root_index, 1);
+        assert_eq!(tree.root_history[1], tree.root);
+    }
+
+    #[test]
+    fn append_pair_rejects_misaligned_and_full_trees() {
+        let mut tree = new_tree(4);
+        MerkleTree::append::<Poseidon>(leaf(0), &mut tree).unwrap();
+        assert!(MerkleTree::append_pair::<Poseidon>([leaf(1), leaf(2)], &mut tree).is_err());
+
+        let mut tree = new_tree(2);
+        MerkleTree::append_pair::<Poseidon>([leaf(0), leaf(1)], &mut tree).unwrap();
+        MerkleTree::append_pair::<Poseidon>([leaf(2), leaf(3)], &mut tree).unwrap();
+        assert!(MerkleTree::append_pair::<Poseidon>([leaf(4), leaf(5)], &mut tree).is_err());
+    }
+
+    // Roots of a height-26 tree after appending leaf(0), leaf(1), ... one at a time.
+    // circuits/test/batchInsert.test.js checks that inserting leaf(3)..leaf(10) as one
+    // batch moves the tree between the same roots.
+    const ROOT_AFTER_3: &str = "13e909f5ca5ba036819bf558a9011d322ecb2983bf5703a8cbc4717626b5547a";
+    const ROOT_AFTER_11: &str = "2168eda286fbcaa8bfba3a19078478c9a637ea78441a8a094150de6fe4df235b";
+
+    fn to_hex(bytes: &[u8; 32]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn append_matches_batch_insert_fixture() {
+        let mut tree = new_tree(26);
+        for index in 0..11 {
+            MerkleTree::append::<Poseidon>(leaf(index), &mut tree).unwrap();
+            match index {
+                2 => assert_eq!(to_hex(&tree.root), ROOT_AFTER_3),
+                10 => assert_eq!(to_hex(&tree.root), ROOT_AFTER_11),
+                _ => {}
+            }
+        }
+    }
+}
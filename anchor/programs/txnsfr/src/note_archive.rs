@@ -0,0 +1,201 @@
+use crate::state::{
+    ArchivedNote, NoteArchivePage, MAX_ARCHIVED_NOTE_LEN, MAX_ENCRYPTED_OUTPUT_LEN,
+    NOTES_PER_ARCHIVE_PAGE,
+};
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use std::cell::RefMut;
+
+pub struct NoteArchive;
+
+impl NoteArchive {
+    pub fn page_index(leaf_index: u64) -> u64 {
+        leaf_index / NOTES_PER_ARCHIVE_PAGE
+    }
+
+    pub fn initialize(
+        page: &mut NoteArchivePage,
+        shard_id: u8,
+        page_index: u64,
+        payer: Pubkey,
+        bump: u8,
+    ) {
+        page.payer = payer;
+        page.page_index = page_index;
+        page.count = 0;
+        page.filled_at = 0;
+        page.bump = bump;
+        page.shard_id = shard_id;
+    }
+
+    /// Whether this instruction just created the page, so it has no discriminator yet.
+    pub fn is_new_page(loader: &AccountLoader<NoteArchivePage>) -> Result<bool> {
+        Ok(loader.as_ref().try_borrow_data()?[..8] == [0u8; 8])
+    }
+
+    /// Loads the page receiving the outputs that start at `leaf_index`, initializing it
+    /// if this instruction just created the account.
+    pub fn load_page<'a>(
+        loader: &'a AccountLoader<NoteArchivePage>,
+        shard_id: u8,
+        leaf_index: u64,
+        payer: Pubkey,
+        bump: u8,
+    ) -> Result<RefMut<'a, NoteArchivePage>> {
+        if !Self::is_new_page(loader)? {
+            return loader.load_mut();
+        }
+
+        let mut page = loader.load_init()?;
+        Self::initialize(&mut page, shard_id, Self::page_index(leaf_index), payer, bump);
+        Ok(page)
+    }
+
+    pub fn record(
+        page: &mut NoteArchivePage,
+        leaf_index: u64,
+        commitment: [u8; 32],
+        encrypted_output: &[u8],
+        current_slot: u64,
+    ) -> Result<()> {
+        require!(
+            Self::page_index(leaf_index) == page.page_index,
+            ErrorCode::InvalidNoteArchivePage
+        );
+        require!(
+            encrypted_output.len() <= MAX_ENCRYPTED_OUTPUT_LEN,
+            ErrorCode::EncryptedOutputTooLarge
+        );
+
+        // Notes too long for a slot keep their commitment and length only; wallets
+        // fetch their ciphertext from the CommitmentData event instead
+        let slot = (leaf_index % NOTES_PER_ARCHIVE_PAGE) as usize;
+        let mut note = [0u8; MAX_ARCHIVED_NOTE_LEN];
+        if encrypted_output.len() <= MAX_ARCHIVED_NOTE_LEN {
+            note[..encrypted_output.len()].copy_from_slice(encrypted_output);
+        }
+
+        page.notes[slot] = ArchivedNote {
+            commitment,
+            note,
+            note_len: encrypted_output.len() as u16,
+        };
+        page.count = page.count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        if page.count == NOTES_PER_ARCHIVE_PAGE {
+            page.filled_at = current_slot;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_page(page_index: u64) -> Box<NoteArchivePage> {
+        let mut page: Box<NoteArchivePage> = Box::new(bytemuck::Zeroable::zeroed());
+        NoteArchive::initialize(&mut page, 0, page_index, Pubkey::new_unique(), 255);
+        page
+    }
+
+    fn commitment(leaf_index: u64) -> [u8; 32] {
+        let mut commitment = [0u8; 32];
+        commitment[24..].copy_from_slice(&leaf_index.to_be_bytes());
+        commitment
+    }
+
+    #[test]
+    fn record_stores_notes_at_their_index() {
+        let mut page = new_page(2);
+        let first = 2 * NOTES_PER_ARCHIVE_PAGE;
+        NoteArchive::record(&mut page, first + 6, commitment(first + 6), &[1, 2, 3], 10).unwrap();
+
+        let note = &page.notes[6];
+        assert_eq!(note.commitment, commitment(first + 6));
+        assert_eq!(note.note_len, 3);
+        assert_eq!(&note.note[..4], &[1, 2, 3, 0]);
+        assert_eq!(page.count, 1);
+        assert_eq!(page.filled_at, 0);
+    }
+
+    #[test]
+    fn record_marks_the_slot_a_page_fills_at() {
+        let mut page = new_page(0);
+        for leaf_index in 0..NOTES_PER_ARCHIVE_PAGE {
+            NoteArchive::record(&mut page, leaf_index, commitment(leaf_index), &[0; 8], 100 + leaf_index).unwrap();
+        }
+        assert_eq!(page.count, NOTES_PER_ARCHIVE_PAGE);
+        assert_eq!(page.filled_at, 100 + NOTES_PER_ARCHIVE_PAGE - 1);
+    }
+
+    #[test]
+    fn record_rejects_a_leaf_from_another_page() {
+        let mut page = new_page(1);
+        assert!(NoteArchive::record(&mut page, 0, commitment(0), &[], 0).is_err());
+        assert!(NoteArchive::record(&mut page, 2 * NOTES_PER_ARCHIVE_PAGE, commitment(0), &[], 0).is_err());
+    }
+
+    #[test]
+    fn record_keeps_only_the_commitment_of_oversized_notes() {
+        let mut page = new_page(0);
+        let note = vec![7u8; MAX_ARCHIVED_NOTE_LEN + 1];
+        NoteArchive::record(&mut page, 1, commitment(1), &note, 0).unwrap();
+
+        assert_eq!(page.notes[1].commitment, commitment(1));
+        assert_eq!(page.notes[1].note_len as usize, MAX_ARCHIVED_NOTE_LEN + 1);
+        assert!(page.notes[1].note.iter().all(|&byte| byte == 0));
+
+        let too_long = vec![7u8; MAX_ENCRYPTED_OUTPUT_LEN + 1];
+        assert!(NoteArchive::record(&mut page, 2, commitment(2), &too_long, 0).is_err());
+    }
+
+    #[test]
+    fn a_page_created_without_outputs_still_takes_the_next_ones() {
+        // a page `init_if_needed` just created, as on a queued tree at leaf 64 whose
+        // transact dropped its zero-note outputs
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; 8 + std::mem::size_of::<NoteArchivePage>()];
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        let leaf_index = 2 * NOTES_PER_ARCHIVE_PAGE;
+
+        let created = AccountLoader::<NoteArchivePage>::try_from_unchecked(&crate::ID, &info).unwrap();
+        drop(NoteArchive::load_page(&created, 0, leaf_index, key, 255).unwrap());
+        created.exit(&crate::ID).unwrap();
+
+        // the next deposit's outputs land on the same page
+        let page = AccountLoader::<NoteArchivePage>::try_from(&info).unwrap();
+        let mut page = NoteArchive::load_page(&page, 0, leaf_index, Pubkey::new_unique(), 255).unwrap();
+        assert_eq!(page.page_index, 2);
+        assert_eq!(page.payer, key);
+        NoteArchive::record(&mut page, leaf_index, commitment(leaf_index), &[1], 0).unwrap();
+        NoteArchive::record(&mut page, leaf_index + 1, commitment(leaf_index + 1), &[2], 0).unwrap();
+        assert_eq!(page.count, 2);
+    }
+
+    #[test]
+    fn a_page_left_uninitialized_takes_no_outputs() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; 8 + std::mem::size_of::<NoteArchivePage>()];
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        let leaf_index = 2 * NOTES_PER_ARCHIVE_PAGE;
+
+        // Anchor writes the discriminator on exit whether or not the page was initialized
+        AccountLoader::<NoteArchivePage>::try_from_unchecked(&crate::ID, &info)
+            .unwrap()
+            .exit(&crate::ID)
+            .unwrap();
+
+        let page = AccountLoader::<NoteArchivePage>::try_from(&info).unwrap();
+        let mut page = NoteArchive::load_page(&page, 0, leaf_index, key, 255).unwrap();
+        assert_eq!(
+            NoteArchive::record(&mut page, leaf_index, commitment(leaf_index), &[1], 0).unwrap_err(),
+            error!(ErrorCode::InvalidNoteArchivePage)
+        );
+    }
+}
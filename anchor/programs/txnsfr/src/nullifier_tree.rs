@@ -0,0 +1,293 @@
+use crate::state::{NullifierTreeAccount, NULLIFIER_TREE_HEIGHT};
+use crate::utils;
+use crate::{ErrorCode, NullifierInsertProof, NullifierLeaf};
+use anchor_lang::prelude::*;
+use light_hasher::Hasher;
+
+pub struct NullifierTree;
+
+impl NullifierTree {
+    /// Starts the tree with the low sentinel at index 0: value 0, linking to nothing.
+    pub fn initialize<H: Hasher>(tree: &mut NullifierTreeAccount) -> Result<()> {
+        let sentinel = NullifierLeaf {
+            value: [0u8; 32],
+            next_index: 0,
+            next_value: [0u8; 32],
+        };
+        let zero_bytes = H::zero_bytes();
+        let mut path = [[0u8; 32]; NULLIFIER_TREE_HEIGHT];
+        path.copy_from_slice(&zero_bytes[..NULLIFIER_TREE_HEIGHT]);
+
+        let (_, root) = Self::update::<H>([0u8; 32], Self::hash_leaf::<H>(&sentinel)?, 0, &path)?;
+        tree.root = root;
+        tree.next_index = 1;
+        Ok(())
+    }
+
+    /// Inserts `nullifier`, failing unless `proof` shows it is not in the tree yet.
+    ///
+    /// The proof's low leaf must be in the tree and link over the nullifier; it is
+    /// relinked to the nullifier, which takes over the low leaf's old link in the next
+    /// empty leaf. Both paths are checked against the current root, so a proof built
+    /// before another spend changed the tree has to be rebuilt.
+    pub fn insert<H: Hasher>(
+        tree: &mut NullifierTreeAccount,
+        nullifier: [u8; 32],
+        proof: &NullifierInsertProof,
+    ) -> Result<()> {
+        let low_leaf = &proof.low_leaf;
+
+        // Values are big-endian field elements, so byte order is numeric order;
+        // a zero `next_value` marks the end of the list
+        require!(
+            low_leaf.value < nullifier
+                && (low_leaf.next_value == [0u8; 32] || nullifier < low_leaf.next_value),
+            ErrorCode::InvalidNullifierProof
+        );
+        require!(
+            proof.low_leaf_index < tree.next_index,
+            ErrorCode::InvalidNullifierProof
+        );
+        require!(
+            tree.next_index < 1u64 << NULLIFIER_TREE_HEIGHT,
+            ErrorCode::MerkleTreeFull
+        );
+
+        let relinked_low_leaf = NullifierLeaf {
+            value: low_leaf.value,
+            next_index: tree.next_index,
+            next_value: nullifier,
+        };
+        let (old_root, relinked_root) = Self::update::<H>(
+            Self::hash_leaf::<H>(low_leaf)?,
+            Self::hash_leaf::<H>(&relinked_low_leaf)?,
+            proof.low_leaf_index,
+            &proof.low_leaf_path,
+        )?;
+        require!(old_root == tree.root, ErrorCode::InvalidNullifierProof);
+
+        let new_leaf = NullifierLeaf {
+            value: nullifier,
+            next_index: low_leaf.next_index,
+            next_value: low_leaf.next_value,
+        };
+        let (empty_root, new_root) = Self::update::<H>(
+            [0u8; 32],
+            Self::hash_leaf::<H>(&new_leaf)?,
+            tree.next_index,
+            &proof.new_leaf_path,
+        )?;
+        require!(empty_root == relinked_root, ErrorCode::InvalidNullifierProof);
+
+        tree.root = new_root;
+        tree.next_index += 1;
+        Ok(())
+    }
+
+    pub fn hash_leaf<H: Hasher>(leaf: &NullifierLeaf) -> Result<[u8; 32]> {
+        H::hashv(&[
+            &leaf.value,
+            &utils::u64_to_field(leaf.next_index),
+            &leaf.next_value,
+        ])
+        .map_err(|_| error!(ErrorCode::InvalidNullifierProof))
+    }
+
+    /// Roots of the tree with `old_leaf` and with `new_leaf` at `index`, given the
+    /// siblings on the leaf's path.
+    fn update<H: Hasher>(
+        old_leaf: [u8; 32],
+        new_leaf: [u8; 32],
+        index: u64,
+        path: &[[u8; 32]; NULLIFIER_TREE_HEIGHT],
+    ) -> Result<([u8; 32], [u8; 32])> {
+        let mut old_node = old_leaf;
+        let mut new_node = new_leaf;
+        for (level, sibling) in path.iter().enumerate() {
+            let hash = |node: &[u8; 32]| {
+                if (index >> level) & 1 == 0 {
+                    H::hashv(&[node, sibling])
+                } else {
+                    H::hashv(&[sibling, node])
+                }
+                .map_err(|_| error!(ErrorCode::InvalidNullifierProof))
+            };
+            old_node = hash(&old_node)?;
+            new_node = hash(&new_node)?;
+        }
+        Ok((old_node, new_node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use light_hasher::Poseidon;
+
+    /// Off-chain copy of the tree, as a client keeps it to build insertion proofs.
+    struct ClientTree {
+        leaves: Vec<NullifierLeaf>,
+    }
+
+    impl ClientTree {
+        fn new() -> Self {
+            Self {
+                leaves: vec![NullifierLeaf {
+                    value: [0u8; 32],
+                    next_index: 0,
+                    next_value: [0u8; 32],
+                }],
+            }
+        }
+
+        fn path(&self, index: u64) -> [[u8; 32]; NULLIFIER_TREE_HEIGHT] {
+            let zero_bytes = Poseidon::zero_bytes();
+            let mut layer: Vec<[u8; 32]> = self
+                .leaves
+                .iter()
+                .map(|leaf| NullifierTree::hash_leaf::<Poseidon>(leaf).unwrap())
+                .collect();
+            let mut path = [[0u8; 32]; NULLIFIER_TREE_HEIGHT];
+            let mut index = index as usize;
+            for (level, sibling) in path.iter_mut().enumerate() {
+                *sibling = *layer.get(index ^ 1).unwrap_or(&zero_bytes[level]);
+                layer = layer
+                    .chunks(2)
+                    .map(|pair| {
+                        let right = pair.get(1).unwrap_or(&zero_bytes[level]);
+                        Poseidon::hashv(&[&pair[0], right]).unwrap()
+                    })
+                    .collect();
+                index /= 2;
+            }
+            path
+        }
+
+        /// Proof for inserting `nullifier`, linked over by the leaf at `low_leaf_index`.
+        fn proof_with_low_leaf(&self, nullifier: [u8; 32], low_leaf_index: usize) -> NullifierInsertProof {
+            let low_leaf = self.leaves[low_leaf_index].clone();
+            let mut relinked = Self {
+                leaves: self.leaves.clone(),
+            };
+            relinked.leaves[low_leaf_index] = NullifierLeaf {
+                value: low_leaf.value,
+                next_index: self.leaves.len() as u64,
+                next_value: nullifier,
+            };
+
+            NullifierInsertProof {
+                low_leaf,
+                low_leaf_index: low_leaf_index as u64,
+                low_leaf_path: self.path(low_leaf_index as u64),
+                new_leaf_path: relinked.path(self.leaves.len() as u64),
+            }
+        }
+
+        fn low_leaf_index(&self, nullifier: [u8; 32]) -> usize {
+            self.leaves
+                .iter()
+                .position(|leaf| {
+                    leaf.value < nullifier && (leaf.next_value == [0u8; 32] || nullifier < leaf.next_value)
+                })
+                .expect("nullifier is not in the tree")
+        }
+
+        fn proof(&self, nullifier: [u8; 32]) -> NullifierInsertProof {
+            self.proof_with_low_leaf(nullifier, self.low_leaf_index(nullifier))
+        }
+
+        fn insert(&mut self, nullifier: [u8; 32]) {
+            let low = self.low_leaf_index(nullifier);
+            let new_leaf = NullifierLeaf {
+                value: nullifier,
+                next_index: self.leaves[low].next_index,
+                next_value: self.leaves[low].next_value,
+            };
+            self.leaves[low].next_index = self.leaves.len() as u64;
+            self.leaves[low].next_value = nullifier;
+            self.leaves.push(new_leaf);
+        }
+
+        fn root(&self) -> [u8; 32] {
+            let leaf = NullifierTree::hash_leaf::<Poseidon>(&self.leaves[0]).unwrap();
+            let (root, _) = NullifierTree::update::<Poseidon>(leaf, leaf, 0, &self.path(0)).unwrap();
+            root
+        }
+    }
+
+    fn nullifier(value: u8) -> [u8; 32] {
+        let mut nullifier = [0u8; 32];
+        nullifier[1] = value;
+        nullifier
+    }
+
+    fn new_tree() -> NullifierTreeAccount {
+        let mut tree: NullifierTreeAccount = bytemuck::Zeroable::zeroed();
+        NullifierTree::initialize::<Poseidon>(&mut tree).unwrap();
+        tree
+    }
+
+    #[test]
+    fn inserts_nullifiers_in_any_order() {
+        let mut tree = new_tree();
+        let mut client = ClientTree::new();
+        assert_eq!(tree.root, client.root());
+
+        // in the middle, at the end and at the front of the sorted list
+        for value in [50, 90, 10, 70, 20] {
+            let proof = client.proof(nullifier(value));
+            NullifierTree::insert::<Poseidon>(&mut tree, nullifier(value), &proof).unwrap();
+            client.insert(nullifier(value));
+            assert_eq!(tree.root, client.root());
+        }
+        assert_eq!(tree.next_index, 6);
+    }
+
+    #[test]
+    fn rejects_a_spent_nullifier() {
+        let mut tree = new_tree();
+        let mut client = ClientTree::new();
+        for value in [30, 60] {
+            let proof = client.proof(nullifier(value));
+            NullifierTree::insert::<Poseidon>(&mut tree, nullifier(value), &proof).unwrap();
+            client.insert(nullifier(value));
+        }
+
+        // no leaf links over a spent nullifier: neither the leaf holding it nor its
+        // predecessor, which now links to it
+        let holder = client.leaves.iter().position(|leaf| leaf.value == nullifier(30)).unwrap();
+        let predecessor = 0;
+        for low_leaf_index in [holder, predecessor] {
+            let proof = client.proof_with_low_leaf(nullifier(30), low_leaf_index);
+            assert!(NullifierTree::insert::<Poseidon>(&mut tree, nullifier(30), &proof).is_err());
+        }
+
+        // a made-up low leaf that would link over it is not in the tree
+        let mut forged = client.proof_with_low_leaf(nullifier(30), 0);
+        forged.low_leaf.next_value = nullifier(40);
+        assert!(NullifierTree::insert::<Poseidon>(&mut tree, nullifier(30), &forged).is_err());
+        assert_eq!(tree.root, client.root());
+    }
+
+    #[test]
+    fn rejects_proofs_made_before_the_tree_changed() {
+        let mut tree = new_tree();
+        let client = ClientTree::new();
+        let first = client.proof(nullifier(10));
+        let second = client.proof(nullifier(20));
+
+        NullifierTree::insert::<Poseidon>(&mut tree, nullifier(10), &first).unwrap();
+        assert!(NullifierTree::insert::<Poseidon>(&mut tree, nullifier(20), &second).is_err());
+    }
+
+    #[test]
+    fn rejects_the_zero_nullifier_and_a_wrong_empty_leaf() {
+        let mut tree = new_tree();
+        let client = ClientTree::new();
+        assert!(NullifierTree::insert::<Poseidon>(&mut tree, [0u8; 32], &client.proof_with_low_leaf([0u8; 32], 0)).is_err());
+
+        let mut proof = client.proof(nullifier(10));
+        proof.new_leaf_path[0] = [1u8; 32];
+        assert!(NullifierTree::insert::<Poseidon>(&mut tree, nullifier(10), &proof).is_err());
+    }
+}
@@ -2,6 +2,38 @@ use anchor_lang::prelude::*;
 
 // Constants
 pub const MERKLE_TREE_HEIGHT: u8 = 26;
+pub const NOTES_PER_ARCHIVE_PAGE: u64 = 32;
+pub const MAX_ARCHIVED_NOTE_LEN: usize = 256; // longer outputs are archived without their ciphertext
+pub const MAX_ENCRYPTED_OUTPUT_LEN: usize = 1024;
+pub const MAX_ENCRYPTED_OUTPUT_LENGTHS: usize = 4;
+pub const DEFAULT_ENCRYPTED_OUTPUT_LEN: u16 = MAX_ARCHIVED_NOTE_LEN as u16; // every note fits in the archive
+pub const DENYLIST_TREE_HEIGHT: usize = 20; // up to ~1M denied addresses
+pub const MAX_VERIFYING_KEY_IC_LEN: usize = 16;
+pub const OUTFLOW_WINDOW_BUCKETS: usize = 8; // the outflow window slides in steps of 1/8 of its length
+pub const MAX_TREE_SHARDS: u8 = 16; // shard 0 is the main tree; shards are 1..=MAX_TREE_SHARDS
+pub const COMMITMENT_QUEUE_CAPACITY: usize = 256;
+pub const COMMITMENT_BATCH_SIZE: usize = 8; // leaves inserted per batch proof
+pub const NULLIFIER_TREE_HEIGHT: usize = 26;
+// Borsh-encoded NullifierInsertProof: low leaf (32 + 8 + 32), its index (8) and two paths
+pub const NULLIFIER_INSERT_PROOF_LEN: usize = 72 + 8 + 2 * 32 * NULLIFIER_TREE_HEIGHT;
+// Borsh-encoded BufferedTransact: Proof (480) + ExtDataMinified (24) + two length-prefixed
+// encrypted outputs of up to MAX_ENCRYPTED_OUTPUT_LEN bytes each + an optional
+// TimelockWindow (1 + 16) + up to MAX_WITHDRAWAL_PAYOUTS length-prefixed payout amounts,
+// followed by a NullifierInsertProof per input when spending through the nullifier tree
+pub const MAX_PROOF_BUFFER_LEN: usize = 480
+    + 24
+    + 2 * (4 + MAX_ENCRYPTED_OUTPUT_LEN)
+    + (1 + 16)
+    + 4
+    + 8 * crate::MAX_WITHDRAWAL_PAYOUTS
+    + 2 * NULLIFIER_INSERT_PROOF_LEN;
+
+// Circuits whose verifying keys are registered on-chain after their trusted setup
+pub const FILE_CLAIM_CIRCUIT: u8 = 1;
+pub const FILE_PURCHASE_CIRCUIT: u8 = 2;
+pub const TIMELOCK_TRANSACTION_CIRCUIT: u8 = 3;
+pub const BATCH_INSERT_CIRCUIT: u8 = 4;
+pub const ASSOCIATION_TRANSACTION_CIRCUIT: u8 = 5;
 
 #[account]
 pub struct TreeTokenAccount {
@@ -9,15 +41,137 @@ pub struct TreeTokenAccount {
     pub bump: u8,
 }
 
+/// State of the rolling outflow circuit breaker for one pool: lamports that left the
+/// pool in each of the last OUTFLOW_WINDOW_BUCKETS slices of the window.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct OutflowWindow {
+    pub bucket_slots: u64,  // slots per bucket when the buckets were filled (0 = empty)
+    pub newest_bucket: u64, // slot / bucket_slots of the latest outflow
+    pub buckets: [u64; OUTFLOW_WINDOW_BUCKETS], // outflow per bucket, indexed by bucket % OUTFLOW_WINDOW_BUCKETS
+}
+
+/// Vault of a tree shard. Holds the shard's lamports along with the state a
+/// transaction would otherwise write to the shared global config and fee vault,
+/// so transactions on different shards never take the same write lock.
+#[account]
+#[derive(InitSpace)]
+pub struct ShardVaultAccount {
+    pub shard_id: u8,
+    pub outflow_window: OutflowWindow,
+    pub pending_protocol_fees: u64, // protocol fees held here until swept to the fee vault
+    pub bump: u8,
+}
+
 #[account]
 pub struct GlobalConfig {
     pub authority: Pubkey,
-    pub deposit_fee_rate: u16,    // basis points (0-10000, where 10000 = 100%)
-    pub withdrawal_fee_rate: u16, // basis points (0-10000, where 10000 = 100%)
-    pub fee_error_margin: u16,    // basis points (0-10000, where 10000 = 100%)
+    pub deposit_fee_rate: u16,       // basis points (0-10000, where 10000 = 100%)
+    pub withdrawal_fee_rate: u16,    // basis points (0-10000, where 10000 = 100%)
+    pub fee_error_margin: u16,       // basis points (0-10000, where 10000 = 100%)
+    pub bump: u8,
+    pub max_outflow_rate: u16,       // basis points of the pool balance that may leave per window
+    pub outflow_window_slots: u64,   // length of the rolling outflow window, in slots
+    pub outflow_window: OutflowWindow, // outflow of the main tree over the rolling window
+    pub guardian: Pubkey,            // may enable or disable withdraw-only mode alongside the authority
+    pub withdraw_only_until: u64,    // slot until which only withdrawals are accepted (0 = inactive)
+    pub pending_authority: Pubkey,   // proposed new authority for the tree, vault and config (default = none)
+    pub pending_authority_after: u64, // slot from which the pending authority may accept
+    pub require_registered_relayer: bool, // transact fees may only go to registered, active relayers
+    pub min_relayer_stake: u64,      // lamports a relayer must stake to register, and keep staked to relay
+    pub max_relayer_transfer_fee: u64, // lamports a registered relayer may charge on a transfer, which has no ext_amount to take its rate of
+    pub note_archive_retention_slots: u64, // slots a full archive page is kept before it may be closed (0 = forever)
+    pub encrypted_output_lengths: [u16; MAX_ENCRYPTED_OUTPUT_LENGTHS], // allowed ciphertext lengths (0 = unused)
+    pub screening_authority: Pubkey, // publishes the deposit denylist's Merkle root
+    pub deposit_screening_enabled: bool, // deposits need a clearance of the signer against the current denylist root
+    pub nullifier_tree_enabled: bool, // main tree spends record nullifiers in the nullifier tree instead of PDAs; shard spends keep PDAs
+    pub file_registration_fee: u64,  // lamports register_file pays into the fee vault
+}
+
+#[account]
+pub struct FeeVaultAccount {
+    pub total_accrued: u64, // protocol fees ever received from transact, in lamports
+    pub total_claimed: u64, // protocol fees ever paid out through claim_fees, in lamports
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RelayerAccount {
+    pub authority: Pubkey,  // relayer wallet; also the fee_recipient_account it relays with
+    pub stake: u64,         // lamports staked, held in this account on top of rent
+    pub fee_rate: u16,      // advertised relayer fee, basis points (0-10000, where 10000 = 100%)
+    #[max_len(128)]
+    pub endpoint: String,   // URL clients submit relay requests to
+    pub jailed: bool,       // set when slashed; a jailed relayer cannot relay or withdraw
+    pub unbond_after: u64,  // slot after which the stake can be withdrawn (0 = active)
+    pub bump: u8,
+}
+
+/// Groth16 verifying key for a circuit that is not compiled into the program.
+/// Written once by the authority after the circuit's trusted setup and never changed.
+#[account]
+#[derive(InitSpace)]
+pub struct VerifyingKeyAccount {
+    pub circuit: u8,
+    pub vk_alpha_g1: [u8; 64],
+    pub vk_beta_g2: [u8; 128],
+    pub vk_gamma_g2: [u8; 128],
+    pub vk_delta_g2: [u8; 128],
+    #[max_len(MAX_VERIFYING_KEY_IC_LEN)]
+    pub vk_ic: Vec<[u8; 64]>,
     pub bump: u8,
 }
 
+/// Terms for buying access to a file on Irys with a shielded payment.
+/// Listings carry no wallet address; the seller is paid to a shielded key.
+#[account]
+#[derive(InitSpace)]
+pub struct FileListing {
+    pub lister: Pubkey,          // signer of list_file; part of the listing's address
+    pub irys_tx_id: [u8; 32],
+    pub content_hash: [u8; 32],
+    pub seller_pubkey: [u8; 32], // shielded public key the payment note is created for
+    pub price: u64,              // lamports paid into the seller's note
+    pub bump: u8,
+}
+
+/// Staging area for transact arguments too large for a single transaction.
+/// Filled by its authority over several `write_proof_buffer` calls, then consumed
+/// and closed by `execute_from_buffer`.
+#[account]
+#[derive(InitSpace)]
+pub struct ProofBuffer {
+    pub authority: Pubkey,
+    #[max_len(MAX_PROOF_BUFFER_LEN)]
+    pub data: Vec<u8>,      // Borsh-encoded BufferedTransact
+    pub bump: u8,
+}
+
+impl ProofBuffer {
+    // Offset of Proof::input_nullifiers: proof_a, proof_b, proof_c, root, public_amount, ext_data_hash
+    const INPUT_NULLIFIERS_OFFSET: usize = 64 + 128 + 64 + 32 * 3;
+
+    /// Input nullifier `index` of the staged proof, or zeroes if it has not been written yet.
+    pub fn input_nullifier(&self, index: usize) -> [u8; 32] {
+        let start = Self::INPUT_NULLIFIERS_OFFSET + index * 32;
+        self.data
+            .get(start..start + 32)
+            .and_then(|bytes| bytes.try_into().ok())
+            .unwrap_or([0u8; 32])
+    }
+}
+
+/// Indexed Merkle tree of the nullifiers spent against the main tree, once enabled.
+/// Each leaf is `Poseidon(value, next_index, next_value)`, linking the leaves into a list
+/// sorted by value, so a leaf that links over a nullifier proves it unspent.
+#[account(zero_copy)]
+pub struct NullifierTreeAccount {
+    pub root: [u8; 32],
+    pub next_index: u64, // index of the next empty leaf
+    pub bump: u8,
+    pub _padding: [u8; 7],
+}
+
 #[account]
 pub struct NullifierAccount {
     /// This account's existence indicates that the nullifier has been used.
@@ -37,6 +191,181 @@ pub struct MerkleTreeAccount {
     pub height: u8,
     pub root_history_size: u8,
     pub bump: u8,
-    // The pub _padding: [u8; 5] is needed because of the #[account(zero_copy)] attribute.
-    pub _padding: [u8; 5],
+    pub queue_enabled: u8,  // outputs go through the commitment queue; `subtrees` is no longer maintained
+    // The pub _padding: [u8; 4] is needed because of the #[account(zero_copy)] attribute.
+    pub _padding: [u8; 4],
+}
+
+/// Commitments assigned a leaf index but not yet hashed into their tree. Leaves are
+/// taken off the front in batches by `insert_commitment_batch`, in index order.
+#[account(zero_copy)]
+pub struct CommitmentQueueAccount {
+    pub next_insert_index: u64, // leaf index of the commitment at the front of the queue
+    pub head: u64,              // position of the front of the queue in `leaves`
+    pub count: u64,
+    pub leaves: [[u8; 32]; COMMITMENT_QUEUE_CAPACITY],
+    pub shard_id: u8,
+    pub bump: u8,
+    pub _padding: [u8; 6],
+}
+
+#[zero_copy]
+pub struct ArchivedNote {
+    pub commitment: [u8; 32],
+    pub note: [u8; MAX_ARCHIVED_NOTE_LEN],
+    pub note_len: u16,    // above MAX_ARCHIVED_NOTE_LEN the note is only in its CommitmentData event
+}
+
+/// A page of the on-chain note archive, holding the commitments and encrypted outputs
+/// of leaves `page_index * NOTES_PER_ARCHIVE_PAGE` up to the next page.
+#[account(zero_copy)]
+pub struct NoteArchivePage {
+    pub payer: Pubkey,    // receives the rent back if the page is ever closed; the fee vault when it paid
+    pub page_index: u64,
+    pub count: u64,
+    pub filled_at: u64,   // slot at which the page became full (0 while not full)
+    pub notes: [ArchivedNote; NOTES_PER_ARCHIVE_PAGE as usize],
+    pub bump: u8,
+    pub shard_id: u8,     // tree shard the page archives (0 = main tree)
+    pub _padding: [u8; 6],
+}
+
+/// Root of the deposit denylist, whose addresses the screening authority publishes
+/// off-chain. See `Denylist` for how the tree is built.
+#[account]
+#[derive(InitSpace)]
+pub struct DenylistAccount {
+    pub root: [u8; 32],
+    pub count: u64, // denied addresses under `root`
+    pub bump: u8,
+}
+
+/// Record that `depositor` proved it is not on the denylist with root `denylist_root`.
+/// Deposits accept it for as long as that is still the denylist's root.
+#[account]
+#[derive(InitSpace)]
+pub struct DepositorClearance {
+    pub depositor: Pubkey,
+    pub denylist_root: [u8; 32],
+    pub bump: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BufferedNullifierTreeTransact, BufferedTransact, ExtDataMinified, NullifierInsertProof, NullifierLeaf,
+        Proof, TimelockWindow, MAX_WITHDRAWAL_PAYOUTS,
+    };
+
+    fn staged(note_len: usize, timelock_window: Option<TimelockWindow>, payouts: usize) -> BufferedTransact {
+        BufferedTransact {
+            proof: Proof {
+                proof_a: [1; 64],
+                proof_b: [2; 128],
+                proof_c: [3; 64],
+                root: [4; 32],
+                public_amount: [5; 32],
+                ext_data_hash: [6; 32],
+                input_nullifiers: [[7; 32], [8; 32]],
+                output_commitments: [[9; 32], [10; 32]],
+            },
+            ext_data_minified: ExtDataMinified {
+                ext_amount: -1,
+                fee: 2,
+                protocol_fee: 3,
+            },
+            encrypted_output1: vec![11; note_len],
+            encrypted_output2: vec![12; note_len],
+            timelock_window,
+            payout_amounts: vec![13; payouts],
+        }
+    }
+
+    fn insert_proof() -> NullifierInsertProof {
+        NullifierInsertProof {
+            low_leaf: NullifierLeaf {
+                value: [14; 32],
+                next_index: 15,
+                next_value: [16; 32],
+            },
+            low_leaf_index: 17,
+            low_leaf_path: [[18; 32]; NULLIFIER_TREE_HEIGHT],
+            new_leaf_path: [[19; 32]; NULLIFIER_TREE_HEIGHT],
+        }
+    }
+
+    #[test]
+    fn proof_buffer_fits_the_largest_staged_transaction() {
+        let largest = BufferedNullifierTreeTransact {
+            transact: staged(
+                MAX_ENCRYPTED_OUTPUT_LEN,
+                Some(TimelockWindow {
+                    min_slot: 1,
+                    max_slot: 2,
+                }),
+                MAX_WITHDRAWAL_PAYOUTS,
+            ),
+            nullifier_proofs: [insert_proof(), insert_proof()],
+        };
+        assert_eq!(insert_proof().try_to_vec().unwrap().len(), NULLIFIER_INSERT_PROOF_LEN);
+        assert_eq!(largest.try_to_vec().unwrap().len(), MAX_PROOF_BUFFER_LEN);
+    }
+
+    #[test]
+    fn proof_buffer_reads_nullifiers_of_a_nullifier_tree_transaction() {
+        let staged = BufferedNullifierTreeTransact {
+            transact: staged(100, None, 0),
+            nullifier_proofs: [insert_proof(), insert_proof()],
+        };
+        let buffer = ProofBuffer {
+            authority: Pubkey::default(),
+            data: staged.try_to_vec().unwrap(),
+            bump: 0,
+        };
+        assert_eq!(buffer.input_nullifier(0), [7; 32]);
+        assert_eq!(buffer.input_nullifier(1), [8; 32]);
+
+        // each kind of staged transaction only decodes as itself
+        assert!(BufferedTransact::try_from_slice(&buffer.data).is_err());
+        let plain = staged.transact.try_to_vec().unwrap();
+        assert!(BufferedNullifierTreeTransact::try_from_slice(&plain).is_err());
+    }
+
+    #[test]
+    fn proof_buffer_reads_nullifiers_of_the_staged_proof() {
+        let mut buffer = ProofBuffer {
+            authority: Pubkey::default(),
+            data: staged(MAX_ARCHIVED_NOTE_LEN + 100, None, 3).try_to_vec().unwrap(),
+            bump: 0,
+        };
+        assert_eq!(buffer.input_nullifier(0), [7; 32]);
+        assert_eq!(buffer.input_nullifier(1), [8; 32]);
+
+        // a buffer that is still being written reads as zeroes
+        buffer.data.truncate(ProofBuffer::INPUT_NULLIFIERS_OFFSET + 40);
+        assert_eq!(buffer.input_nullifier(0), [7; 32]);
+        assert_eq!(buffer.input_nullifier(1), [0; 32]);
+    }
+
+    #[test]
+    fn staged_transaction_roundtrips_through_the_buffer() {
+        let window = TimelockWindow {
+            min_slot: 500,
+            max_slot: 900,
+        };
+        let bytes = staged(300, Some(window), 4).try_to_vec().unwrap();
+        let decoded = BufferedTransact::try_from_slice(&bytes).unwrap();
+
+        assert_eq!(decoded.encrypted_output1.len(), 300);
+        assert_eq!(decoded.payout_amounts, vec![13; 4]);
+        let decoded_window = decoded.timelock_window.unwrap();
+        assert_eq!((decoded_window.min_slot, decoded_window.max_slot), (500, 900));
+
+        // trailing garbage or a truncated buffer is not a valid staged transaction
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(BufferedTransact::try_from_slice(&padded).is_err());
+        assert!(BufferedTransact::try_from_slice(&bytes[..bytes.len() - 1]).is_err());
+    }
 }
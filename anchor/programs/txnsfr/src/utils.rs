@@ -1,11 +1,13 @@
-use crate::Proof;
+use crate::{Payout, Proof, TimelockWindow};
+use crate::state::{GlobalConfig, OutflowWindow, RelayerAccount, VerifyingKeyAccount, MAX_ENCRYPTED_OUTPUT_LEN, OUTFLOW_WINDOW_BUCKETS};
 use crate::groth16::{Groth16Verifier, Groth16Verifyingkey};
 use crate::ErrorCode;
 use ark_bn254;
+use light_hasher::{Hasher, Poseidon};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress, Validate};
 use std::ops::Neg;
 use ark_bn254::Fr;
-use ark_ff::PrimeField;
+use ark_ff::{BigInteger, PrimeField};
 use anchor_lang::prelude::*;
 use solana_program::hash::hash;
 
@@ -128,7 +130,8 @@ pub fn check_public_amount(ext_amount: i64, fee: u64, public_amount_bytes: [u8;
 }
 
 /**
- * Validates that the provided fee meets the minimum required fee based on global configuration.
+ * Validates that the provided protocol fee meets the minimum required fee based on global configuration.
+ * The relayer fee is set by the relayer and is not checked here.
  * 
  * For deposits (ext_amount > 0):
  * - expected_fee = (ext_amount * deposit_fee_rate) / 10000
@@ -211,6 +214,247 @@ pub fn validate_fee(
     Ok(())
 }
 
+/**
+ * Checks that a registered relayer may relay: it is not jailed or unbonding, and its
+ * stake, which slashing lowers, still meets the current minimum.
+ *
+ * @param relayer The relayer's registry entry
+ * @param min_stake The minimum relayer stake from the global config
+ * @return Ok(()) if the relayer may relay, the reason it may not otherwise
+ */
+pub fn check_relayer_active(relayer: &RelayerAccount, min_stake: u64) -> Result<()> {
+    require!(!relayer.jailed, ErrorCode::RelayerJailed);
+    require!(relayer.unbond_after == 0, ErrorCode::RelayerNotRegistered);
+    require!(relayer.stake >= min_stake, ErrorCode::InsufficientRelayerStake);
+    Ok(())
+}
+
+/**
+ * Checks a relayer fee against the fee rate the relayer registered, so a relayer can't
+ * charge more than it advertises. A transfer moves nothing in or out, so its fee is
+ * capped at a flat amount instead.
+ *
+ * @param fee The relayer fee from the external data
+ * @param ext_amount The external amount; the rate applies to its absolute value
+ * @param fee_rate The relayer's registered fee rate (in basis points, 0-10000)
+ * @param max_transfer_fee The most a relayer may charge when `ext_amount` is 0
+ * @return Ok(()) if `fee` is within its cap, Err(ErrorCode::RelayerFeeTooHigh) otherwise
+ */
+pub fn check_relayer_fee(fee: u64, ext_amount: i64, fee_rate: u16, max_transfer_fee: u64) -> Result<()> {
+    let max_fee = if ext_amount == 0 {
+        max_transfer_fee as u128
+    } else {
+        (ext_amount.unsigned_abs() as u128)
+            .checked_mul(fee_rate as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / 10000
+    };
+
+    require!(fee as u128 <= max_fee, ErrorCode::RelayerFeeTooHigh);
+    Ok(())
+}
+
+/**
+ * Checks an encrypted output's length against the configured set of allowed lengths.
+ *
+ * Fixed lengths keep notes from different wallets indistinguishable on-chain and
+ * stop oversized ciphertexts from pushing transact over compute or log limits.
+ *
+ * @param len Length of the encrypted output in bytes
+ * @param allowed_lengths Allowed lengths from the global config; zero entries are unused
+ * @return true if `len` is one of the allowed lengths and at most MAX_ENCRYPTED_OUTPUT_LEN
+ */
+pub fn check_encrypted_output_length(len: usize, allowed_lengths: &[u16]) -> bool {
+    if len > MAX_ENCRYPTED_OUTPUT_LEN {
+        return false;
+    }
+
+    allowed_lengths
+        .iter()
+        .any(|&allowed| allowed != 0 && allowed as usize == len)
+}
+
+/**
+ * Whether a queued tree drops a transaction's outputs instead of queueing them: only
+ * withdrawals whose outputs are both zero notes, which have nothing left to spend.
+ *
+ * @param queue_enabled Whether the tree queues its outputs
+ * @param ext_amount The external amount (negative for withdrawals)
+ * @param output_commitments The proof's output commitments
+ * @return true if the outputs are dropped
+ */
+pub fn drops_outputs(queue_enabled: bool, ext_amount: i64, output_commitments: [[u8; 32]; 2]) -> bool {
+    queue_enabled && ext_amount < 0 && output_commitments == [zero_note_commitment(); 2]
+}
+
+/**
+ * Lamports a transaction moves out of the pool, as counted by the outflow window.
+ * The protocol fee stays with the protocol (fee vault or shard reserve), so it is not
+ * outflow; a deposit covers its own relayer fee.
+ *
+ * @param ext_amount The external amount (negative for withdrawals)
+ * @param fee The relayer fee from the external data
+ * @return The withdrawal amount plus relayer fee, or 0 for deposits
+ */
+pub fn transact_outflow(ext_amount: i64, fee: u64) -> Result<u64> {
+    if ext_amount > 0 {
+        return Ok(0);
+    }
+    Ok(ext_amount
+        .unsigned_abs()
+        .checked_add(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow)?)
+}
+
+/**
+ * Records lamports leaving the pool against the rolling outflow window and rejects
+ * the transaction if the window total would exceed max_outflow_rate of the pool balance.
+ *
+ * The window slides in buckets of outflow_window_slots / OUTFLOW_WINDOW_BUCKETS slots,
+ * so outflow is limited over any stretch of that length, not only within fixed windows.
+ * The limit is taken of the pool balance before the outflow still in the window.
+ *
+ * @param global_config The global config holding the limit and the window state
+ * @param outflow Lamports about to leave the pool (withdrawal amount plus relayer fee)
+ * @param pool_balance Current pool balance, excluding the rent-exempt minimum
+ * @param current_slot The current slot from the Clock sysvar
+ * @return Ok(()) if the outflow fits in the window, Err(ErrorCode::OutflowLimitExceeded) otherwise
+ */
+pub fn check_outflow_limit(
+    global_config: &mut GlobalConfig,
+    outflow: u64,
+    pool_balance: u64,
+    current_slot: u64,
+) -> Result<()> {
+    check_outflow_window(
+        &mut global_config.outflow_window,
+        global_config.max_outflow_rate,
+        global_config.outflow_window_slots,
+        outflow,
+        pool_balance,
+        current_slot,
+    )
+}
+
+/**
+ * Same as check_outflow_limit, for a pool that keeps its own window state, such as
+ * a tree shard. The limit itself always comes from the global config.
+ *
+ * @param window The pool's outflow window state
+ * @param max_outflow_rate Basis points of the pool balance that may leave per window
+ * @param outflow_window_slots Length of the rolling window, in slots
+ * @param outflow Lamports about to leave the pool (withdrawal amount plus relayer fee)
+ * @param pool_balance Current pool balance, excluding reserved lamports
+ * @param current_slot The current slot from the Clock sysvar
+ * @return Ok(()) if the outflow fits in the window, Err(ErrorCode::OutflowLimitExceeded) otherwise
+ */
+pub fn check_outflow_window(
+    window: &mut OutflowWindow,
+    max_outflow_rate: u16,
+    outflow_window_slots: u64,
+    outflow: u64,
+    pool_balance: u64,
+    current_slot: u64,
+) -> Result<()> {
+    let bucket_slots = outflow_window_slots
+        .div_ceil(OUTFLOW_WINDOW_BUCKETS as u64)
+        .max(1);
+
+    // Buckets filled under another window length can't be compared, so start afresh
+    if window.bucket_slots != bucket_slots {
+        *window = OutflowWindow {
+            bucket_slots,
+            ..OutflowWindow::default()
+        };
+    }
+
+    // Empty the buckets that have slid out of the window since the last outflow
+    let bucket = current_slot / bucket_slots;
+    let expired = bucket
+        .saturating_sub(window.newest_bucket)
+        .min(OUTFLOW_WINDOW_BUCKETS as u64);
+    for age in 0..expired {
+        window.buckets[((bucket - age) % OUTFLOW_WINDOW_BUCKETS as u64) as usize] = 0;
+    }
+    window.newest_bucket = window.newest_bucket.max(bucket);
+
+    let total = window
+        .buckets
+        .iter()
+        .try_fold(0u64, |total, &amount| total.checked_add(amount))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    let max_outflow = (pool_balance as u128)
+        .checked_add(total as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_mul(max_outflow_rate as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    let new_total = total
+        .checked_add(outflow)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    require!(
+        new_total as u128 <= max_outflow,
+        ErrorCode::OutflowLimitExceeded
+    );
+
+    let slot = &mut window.buckets[(window.newest_bucket % OUTFLOW_WINDOW_BUCKETS as u64) as usize];
+    *slot = slot
+        .checked_add(outflow)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    Ok(())
+}
+
+/**
+ * Moves lamports out of a program-owned account by adjusting balances directly,
+ * since PDAs cannot sign system transfers.
+ *
+ * @param from Program-owned account to debit
+ * @param to Account to credit
+ * @param amount Lamports to move
+ */
+pub fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let new_from_balance = from
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let new_to_balance = to
+        .lamports()
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    **from.try_borrow_mut_lamports()? = new_from_balance;
+    **to.try_borrow_mut_lamports()? = new_to_balance;
+
+    Ok(())
+}
+
+/**
+ * Encodes a u64 as a big endian public input.
+ */
+pub fn u64_to_field(value: u64) -> [u8; 32] {
+    let mut field_bytes = [0u8; 32];
+    field_bytes[24..].copy_from_slice(&value.to_be_bytes());
+    field_bytes
+}
+
+/**
+ * Reduces a 32-byte big endian value into the BN254 scalar field.
+ *
+ * @param bytes Big endian value, e.g. a SHA-256 content hash
+ * @return The reduced value as a big endian public input
+ */
+pub fn reduce_to_field(bytes: &[u8; 32]) -> [u8; 32] {
+    let reduced = Fr::from_be_bytes_mod_order(bytes).into_bigint().to_bytes_be();
+    let mut field_bytes = [0u8; 32];
+    field_bytes[32 - reduced.len()..].copy_from_slice(&reduced);
+    field_bytes
+}
+
 pub fn verify_proof(proof: Proof, verifying_key: Groth16Verifyingkey) -> bool {
     let mut public_inputs_vec: [[u8; 32]; 7] = [[0u8; 32]; 7];
 
@@ -222,9 +466,32 @@ pub fn verify_proof(proof: Proof, verifying_key: Groth16Verifyingkey) -> bool {
     public_inputs_vec[5] = proof.output_commitments[0];
     public_inputs_vec[6] = proof.output_commitments[1];
 
+    verify_groth16(
+        &proof.proof_a,
+        &proof.proof_b,
+        &proof.proof_c,
+        &public_inputs_vec,
+        &verifying_key,
+    )
+}
+
+/**
+ * Verifies a Groth16 proof against any verifying key and set of public inputs.
+ *
+ * @param proof_a The proof's A point, as produced by the prover (it is negated here)
+ * @param public_inputs Public inputs in big endian, in the circuit's declared order
+ * @return true if the proof verifies, false on any decoding or verification failure
+ */
+pub fn verify_groth16<const NR_INPUTS: usize>(
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    public_inputs: &[[u8; 32]; NR_INPUTS],
+    verifying_key: &Groth16Verifyingkey,
+) -> bool {
      // First deserialize PROOF_A into a G1 point
      let g1_point = match G1::deserialize_with_mode(
-        &*[&change_endianness(&proof.proof_a[0..64]), &[0u8][..]].concat(),
+        &*[&change_endianness(&proof_a[0..64]), &[0u8][..]].concat(),
         Compress::No,
         Validate::Yes,
     ) {
@@ -255,10 +522,10 @@ pub fn verify_proof(proof: Proof, verifying_key: Groth16Verifyingkey) -> bool {
 
     let mut verifier = match Groth16Verifier::new(
         &proof_a,
-        &proof.proof_b,
-        &proof.proof_c,
-        &public_inputs_vec,
-        &verifying_key
+        proof_b,
+        proof_c,
+        public_inputs,
+        verifying_key
     ) {
         Ok(v) => v,
         Err(_) => return false,
@@ -272,15 +539,21 @@ pub fn verify_proof(proof: Proof, verifying_key: Groth16Verifyingkey) -> bool {
  * This matches the client-side calculation for hash verification
  * 
  * This is for SOL mint address only
+ *
+ * When `payouts` is non-empty the withdrawal is split, and the hashed data lists every
+ * (recipient, amount) pair in place of `recipient`
  */
+#[allow(clippy::too_many_arguments)]
 pub fn calculate_complete_ext_data_hash(
     recipient: Pubkey,
     ext_amount: i64,
     encrypted_output1: &[u8],
     encrypted_output2: &[u8],
     fee: u64,
+    protocol_fee: u64,
     fee_recipient: Pubkey,
     mint_address: Pubkey,
+    payouts: &[Payout],
 ) -> Result<[u8; 32]> {
     #[derive(AnchorSerialize)]
     struct CompleteExtData {
@@ -289,6 +562,7 @@ pub fn calculate_complete_ext_data_hash(
         pub encrypted_output1: Vec<u8>,
         pub encrypted_output2: Vec<u8>,
         pub fee: u64,
+        pub protocol_fee: u64,
         pub fee_recipient: Pubkey,
         pub mint_address: Pubkey,
     }
@@ -299,17 +573,122 @@ pub fn calculate_complete_ext_data_hash(
         encrypted_output1: encrypted_output1.to_vec(),
         encrypted_output2: encrypted_output2.to_vec(),
         fee,
+        protocol_fee,
         fee_recipient,
         mint_address
     };
     
     let mut serialized_ext_data = Vec::new();
-    complete_ext_data.serialize(&mut serialized_ext_data)?;
+    if payouts.is_empty() {
+        complete_ext_data.serialize(&mut serialized_ext_data)?;
+    } else {
+        #[derive(AnchorSerialize)]
+        struct MultiRecipientExtData<'a> {
+            pub payouts: &'a [Payout],
+            pub ext_amount: i64,
+            pub encrypted_output1: &'a [u8],
+            pub encrypted_output2: &'a [u8],
+            pub fee: u64,
+            pub protocol_fee: u64,
+            pub fee_recipient: Pubkey,
+            pub mint_address: Pubkey,
+        }
+
+        MultiRecipientExtData {
+            payouts,
+            ext_amount,
+            encrypted_output1,
+            encrypted_output2,
+            fee,
+            protocol_fee,
+            fee_recipient,
+            mint_address,
+        }
+        .serialize(&mut serialized_ext_data)?;
+    }
     let calculated_ext_data_hash = hash(&serialized_ext_data).to_bytes();
     
     Ok(calculated_ext_data_hash)
 }
 
+/**
+ * Borrows a verifying key stored on-chain in the layout the Groth16 verifier expects.
+ */
+pub fn groth16_verifying_key(account: &VerifyingKeyAccount) -> Groth16Verifyingkey<'_> {
+    Groth16Verifyingkey {
+        nr_pubinputs: account.vk_ic.len().saturating_sub(1),
+        vk_alpha_g1: account.vk_alpha_g1,
+        vk_beta_g2: account.vk_beta_g2,
+        vk_gamme_g2: account.vk_gamma_g2,
+        vk_delta_g2: account.vk_delta_g2,
+        vk_ic: &account.vk_ic,
+    }
+}
+
+/**
+ * Checks that the current slot is inside the slot window a timelock proof was made for.
+ *
+ * The circuit only lets the recipient claim with `max_slot` before a note's expiry and
+ * the sender refund with `min_slot` at or after it, so this is what enforces the lock.
+ *
+ * @param window The proof's public slot window
+ * @param current_slot The current slot from the Clock sysvar
+ * @return true if `current_slot` is within `[min_slot, max_slot]`
+ */
+pub fn check_timelock_window(window: &TimelockWindow, current_slot: u64) -> bool {
+    window.min_slot <= current_slot && current_slot <= window.max_slot
+}
+
+/**
+ * Commitment of the zero note, `Poseidon(0, 0, 0, SOL_ADDRESS)`.
+ *
+ * Its amount is zero and its public key has no known private key, so it is worth
+ * nothing and can never be spent. Withdrawals that leave no change use it for both
+ * outputs, which lets queued trees drop them instead of queueing them.
+ *
+ * @return The commitment as a big-endian field element
+ */
+pub fn zero_note_commitment() -> [u8; 32] {
+    let zero = [0u8; 32];
+    Poseidon::hashv(&[&zero, &zero, &zero, &SOL_ADDRESS.to_bytes()])
+        .expect("zero note inputs are field elements")
+}
+
+/**
+ * Checks that `initialize` is signed by the key allowed to set up a fresh deployment.
+ *
+ * @param authority The signer of `initialize`
+ * @param admin Build-time admin key (ADMIN_PUBKEY); takes precedence when set
+ * @param upgrade_authority Upgrade authority from the program's ProgramData account
+ * @return Ok(()) if the signer is the admin key, or the upgrade authority when there is none
+ */
+pub fn check_initializer(
+    authority: &Pubkey,
+    admin: Option<Pubkey>,
+    upgrade_authority: Option<Pubkey>,
+) -> Result<()> {
+    let expected = match admin {
+        Some(admin) => Some(admin),
+        None => upgrade_authority,
+    };
+    require!(expected == Some(*authority), ErrorCode::Unauthorized);
+    Ok(())
+}
+
+/**
+ * Last seed of a per-tree PDA. Empty for the main tree (shard 0), so its accounts keep
+ * the addresses they had before shards existed.
+ *
+ * @param shard_id The tree shard, 0 for the main tree
+ */
+pub fn shard_seed(shard_id: &u8) -> &[u8] {
+    if *shard_id == 0 {
+        &[]
+    } else {
+        std::slice::from_ref(shard_id)
+    }
+}
+
 pub fn change_endianness(bytes: &[u8]) -> Vec<u8> {
     let mut vec = Vec::new();
     for b in bytes.chunks(32) {
@@ -320,3 +699,261 @@ pub fn change_endianness(bytes: &[u8]) -> Vec<u8> {
     vec
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_note_commits_to_the_sol_mint_field() {
+        // the circuits take SOL's mint as the field element 1
+        let sol_mint = u64_to_field(1);
+        assert_eq!(SOL_ADDRESS.to_bytes(), sol_mint);
+
+        let zero = [0u8; 32];
+        let expected = Poseidon::hashv(&[&zero, &zero, &zero, &sol_mint]).unwrap();
+        assert_eq!(zero_note_commitment(), expected);
+        assert_ne!(zero_note_commitment(), Poseidon::hashv(&[&u64_to_field(1), &zero, &zero, &sol_mint]).unwrap());
+    }
+
+    #[test]
+    fn initializer_must_be_the_admin_or_upgrade_authority() {
+        let upgrade_authority = Pubkey::new_unique();
+        let admin = Pubkey::new_unique();
+        let front_runner = Pubkey::new_unique();
+
+        assert!(check_initializer(&upgrade_authority, None, Some(upgrade_authority)).is_ok());
+        assert_eq!(
+            check_initializer(&front_runner, None, Some(upgrade_authority)).unwrap_err(),
+            error!(ErrorCode::Unauthorized)
+        );
+
+        // a pinned admin key replaces the upgrade authority
+        assert!(check_initializer(&admin, Some(admin), Some(upgrade_authority)).is_ok());
+        assert_eq!(
+            check_initializer(&upgrade_authority, Some(admin), Some(upgrade_authority)).unwrap_err(),
+            error!(ErrorCode::Unauthorized)
+        );
+
+        // an immutable program has no upgrade authority left to initialize it
+        assert_eq!(
+            check_initializer(&front_runner, None, None).unwrap_err(),
+            error!(ErrorCode::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn relayer_fee_is_capped_by_its_rate() {
+        // 0.5% of a 2 SOL withdrawal
+        let withdrawal = -2_000_000_000;
+        let transfer_cap = 10_000_000;
+        assert!(check_relayer_fee(10_000_000, withdrawal, 50, transfer_cap).is_ok());
+        assert!(check_relayer_fee(0, withdrawal, 50, transfer_cap).is_ok());
+        assert_eq!(
+            check_relayer_fee(10_000_001, withdrawal, 50, transfer_cap).unwrap_err(),
+            error!(ErrorCode::RelayerFeeTooHigh)
+        );
+
+        // the cap rounds down, and a zero rate relays for free
+        assert!(check_relayer_fee(1, 19_999, 1, transfer_cap).is_ok());
+        assert!(check_relayer_fee(2, 19_999, 1, transfer_cap).is_err());
+        assert!(check_relayer_fee(1, withdrawal, 0, transfer_cap).is_err());
+        assert!(check_relayer_fee(u64::MAX, i64::MIN, 10000, transfer_cap).is_err());
+    }
+
+    #[test]
+    fn transfer_fee_is_capped_at_a_flat_amount() {
+        // a transfer moves nothing in or out, so there is nothing to take a rate of
+        assert!(check_relayer_fee(10_000_000, 0, 50, 10_000_000).is_ok());
+        assert!(check_relayer_fee(10_000_000, 0, 0, 10_000_000).is_ok());
+        assert_eq!(
+            check_relayer_fee(10_000_001, 0, 10000, 10_000_000).unwrap_err(),
+            error!(ErrorCode::RelayerFeeTooHigh)
+        );
+        assert!(check_relayer_fee(1, 0, 10000, 0).is_err());
+    }
+
+    #[test]
+    fn relayer_must_keep_the_minimum_stake_to_relay() {
+        let mut relayer = RelayerAccount {
+            authority: Pubkey::new_unique(),
+            stake: 10_000_000_000,
+            fee_rate: 50,
+            endpoint: String::new(),
+            jailed: false,
+            unbond_after: 0,
+            bump: 0,
+        };
+        assert!(check_relayer_active(&relayer, 10_000_000_000).is_ok());
+
+        // slashed, then unjailed with less than the minimum left
+        relayer.stake = 6_000_000_000;
+        assert_eq!(
+            check_relayer_active(&relayer, 10_000_000_000).unwrap_err(),
+            error!(ErrorCode::InsufficientRelayerStake)
+        );
+
+        // or the minimum was raised since it registered
+        relayer.stake = 10_000_000_000;
+        assert_eq!(
+            check_relayer_active(&relayer, 20_000_000_000).unwrap_err(),
+            error!(ErrorCode::InsufficientRelayerStake)
+        );
+
+        relayer.jailed = true;
+        assert_eq!(
+            check_relayer_active(&relayer, 10_000_000_000).unwrap_err(),
+            error!(ErrorCode::RelayerJailed)
+        );
+        relayer.jailed = false;
+        relayer.unbond_after = 1;
+        assert_eq!(
+            check_relayer_active(&relayer, 10_000_000_000).unwrap_err(),
+            error!(ErrorCode::RelayerNotRegistered)
+        );
+    }
+
+    #[test]
+    fn encrypted_outputs_must_have_an_allowed_length() {
+        use crate::state::DEFAULT_ENCRYPTED_OUTPUT_LEN;
+
+        // the default accepts exactly one length
+        let default = [DEFAULT_ENCRYPTED_OUTPUT_LEN, 0, 0, 0];
+        assert!(check_encrypted_output_length(DEFAULT_ENCRYPTED_OUTPUT_LEN as usize, &default));
+        for len in [0, 1, DEFAULT_ENCRYPTED_OUTPUT_LEN as usize - 1, DEFAULT_ENCRYPTED_OUTPUT_LEN as usize + 1] {
+            assert!(!check_encrypted_output_length(len, &default));
+        }
+
+        // zero entries are unused, never an allowed length
+        let two = [0, 189, 0, 512];
+        assert!(check_encrypted_output_length(189, &two));
+        assert!(check_encrypted_output_length(512, &two));
+        assert!(!check_encrypted_output_length(0, &two));
+        assert!(!check_encrypted_output_length(100, &[0; 4]));
+
+        assert!(!check_encrypted_output_length(MAX_ENCRYPTED_OUTPUT_LEN + 1, &[(MAX_ENCRYPTED_OUTPUT_LEN + 1) as u16, 0, 0, 0]));
+    }
+
+    const OUTFLOW_RATE: u16 = 2500;
+    const OUTFLOW_WINDOW_SLOTS: u64 = 800; // buckets of 100 slots
+
+    fn withdraw(window: &mut OutflowWindow, amount: u64, pool_balance: u64, slot: u64) -> Result<()> {
+        check_outflow_window(window, OUTFLOW_RATE, OUTFLOW_WINDOW_SLOTS, amount, pool_balance, slot)
+    }
+
+    #[test]
+    fn outflow_is_limited_to_a_share_of_the_pool() {
+        let mut window = OutflowWindow::default();
+        assert!(withdraw(&mut window, 150, 1_000, 1_000).is_ok());
+        assert!(withdraw(&mut window, 100, 850, 1_050).is_ok());
+
+        // 25% of the 1000 lamports the pool held before the window's outflow
+        assert_eq!(
+            withdraw(&mut window, 1, 750, 1_099).unwrap_err(),
+            error!(ErrorCode::OutflowLimitExceeded)
+        );
+        assert_eq!(
+            withdraw(&mut window, 1, 750, 1_799).unwrap_err(),
+            error!(ErrorCode::OutflowLimitExceeded)
+        );
+
+        // a single withdrawal over the limit never fits
+        let mut window = OutflowWindow::default();
+        assert!(withdraw(&mut window, 251, 1_000, 0).is_err());
+        assert!(check_outflow_window(&mut window, 0, OUTFLOW_WINDOW_SLOTS, 1, 1_000, 0).is_err());
+        assert!(check_outflow_window(&mut window, 10000, OUTFLOW_WINDOW_SLOTS, 1_000, 1_000, 0).is_ok());
+    }
+
+    #[test]
+    fn outflow_window_rolls_instead_of_resetting() {
+        // a fixed window would let 250 out at its end and another 250 right after
+        let mut window = OutflowWindow::default();
+        assert!(withdraw(&mut window, 250, 1_000, 799).is_ok());
+        assert!(withdraw(&mut window, 1, 750, 800).is_err());
+        assert!(withdraw(&mut window, 1, 750, 1_499).is_err());
+
+        // the outflow leaves the window a full window length after its bucket
+        assert!(withdraw(&mut window, 187, 750, 1_500).is_ok());
+
+        // older buckets slide out one at a time
+        let mut window = OutflowWindow::default();
+        assert!(withdraw(&mut window, 100, 1_000, 0).is_ok());
+        assert!(withdraw(&mut window, 150, 900, 400).is_ok());
+        assert!(withdraw(&mut window, 1, 750, 799).is_err());
+        assert!(withdraw(&mut window, 76, 750, 800).is_err());
+        assert!(withdraw(&mut window, 75, 750, 800).is_ok());
+        assert!(withdraw(&mut window, 1, 675, 1_199).is_err());
+        assert!(withdraw(&mut window, 112, 675, 1_200).is_ok());
+
+        // a long quiet spell empties the whole window
+        assert!(withdraw(&mut window, 250, 1_000, 1_000_000).is_ok());
+        assert_eq!(window.buckets.iter().sum::<u64>(), 250);
+    }
+
+    #[test]
+    fn new_window_length_starts_afresh() {
+        let mut window = OutflowWindow::default();
+        assert!(withdraw(&mut window, 250, 1_000, 0).is_ok());
+        assert!(check_outflow_window(&mut window, OUTFLOW_RATE, 1_600, 250, 1_000, 1).is_ok());
+        assert_eq!(window.bucket_slots, 200);
+        assert_eq!(window.buckets.iter().sum::<u64>(), 250);
+    }
+
+    #[test]
+    fn only_withdrawals_to_zero_notes_drop_their_outputs() {
+        let zero = [zero_note_commitment(); 2];
+        assert!(drops_outputs(true, -1, zero));
+        assert!(!drops_outputs(false, -1, zero));
+
+        // transfers and deposits always queue their outputs, zero notes or not
+        assert!(!drops_outputs(true, 0, zero));
+        assert!(!drops_outputs(true, 1, zero));
+        assert!(!drops_outputs(true, -1, [zero[0], [1; 32]]));
+    }
+
+    #[test]
+    fn protocol_fees_are_not_outflow() {
+        assert_eq!(transact_outflow(-1_000, 5).unwrap(), 1_005);
+        assert_eq!(transact_outflow(0, 5).unwrap(), 5);
+        assert_eq!(transact_outflow(1_000, 5).unwrap(), 0);
+        assert_eq!(transact_outflow(i64::MIN, 0).unwrap(), 1 << 63);
+        assert!(transact_outflow(i64::MIN, u64::MAX).is_err());
+    }
+
+    const EXPIRY: u64 = 1_000;
+
+    // windows as a client builds them: a claim must be proven entirely before the
+    // expiry, a refund entirely at or after it
+    fn claim_window(current_slot: u64) -> TimelockWindow {
+        TimelockWindow {
+            min_slot: current_slot,
+            max_slot: EXPIRY - 1,
+        }
+    }
+
+    fn refund_window() -> TimelockWindow {
+        TimelockWindow {
+            min_slot: EXPIRY,
+            max_slot: u64::MAX,
+        }
+    }
+
+    #[test]
+    fn timelock_claim_only_before_expiry() {
+        let window = claim_window(900);
+        assert!(!check_timelock_window(&window, 899));
+        assert!(check_timelock_window(&window, 900));
+        assert!(check_timelock_window(&window, EXPIRY - 1));
+        assert!(!check_timelock_window(&window, EXPIRY));
+        assert!(!check_timelock_window(&window, EXPIRY + 500));
+    }
+
+    #[test]
+    fn timelock_refund_only_from_expiry() {
+        let window = refund_window();
+        assert!(!check_timelock_window(&window, 0));
+        assert!(!check_timelock_window(&window, EXPIRY - 1));
+        assert!(check_timelock_window(&window, EXPIRY));
+        assert!(check_timelock_window(&window, EXPIRY + 500));
+    }
+}
@@ -0,0 +1,305 @@
+import { BN } from "@coral-xyz/anchor";
+import {
+  createInstructionData,
+  GovernanceConfig,
+  MintMaxVoteWeightSource,
+  PROGRAM_VERSION_V3,
+  Vote,
+  VoteThreshold,
+  VoteThresholdType,
+  VoteTipping,
+  VoteType,
+  YesNoVote,
+  withCastVote,
+  withCreateGovernance,
+  withCreateProposal,
+  withCreateRealm,
+  withDepositGoverningTokens,
+  withExecuteTransaction,
+  withInsertTransaction,
+  withSignOffProposal,
+} from "@solana/spl-governance";
+import {
+  ACCOUNT_SIZE,
+  createInitializeAccount3Instruction,
+  createInitializeMint2Instruction,
+  createMintToInstruction,
+  MINT_SIZE,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram, TransactionInstruction } from "@solana/web3.js";
+import { expect } from "chai";
+import {
+  expectError,
+  expectFailure,
+  GOVERNANCE_PROGRAM_ID,
+  pda,
+  Pool,
+  send,
+  startInitializedPool,
+} from "./helpers";
+
+const TOKEN_SUPPLY = 1_000_000;
+
+// A $TXNSFR realm whose one token holder owns the whole supply, so a yes vote
+// tips the proposal right away
+interface Dao {
+  mint: PublicKey;
+  realm: PublicKey;
+  governance: PublicKey;
+  tokenOwnerRecord: PublicKey;
+  proposalCount: number;
+}
+
+async function createDao(pool: Pool): Promise<Dao> {
+  const payer = pool.context.payer;
+  const mint = Keypair.generate();
+  const holderTokens = Keypair.generate();
+
+  await send(
+    pool,
+    [
+      SystemProgram.createAccount({
+        fromPubkey: payer.publicKey,
+        newAccountPubkey: mint.publicKey,
+        lamports: LAMPORTS_PER_SOL,
+        space: MINT_SIZE,
+        programId: TOKEN_PROGRAM_ID,
+      }),
+      createInitializeMint2Instruction(mint.publicKey, 0, payer.publicKey, null),
+      SystemProgram.createAccount({
+        fromPubkey: payer.publicKey,
+        newAccountPubkey: holderTokens.publicKey,
+        lamports: LAMPORTS_PER_SOL,
+        space: ACCOUNT_SIZE,
+        programId: TOKEN_PROGRAM_ID,
+      }),
+      createInitializeAccount3Instruction(holderTokens.publicKey, mint.publicKey, payer.publicKey),
+      createMintToInstruction(mint.publicKey, holderTokens.publicKey, payer.publicKey, TOKEN_SUPPLY),
+    ],
+    [mint, holderTokens]
+  );
+
+  const realmInstructions: TransactionInstruction[] = [];
+  const realm = await withCreateRealm(
+    realmInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    "txnsfr",
+    payer.publicKey,
+    mint.publicKey,
+    payer.publicKey,
+    undefined,
+    MintMaxVoteWeightSource.FULL_SUPPLY_FRACTION,
+    new BN(1)
+  );
+  const tokenOwnerRecord = await withDepositGoverningTokens(
+    realmInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    realm,
+    holderTokens.publicKey,
+    mint.publicKey,
+    payer.publicKey,
+    payer.publicKey,
+    payer.publicKey,
+    new BN(TOKEN_SUPPLY)
+  );
+  await send(pool, realmInstructions);
+
+  const governanceInstructions: TransactionInstruction[] = [];
+  const governance = await withCreateGovernance(
+    governanceInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    realm,
+    Keypair.generate().publicKey,
+    new GovernanceConfig({
+      communityVoteThreshold: new VoteThreshold({ type: VoteThresholdType.YesVotePercentage, value: 60 }),
+      minCommunityTokensToCreateProposal: new BN(1),
+      minInstructionHoldUpTime: 0,
+      baseVotingTime: 3 * 24 * 60 * 60,
+      communityVoteTipping: VoteTipping.Early,
+      minCouncilTokensToCreateProposal: new BN(1),
+      councilVoteThreshold: new VoteThreshold({ type: VoteThresholdType.Disabled }),
+      councilVetoVoteThreshold: new VoteThreshold({ type: VoteThresholdType.Disabled }),
+      communityVetoVoteThreshold: new VoteThreshold({ type: VoteThresholdType.Disabled }),
+      councilVoteTipping: VoteTipping.Disabled,
+      votingCoolOffTime: 0,
+      depositExemptProposalCount: 10,
+    }),
+    tokenOwnerRecord,
+    payer.publicKey,
+    payer.publicKey
+  );
+  await send(pool, governanceInstructions);
+
+  return { mint: mint.publicKey, realm, governance, tokenOwnerRecord, proposalCount: 0 };
+}
+
+// Creates and signs off a proposal to run `instructions`, signed by the governance PDA
+async function propose(pool: Pool, dao: Dao, instructions: TransactionInstruction[]) {
+  const payer = pool.context.payer.publicKey;
+  const proposalInstructions: TransactionInstruction[] = [];
+  const proposal = await withCreateProposal(
+    proposalInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    dao.realm,
+    dao.governance,
+    dao.tokenOwnerRecord,
+    `txnsfr proposal ${dao.proposalCount}`,
+    "",
+    dao.mint,
+    payer,
+    dao.proposalCount++,
+    VoteType.SINGLE_CHOICE,
+    ["Approve"],
+    true,
+    payer
+  );
+  const transactionInstructions = instructions.map(createInstructionData);
+  const proposalTransaction = await withInsertTransaction(
+    proposalInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    dao.governance,
+    proposal,
+    dao.tokenOwnerRecord,
+    payer,
+    0,
+    0,
+    0,
+    transactionInstructions,
+    payer
+  );
+  await withSignOffProposal(
+    proposalInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    dao.realm,
+    dao.governance,
+    proposal,
+    payer,
+    undefined,
+    dao.tokenOwnerRecord
+  );
+  await send(pool, proposalInstructions);
+
+  const executeInstructions: TransactionInstruction[] = [];
+  await withExecuteTransaction(
+    executeInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    dao.governance,
+    proposal,
+    proposalTransaction,
+    transactionInstructions
+  );
+  return { proposal, execute: () => send(pool, executeInstructions) };
+}
+
+async function voteYes(pool: Pool, dao: Dao, proposal: PublicKey) {
+  const payer = pool.context.payer.publicKey;
+  const voteInstructions: TransactionInstruction[] = [];
+  await withCastVote(
+    voteInstructions,
+    GOVERNANCE_PROGRAM_ID,
+    PROGRAM_VERSION_V3,
+    dao.realm,
+    dao.governance,
+    proposal,
+    dao.tokenOwnerRecord,
+    dao.tokenOwnerRecord,
+    payer,
+    dao.mint,
+    Vote.fromYesNoVote(YesNoVote.Yes),
+    payer
+  );
+  await send(pool, voteInstructions);
+}
+
+async function passAndExecute(pool: Pool, dao: Dao, instructions: TransactionInstruction[]) {
+  const { proposal, execute } = await propose(pool, dao, instructions);
+  await voteYes(pool, dao, proposal);
+  await execute();
+}
+
+describe("governance", () => {
+  let pool: Pool;
+  let dao: Dao;
+
+  before(async () => {
+    pool = await startInitializedPool({ governance: true });
+    dao = await createDao(pool);
+
+    // the deployer hands the pool to the DAO, which accepts it with a passed proposal
+    await pool.program.methods
+      .proposeAuthority(dao.governance, new BN(0))
+      .accountsPartial({ authority: pool.upgradeAuthority.publicKey })
+      .signers([pool.upgradeAuthority])
+      .rpc();
+    await passAndExecute(pool, dao, [
+      await pool.program.methods.acceptAuthority().accountsPartial({ newAuthority: dao.governance }).instruction(),
+    ]);
+  });
+
+  it("hands the pool authority to the governance PDA", async () => {
+    const globalConfig = await pool.program.account.globalConfig.fetch(pda(pool.program, Buffer.from("global_config")));
+    const tree = await pool.program.account.merkleTreeAccount.fetch(pda(pool.program, Buffer.from("merkle_tree")));
+    expect(globalConfig.authority.equals(dao.governance)).to.be.true;
+    expect(tree.authority.equals(dao.governance)).to.be.true;
+  });
+
+  it("executes a passed proposal that updates the fee config and deposit limit", async () => {
+    await passAndExecute(pool, dao, [
+      await pool.program.methods
+        .updateFeeConfig(10, 40, 250)
+        .accountsPartial({ authority: dao.governance })
+        .instruction(),
+      await pool.program.methods
+        .updateDepositLimit(new BN(5 * LAMPORTS_PER_SOL))
+        .accountsPartial({ authority: dao.governance })
+        .instruction(),
+    ]);
+
+    const globalConfig = await pool.program.account.globalConfig.fetch(pda(pool.program, Buffer.from("global_config")));
+    expect(globalConfig.depositFeeRate).to.equal(10);
+    expect(globalConfig.withdrawalFeeRate).to.equal(40);
+    expect(globalConfig.feeErrorMargin).to.equal(250);
+    const tree = await pool.program.account.merkleTreeAccount.fetch(pda(pool.program, Buffer.from("merkle_tree")));
+    expect(tree.maxDepositAmount.toNumber()).to.equal(5 * LAMPORTS_PER_SOL);
+  });
+
+  it("does not execute a proposal that has not passed", async () => {
+    const { execute } = await propose(pool, dao, [
+      await pool.program.methods
+        .updateDepositLimit(new BN(1))
+        .accountsPartial({ authority: dao.governance })
+        .instruction(),
+    ]);
+    await expectFailure(execute());
+
+    const tree = await pool.program.account.merkleTreeAccount.fetch(pda(pool.program, Buffer.from("merkle_tree")));
+    expect(tree.maxDepositAmount.toNumber()).to.equal(5 * LAMPORTS_PER_SOL);
+  });
+
+  it("rejects parameter changes signed by the former authority", async () => {
+    await expectError(
+      pool.program.methods
+        .updateFeeConfig(0, 0, 0)
+        .accountsPartial({ authority: pool.upgradeAuthority.publicKey })
+        .signers([pool.upgradeAuthority])
+        .rpc(),
+      "Unauthorized"
+    );
+    await expectError(
+      pool.program.methods
+        .updateDepositLimit(new BN(1))
+        .accountsPartial({ authority: pool.upgradeAuthority.publicKey })
+        .signers([pool.upgradeAuthority])
+        .rpc(),
+      "Unauthorized"
+    );
+  });
+});
@@ -0,0 +1,155 @@
+import { AnchorError, Program, ProgramError } from "@coral-xyz/anchor";
+import {
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import { expect } from "chai";
+import { readFileSync } from "fs";
+import path from "path";
+import { BankrunProvider } from "anchor-bankrun";
+import { AddedAccount, ProgramTestContext, start } from "solana-bankrun";
+import IDL from "../target/idl/txnsfr.json";
+import { Txnsfr } from "../target/types/txnsfr";
+
+export const GOVERNANCE_PROGRAM_ID = new PublicKey("GovER5Lthms3bLBqWub97yVrMmEogzX7xNjdXpPPCVZw");
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
+export interface Pool {
+  context: ProgramTestContext;
+  provider: BankrunProvider;
+  program: Program<Txnsfr>;
+  // signs `initialize`, and is the pool authority once it has run
+  upgradeAuthority: Keypair;
+}
+
+export function pda(program: Program<Txnsfr>, ...seeds: (Buffer | Uint8Array)[]): PublicKey {
+  return PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+}
+
+export function programDataAddress(programId: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync([programId.toBuffer()], BPF_LOADER_UPGRADEABLE_ID)[0];
+}
+
+export function fundedAccount(address: PublicKey, lamports = 1_000 * LAMPORTS_PER_SOL): AddedAccount {
+  return {
+    address,
+    info: { lamports, data: Buffer.alloc(0), owner: SystemProgram.programId, executable: false },
+  };
+}
+
+// The program is loaded the way `solana program deploy` leaves it, with a ProgramData
+// account naming the upgrade authority, because `initialize` checks its signer against it.
+function upgradeableProgram(programId: PublicKey, upgradeAuthority: PublicKey): AddedAccount[] {
+  const programData = programDataAddress(programId);
+  const elf = readFileSync(path.join(__dirname, "..", "target", "deploy", "txnsfr.so"));
+
+  const program = Buffer.alloc(36);
+  program.writeUInt32LE(2, 0); // UpgradeableLoaderState::Program
+  programData.toBuffer().copy(program, 4);
+
+  const header = Buffer.alloc(45);
+  header.writeUInt32LE(3, 0); // UpgradeableLoaderState::ProgramData
+  header.writeBigUInt64LE(0n, 4); // deployment slot
+  header.writeUInt8(1, 12); // Some(upgrade_authority_address)
+  upgradeAuthority.toBuffer().copy(header, 13);
+
+  return [
+    {
+      address: programId,
+      info: { lamports: LAMPORTS_PER_SOL, data: program, owner: BPF_LOADER_UPGRADEABLE_ID, executable: true },
+    },
+    {
+      address: programData,
+      info: {
+        lamports: 100 * LAMPORTS_PER_SOL,
+        data: Buffer.concat([header, elf]),
+        owner: BPF_LOADER_UPGRADEABLE_ID,
+        executable: false,
+      },
+    },
+  ];
+}
+
+/**
+ * Starts an in-process SVM with the txnsfr program deployed but not initialized.
+ * With `governance`, SPL Governance is loaded from `tests/fixtures/spl_governance.so`
+ * (`npm run fixtures`).
+ */
+export async function startPool({ governance = false, accounts = [] as AddedAccount[] } = {}): Promise<Pool> {
+  const programId = new PublicKey(IDL.address);
+  const upgradeAuthority = Keypair.generate();
+
+  const context = await start(
+    governance ? [{ name: "spl_governance", programId: GOVERNANCE_PROGRAM_ID }] : [],
+    [
+      ...upgradeableProgram(programId, upgradeAuthority.publicKey),
+      fundedAccount(upgradeAuthority.publicKey),
+      ...accounts,
+    ]
+  );
+  const provider = new BankrunProvider(context);
+  const program = new Program<Txnsfr>(IDL as Txnsfr, provider);
+  return { context, provider, program, upgradeAuthority };
+}
+
+export function initialize(pool: Pool, authority: Keypair = pool.upgradeAuthority) {
+  return pool.program.methods
+    .initialize()
+    .accountsPartial({
+      authority: authority.publicKey,
+      programData: programDataAddress(pool.program.programId),
+    })
+    .signers([authority])
+    .rpc();
+}
+
+/** Starts an in-process SVM with an initialized pool, whose authority is `pool.upgradeAuthority`. */
+export async function startInitializedPool(options: Parameters<typeof startPool>[0] = {}): Promise<Pool> {
+  const pool = await startPool(options);
+  await initialize(pool);
+  return pool;
+}
+
+export async function send(pool: Pool, instructions: TransactionInstruction[], signers: Keypair[] = []) {
+  await pool.provider.sendAndConfirm(new Transaction().add(...instructions), signers);
+}
+
+export async function lamports(pool: Pool, address: PublicKey): Promise<number> {
+  return Number(await pool.context.banksClient.getBalance(address));
+}
+
+export async function warpSlots(pool: Pool, slots: number) {
+  const clock = await pool.context.banksClient.getClock();
+  pool.context.warpToSlot(clock.slot + BigInt(slots));
+}
+
+/** Expects `promise` to fail with the program error named `name`, e.g. "Unauthorized". */
+export async function expectError(promise: Promise<unknown>, name: string) {
+  const error = IDL.errors.find((e) => e.name === name);
+  if (!error) throw new Error(`txnsfr has no error named ${name}`);
+
+  try {
+    await promise;
+  } catch (err) {
+    if (err instanceof AnchorError) {
+      expect(err.error.errorCode.number).to.equal(error.code);
+    } else if (err instanceof ProgramError) {
+      expect(err.code).to.equal(error.code);
+    } else {
+      expect(String(err)).to.include(`custom program error: 0x${error.code.toString(16)}`);
+    }
+    return;
+  }
+  expect.fail(`expected the transaction to fail with ${name}`);
+}
+
+/** Expects `promise` to fail, for errors raised outside the txnsfr program. */
+export async function expectFailure(promise: Promise<unknown>) {
+  let failed = false;
+  await promise.catch(() => (failed = true));
+  expect(failed, "expected the transaction to fail").to.be.true;
+}
@@ -0,0 +1,26 @@
+import { Keypair } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, expectFailure, fundedAccount, initialize, pda, startPool } from "./helpers";
+
+describe("initialize", () => {
+  it("rejects an initializer that is not the upgrade authority", async () => {
+    const frontRunner = Keypair.generate();
+    const pool = await startPool({ accounts: [fundedAccount(frontRunner.publicKey)] });
+
+    await expectError(initialize(pool, frontRunner), "Unauthorized");
+
+    const globalConfig = await pool.context.banksClient.getAccount(pda(pool.program, Buffer.from("global_config")));
+    expect(globalConfig).to.be.null;
+  });
+
+  it("lets the upgrade authority initialize once", async () => {
+    const pool = await startPool();
+    await initialize(pool);
+
+    const globalConfig = await pool.program.account.globalConfig.fetch(pda(pool.program, Buffer.from("global_config")));
+    expect(globalConfig.authority.equals(pool.upgradeAuthority.publicKey)).to.be.true;
+
+    // the config PDAs exist now, so initialize can't be replayed
+    await expectFailure(initialize(pool));
+  });
+});
@@ -0,0 +1,143 @@
+import { BN } from "@coral-xyz/anchor";
+import { Keypair, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import {
+  expectError,
+  expectFailure,
+  fundedAccount,
+  lamports,
+  pda,
+  Pool,
+  startInitializedPool,
+  warpSlots,
+} from "./helpers";
+
+const MIN_STAKE = 10 * LAMPORTS_PER_SOL;
+const RELAYER_UNBONDING_SLOTS = 432_000;
+
+describe("relayer registry", () => {
+  let pool: Pool;
+  const relayers = Array.from({ length: 3 }, () => Keypair.generate());
+  const [honest, misbehaving, outsider] = relayers;
+
+  const relayerAddress = (relayer: Keypair) =>
+    pda(pool.program, Buffer.from("relayer"), relayer.publicKey.toBuffer());
+  const feeVault = () => pda(pool.program, Buffer.from("fee_vault"));
+
+  const register = (relayer: Keypair, feeRate: number, stake: number) =>
+    pool.program.methods
+      .registerRelayer("https://relay.example.org", feeRate, new BN(stake))
+      .accountsPartial({ authority: relayer.publicKey })
+      .signers([relayer])
+      .rpc();
+
+  const slash = (relayer: Keypair, amount: number, authority = pool.upgradeAuthority) =>
+    pool.program.methods
+      .slashRelayer(new BN(amount))
+      .accountsPartial({ relayer: relayerAddress(relayer), authority: authority.publicKey })
+      .signers([authority])
+      .rpc();
+
+  const withdraw = (relayer: Keypair) =>
+    pool.program.methods
+      .withdrawRelayerStake()
+      .accountsPartial({ authority: relayer.publicKey })
+      .signers([relayer])
+      .rpc();
+
+  before(async () => {
+    pool = await startInitializedPool({ accounts: relayers.map((relayer) => fundedAccount(relayer.publicKey)) });
+  });
+
+  it("registers a relayer with its stake, endpoint and fee rate", async () => {
+    await expectError(register(honest, 50, MIN_STAKE - 1), "InsufficientRelayerStake");
+    await expectError(register(honest, 10_001, MIN_STAKE), "InvalidFeeRate");
+
+    const before = await lamports(pool, honest.publicKey);
+    await register(honest, 50, MIN_STAKE);
+    await register(misbehaving, 100, MIN_STAKE);
+
+    const relayer = await pool.program.account.relayerAccount.fetch(relayerAddress(honest));
+    expect(relayer.authority.equals(honest.publicKey)).to.be.true;
+    expect(relayer.stake.toNumber()).to.equal(MIN_STAKE);
+    expect(relayer.feeRate).to.equal(50);
+    expect(relayer.endpoint).to.equal("https://relay.example.org");
+    expect(relayer.jailed).to.be.false;
+    expect(before - (await lamports(pool, honest.publicKey))).to.be.greaterThan(MIN_STAKE);
+  });
+
+  it("lets a relayer update its own advertised fee rate only", async () => {
+    await pool.program.methods
+      .updateRelayer("https://relay2.example.org", 30)
+      .accountsPartial({ authority: honest.publicKey })
+      .signers([honest])
+      .rpc();
+    const relayer = await pool.program.account.relayerAccount.fetch(relayerAddress(honest));
+    expect(relayer.feeRate).to.equal(30);
+    expect(relayer.endpoint).to.equal("https://relay2.example.org");
+
+    // the relayer PDA is derived from the signer, so an outsider can't address it
+    await expectFailure(
+      pool.program.methods
+        .updateRelayer("https://evil.example.org", 10_000)
+        .accountsPartial({ relayer: relayerAddress(honest), authority: outsider.publicKey })
+        .signers([outsider])
+        .rpc()
+    );
+  });
+
+  it("only lets the pool authority slash, and jails the slashed relayer", async () => {
+    await expectError(slash(misbehaving, LAMPORTS_PER_SOL, outsider), "Unauthorized");
+    await expectError(slash(misbehaving, MIN_STAKE + 1), "InvalidSlashAmount");
+
+    const vaultBefore = await lamports(pool, feeVault());
+    await slash(misbehaving, 4 * LAMPORTS_PER_SOL);
+
+    const relayer = await pool.program.account.relayerAccount.fetch(relayerAddress(misbehaving));
+    expect(relayer.jailed).to.be.true;
+    expect(relayer.stake.toNumber()).to.equal(MIN_STAKE - 4 * LAMPORTS_PER_SOL);
+    expect((await lamports(pool, feeVault())) - vaultBefore).to.equal(4 * LAMPORTS_PER_SOL);
+  });
+
+  it("keeps the stake locked until the unbonding period has elapsed", async () => {
+    for (const relayer of [honest, misbehaving]) {
+      await pool.program.methods
+        .beginRelayerUnbond()
+        .accountsPartial({ authority: relayer.publicKey })
+        .signers([relayer])
+        .rpc();
+    }
+    await expectError(withdraw(honest), "RelayerUnbondingActive");
+
+    // still slashable while unbonding
+    await slash(honest, LAMPORTS_PER_SOL);
+    await pool.program.methods
+      .unjailRelayer()
+      .accountsPartial({ relayer: relayerAddress(honest), authority: pool.upgradeAuthority.publicKey })
+      .signers([pool.upgradeAuthority])
+      .rpc();
+
+    await warpSlots(pool, RELAYER_UNBONDING_SLOTS);
+
+    // a jailed relayer stays locked
+    await expectError(withdraw(misbehaving), "RelayerJailed");
+
+    const before = await lamports(pool, honest.publicKey);
+    await withdraw(honest);
+    expect((await lamports(pool, honest.publicKey)) - before).to.be.greaterThan(MIN_STAKE - LAMPORTS_PER_SOL);
+    expect(await pool.context.banksClient.getAccount(relayerAddress(honest))).to.be.null;
+  });
+
+  it("raises the stake needed to register through the relayer config", async () => {
+    await pool.program.methods
+      .updateRelayerConfig(new BN(2 * MIN_STAKE), true, new BN(LAMPORTS_PER_SOL / 100))
+      .accountsPartial({ authority: pool.upgradeAuthority.publicKey })
+      .signers([pool.upgradeAuthority])
+      .rpc();
+    await expectError(register(outsider, 50, MIN_STAKE), "InsufficientRelayerStake");
+
+    const globalConfig = await pool.program.account.globalConfig.fetch(pda(pool.program, Buffer.from("global_config")));
+    expect(globalConfig.requireRegisteredRelayer).to.be.true;
+    expect(globalConfig.maxRelayerTransferFee.toNumber()).to.equal(LAMPORTS_PER_SOL / 100);
+  });
+});
@@ -0,0 +1,59 @@
+pragma circom 2.0.0;
+
+include "circomlib/circuits/bitify.circom";
+include "circomlib/circuits/comparators.circom";
+include "./merkleProof.circom";
+
+// Proves that writing the first `count` of `leaves` at consecutive indices from
+// `startIndex` moves the tree from `oldRoot` to `newRoot`.
+// Each leaf takes the place of an empty (zero) leaf: one Merkle path per leaf must lead
+// to the running root with a zero leaf and to the next root with the new leaf.
+// Leaves past `count` must be zero and leave the root unchanged.
+template BatchInsert(levels, batchSize) {
+    signal input oldRoot;
+    signal input newRoot;
+    signal input startIndex;
+    signal input count;
+    signal input leaves[batchSize];
+
+    signal input pathElements[batchSize][levels];
+
+    component countBits = Num2Bits(8);
+    countBits.in <== count;
+    component countInRange = LessEqThan(8);
+    countInRange.in[0] <== count;
+    countInRange.in[1] <== batchSize;
+    countInRange.out === 1;
+
+    component inBatch[batchSize];
+    component emptyPath[batchSize];
+    component filledPath[batchSize];
+    signal roots[batchSize + 1];
+    roots[0] <== oldRoot;
+
+    for (var k = 0; k < batchSize; k++) {
+        inBatch[k] = LessThan(8);
+        inBatch[k].in[0] <== k;
+        inBatch[k].in[1] <== count;
+        leaves[k] * (1 - inBatch[k].out) === 0;
+
+        emptyPath[k] = MerkleProof(levels);
+        emptyPath[k].leaf <== 0;
+        emptyPath[k].pathIndices <== startIndex + k;
+
+        filledPath[k] = MerkleProof(levels);
+        filledPath[k].leaf <== leaves[k];
+        filledPath[k].pathIndices <== startIndex + k;
+
+        for (var i = 0; i < levels; i++) {
+            emptyPath[k].pathElements[i] <== pathElements[k][i];
+            filledPath[k].pathElements[i] <== pathElements[k][i];
+        }
+
+        // The slot must be empty under the running root, unless the leaf is padding
+        (emptyPath[k].root - roots[k]) * inBatch[k].out === 0;
+        roots[k + 1] <== roots[k] + inBatch[k].out * (filledPath[k].root - roots[k]);
+    }
+
+    newRoot === roots[batchSize];
+}
@@ -0,0 +1,6 @@
+pragma circom 2.0.0;
+
+include "./batchInsert.circom";
+
+// Entry point for inserting queued commitments: 26 levels, up to 8 leaves per batch.
+component main {public [oldRoot, newRoot, startIndex, count, leaves]} = BatchInsert(26, 8);
@@ -0,0 +1,64 @@
+pragma circom 2.0.0;
+
+include "circomlib/circuits/poseidon.circom";
+include "./merkleProof.circom";
+include "./keypair.circom";
+
+/*
+File note structure:
+{
+    fileHash, // content hash of the encrypted file, reduced into the field
+    pubkey,
+    blinding  // random number
+}
+
+commitment = hash(fileHash, pubKey, blinding)
+nullifier = hash(commitment, merklePath, sign(privKey, commitment, merklePath))
+*/
+
+// Proves knowledge of the private key behind a recipient commitment in the file registry
+// without revealing which commitment, and derives its nullifier so a file is claimed once.
+// The proof is bound to `claimer`, the wallet submitting the claim, so it can't be taken
+// from a pending transaction and submitted by someone else.
+template FileClaim(levels) {
+    signal input root;
+    signal input nullifier;
+    signal input claimer;
+
+    signal input fileHash;
+    signal input privateKey;
+    signal input blinding;
+    signal input pathIndices;
+    signal input pathElements[levels];
+
+    component keypair = Keypair();
+    keypair.privateKey <== privateKey;
+
+    component commitmentHasher = Poseidon(3);
+    commitmentHasher.inputs[0] <== fileHash;
+    commitmentHasher.inputs[1] <== keypair.publicKey;
+    commitmentHasher.inputs[2] <== blinding;
+
+    component tree = MerkleProof(levels);
+    tree.leaf <== commitmentHasher.out;
+    tree.pathIndices <== pathIndices;
+    for (var i = 0; i < levels; i++) {
+        tree.pathElements[i] <== pathElements[i];
+    }
+    tree.root === root;
+
+    component signature = Signature();
+    signature.privateKey <== privateKey;
+    signature.commitment <== commitmentHasher.out;
+    signature.merklePath <== pathIndices;
+
+    component nullifierHasher = Poseidon(3);
+    nullifierHasher.inputs[0] <== commitmentHasher.out;
+    nullifierHasher.inputs[1] <== pathIndices;
+    nullifierHasher.inputs[2] <== signature.out;
+    nullifierHasher.out === nullifier;
+
+    // Constrain the claimer so it can't be changed without invalidating the proof
+    signal claimerSquare;
+    claimerSquare <== claimer * claimer;
+}
@@ -0,0 +1,6 @@
+pragma circom 2.0.0;
+
+include "./fileClaim.circom";
+
+// Entry point for file claims against the 26-level file registry.
+component main {public [root, nullifier, claimer]} = FileClaim(26);
@@ -0,0 +1,82 @@
+pragma circom 2.0.0;
+
+include "./transaction.circom";
+
+/*
+File purchase: a JoinSplit that pays for access to a listed file.
+
+Same JoinSplit as Transaction, plus:
+- the first output is the seller's payment: outAmount[0] == price and
+  outPubkey[0] == sellerPubkey, both taken from the on-chain listing;
+- claimCommitment = hash(fileHash, claimPubkey, claimBlinding) is a file note for
+  the listed file under a key only the buyer knows, inserted into the file registry
+  so it can later be claimed with FileClaim.
+*/
+template FilePurchase(levels, nIns, nOuts) {
+    signal input root;
+    signal input publicAmount;
+    signal input extDataHash;
+    signal input mintAddress;
+
+    // data for transaction inputs
+    signal input inputNullifier[nIns];
+    signal input inAmount[nIns];
+    signal input inPrivateKey[nIns];
+    signal input inBlinding[nIns];
+    signal input inPathIndices[nIns];
+    signal input inPathElements[nIns][levels];
+
+    // data for transaction outputs
+    signal input outputCommitment[nOuts];
+    signal input outAmount[nOuts];
+    signal input outPubkey[nOuts];
+    signal input outBlinding[nOuts];
+
+    // listing terms and the claim released to the buyer. Public signals are ordered as
+    // declared, so these come after the transaction's, as the program passes them
+    signal input sellerPubkey;
+    signal input price;
+    signal input fileHash;
+    signal input claimCommitment;
+
+    // data for the buyer's claim
+    signal input claimPrivateKey;
+    signal input claimBlinding;
+
+    component transaction = Transaction(levels, nIns, nOuts);
+    transaction.root <== root;
+    transaction.publicAmount <== publicAmount;
+    transaction.extDataHash <== extDataHash;
+    transaction.mintAddress <== mintAddress;
+
+    for (var tx = 0; tx < nIns; tx++) {
+        transaction.inputNullifier[tx] <== inputNullifier[tx];
+        transaction.inAmount[tx] <== inAmount[tx];
+        transaction.inPrivateKey[tx] <== inPrivateKey[tx];
+        transaction.inBlinding[tx] <== inBlinding[tx];
+        transaction.inPathIndices[tx] <== inPathIndices[tx];
+        for (var i = 0; i < levels; i++) {
+            transaction.inPathElements[tx][i] <== inPathElements[tx][i];
+        }
+    }
+
+    for (var tx = 0; tx < nOuts; tx++) {
+        transaction.outputCommitment[tx] <== outputCommitment[tx];
+        transaction.outAmount[tx] <== outAmount[tx];
+        transaction.outPubkey[tx] <== outPubkey[tx];
+        transaction.outBlinding[tx] <== outBlinding[tx];
+    }
+
+    // the first output pays the seller the listed price
+    outAmount[0] === price;
+    outPubkey[0] === sellerPubkey;
+
+    component claimKeypair = Keypair();
+    claimKeypair.privateKey <== claimPrivateKey;
+
+    component claimHasher = Poseidon(3);
+    claimHasher.inputs[0] <== fileHash;
+    claimHasher.inputs[1] <== claimKeypair.publicKey;
+    claimHasher.inputs[2] <== claimBlinding;
+    claimHasher.out === claimCommitment;
+}
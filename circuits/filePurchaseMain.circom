@@ -0,0 +1,6 @@
+pragma circom 2.0.0;
+
+include "./filePurchase.circom";
+
+// Entry point for file purchases: 26 levels, 2 inputs, 2 outputs.
+component main {public [root, publicAmount, extDataHash, inputNullifier, outputCommitment, sellerPubkey, price, fileHash, claimCommitment]} = FilePurchase(26, 2, 2);
@@ -0,0 +1,114 @@
+const { MerkleTree, buildHasher, expectWitnessFails, loadCircuit, LEVELS } = require("./helpers");
+
+const BATCH_SIZE = 8;
+
+// Roots the program's MerkleTree::append reaches with leaves 1, 2, 3, ... (see
+// append_matches_batch_insert_fixture in anchor/programs/txnsfr/src/merkle_tree.rs)
+const ROOT_AFTER_3 = BigInt("0x13e909f5ca5ba036819bf558a9011d322ecb2983bf5703a8cbc4717626b5547a");
+const ROOT_AFTER_11 = BigInt("0x2168eda286fbcaa8bfba3a19078478c9a637ea78441a8a094150de6fe4df235b");
+
+describe("batchInsertMain", function () {
+  let circuit;
+  let hash;
+
+  before(async function () {
+    circuit = await loadCircuit("batchInsertMain.circom");
+    hash = await buildHasher();
+  });
+
+  // a tree holding `existing` leaves 1, 2, 3, ... and the input that inserts the next
+  // `count` of them as one batch, with paths taken from the tree as it fills up
+  function batch(existing, count) {
+    const tree = new MerkleTree(hash);
+    for (let i = 1n; i <= BigInt(existing); i++) {
+      tree.insert(i);
+    }
+    const oldRoot = tree.root();
+    const startIndex = BigInt(existing);
+
+    const leaves = new Array(BATCH_SIZE).fill(0n);
+    const pathElements = [];
+    for (let k = 0; k < BATCH_SIZE; k++) {
+      if (k < count) {
+        pathElements.push(tree.path(existing + k));
+        leaves[k] = startIndex + BigInt(k) + 1n;
+        tree.insert(leaves[k]);
+      } else {
+        pathElements.push(new Array(LEVELS).fill(0n));
+      }
+    }
+
+    return {
+      tree,
+      input: { oldRoot, newRoot: tree.root(), startIndex, count: BigInt(count), leaves, pathElements },
+    };
+  }
+
+  it("moves the tree to the root of appending the leaves one by one", async function () {
+    const { tree, input } = batch(3, BATCH_SIZE);
+    if (input.oldRoot !== ROOT_AFTER_3 || tree.root() !== ROOT_AFTER_11) {
+      throw new Error("test tree does not match the program's MerkleTree");
+    }
+
+    const witness = await circuit.calculateWitness(input, true);
+    await circuit.checkConstraints(witness);
+  });
+
+  it("accepts a partial batch padded with zero leaves", async function () {
+    const { input } = batch(6, 3);
+    const witness = await circuit.calculateWitness(input, true);
+    await circuit.checkConstraints(witness);
+  });
+
+  it("accepts an empty batch that keeps the root", async function () {
+    const { input } = batch(4, 0);
+    if (input.newRoot !== input.oldRoot) {
+      throw new Error("an empty batch must keep the root");
+    }
+    await circuit.calculateWitness(input, true);
+  });
+
+  it("rejects a new root other than the sequential one", async function () {
+    const { input } = batch(3, BATCH_SIZE);
+    await expectWitnessFails(circuit, { ...input, newRoot: ROOT_AFTER_3 });
+  });
+
+  it("rejects leaves in a different order", async function () {
+    const { input } = batch(3, BATCH_SIZE);
+    const leaves = input.leaves.slice();
+    [leaves[0], leaves[1]] = [leaves[1], leaves[0]];
+    await expectWitnessFails(circuit, { ...input, leaves });
+  });
+
+  it("rejects non-zero padding past the count", async function () {
+    const { input } = batch(6, 3);
+    const leaves = input.leaves.slice();
+    leaves[5] = 99n;
+    await expectWitnessFails(circuit, { ...input, leaves });
+  });
+
+  it("rejects overwriting an occupied leaf", async function () {
+    // claim the batch starts one slot early, on top of the last existing leaf
+    const tree = new MerkleTree(hash);
+    tree.insert(1n);
+    tree.insert(2n);
+    const oldRoot = tree.root();
+    const pathElements = [tree.path(1)];
+    for (let k = 1; k < BATCH_SIZE; k++) {
+      pathElements.push(new Array(LEVELS).fill(0n));
+    }
+    await expectWitnessFails(circuit, {
+      oldRoot,
+      newRoot: oldRoot,
+      startIndex: 1n,
+      count: 1n,
+      leaves: [2n, 0n, 0n, 0n, 0n, 0n, 0n, 0n],
+      pathElements,
+    });
+  });
+
+  it("rejects a count above the batch size", async function () {
+    const { input } = batch(3, BATCH_SIZE);
+    await expectWitnessFails(circuit, { ...input, count: BigInt(BATCH_SIZE + 1) });
+  });
+});
@@ -0,0 +1,80 @@
+const path = require("path");
+const { buildPoseidon } = require("circomlibjs");
+const wasmTester = require("circom_tester").wasm;
+
+const LEVELS = 26;
+
+// compiles a circuit entry point, resolving circomlib includes from node_modules
+async function loadCircuit(file) {
+  return wasmTester(path.join(__dirname, "..", file), {
+    include: path.join(__dirname, "..", "node_modules"),
+  });
+}
+
+async function buildHasher() {
+  const poseidon = await buildPoseidon();
+  return (...inputs) => poseidon.F.toObject(poseidon(inputs));
+}
+
+// Poseidon Merkle tree with zero leaves, matching the program's MerkleTree
+class MerkleTree {
+  constructor(hash, levels = LEVELS) {
+    this.hash = hash;
+    this.levels = levels;
+    this.zeros = [0n];
+    for (let i = 0; i < levels; i++) {
+      this.zeros.push(hash(this.zeros[i], this.zeros[i]));
+    }
+    this.leaves = [];
+  }
+
+  insert(leaf) {
+    this.leaves.push(leaf);
+    return this.leaves.length - 1;
+  }
+
+  // nodes of every layer, from the leaves up to the root
+  layers() {
+    const layers = [this.leaves.slice()];
+    for (let level = 0; level < this.levels; level++) {
+      const below = layers[level];
+      const layer = [];
+      for (let i = 0; i < below.length; i += 2) {
+        const right = i + 1 < below.length ? below[i + 1] : this.zeros[level];
+        layer.push(this.hash(below[i], right));
+      }
+      layers.push(layer);
+    }
+    return layers;
+  }
+
+  root() {
+    const top = this.layers()[this.levels];
+    return top.length ? top[0] : this.zeros[this.levels];
+  }
+
+  path(index) {
+    const layers = this.layers();
+    const elements = [];
+    for (let level = 0; level < this.levels; level++) {
+      const sibling = (index >> level) ^ 1;
+      const node = layers[level][sibling];
+      elements.push(node === undefined ? this.zeros[level] : node);
+    }
+    return elements;
+  }
+}
+
+async function expectWitnessFails(circuit, input) {
+  let failed = false;
+  try {
+    await circuit.calculateWitness(input, true);
+  } catch (err) {
+    failed = true;
+  }
+  if (!failed) {
+    throw new Error("expected the witness to violate a constraint");
+  }
+}
+
+module.exports = { LEVELS, MerkleTree, buildHasher, expectWitnessFails, loadCircuit };
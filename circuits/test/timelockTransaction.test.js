@@ -0,0 +1,142 @@
+const { MerkleTree, buildHasher, expectWitnessFails, loadCircuit, LEVELS } = require("./helpers");
+
+const MINT = 1n;
+const AMOUNT = 1_000_000n;
+const EXPIRY = 5_000n;
+
+describe("timelockTransaction2", function () {
+  let circuit;
+  let hash;
+
+  const recipientKey = 11n;
+  const senderKey = 22n;
+  const dummyKey = 33n;
+
+  before(async function () {
+    circuit = await loadCircuit("timelockTransaction2.circom");
+    hash = await buildHasher();
+  });
+
+  // a transaction spending one locked note, plus a zero-amount dummy input
+  function lockedSpend({ refund, spenderKey, minSlot, maxSlot, hashlock = 0n, preimage = 0n }) {
+    const recipientPubkey = hash(recipientKey);
+    const senderPubkey = hash(senderKey);
+    const lockHash = hash(recipientPubkey, senderPubkey, EXPIRY, hashlock);
+
+    const tree = new MerkleTree(hash);
+    tree.insert(hash(7n, 7n)); // some earlier note
+    const blinding = 123n;
+    const commitment = hash(AMOUNT, lockHash, blinding, MINT);
+    const index = tree.insert(commitment);
+
+    const dummyBlinding = 456n;
+    const dummyCommitment = hash(0n, hash(dummyKey), dummyBlinding, MINT);
+    const dummySignature = hash(dummyKey, dummyCommitment, 0n);
+
+    const outPubkey = hash(spenderKey);
+    const outBlinding = 789n;
+
+    return {
+      root: tree.root(),
+      publicAmount: 0n,
+      extDataHash: 42n,
+      mintAddress: MINT,
+      minSlot,
+      maxSlot,
+
+      inputNullifier: [
+        hash(commitment, BigInt(index), lockHash),
+        hash(dummyCommitment, 0n, dummySignature),
+      ],
+      inAmount: [AMOUNT, 0n],
+      inPrivateKey: [spenderKey, dummyKey],
+      inBlinding: [blinding, dummyBlinding],
+      inPathIndices: [BigInt(index), 0n],
+      inPathElements: [tree.path(index), new Array(LEVELS).fill(0n)],
+
+      inLocked: [1n, 0n],
+      inRefund: [refund ? 1n : 0n, 0n],
+      inRecipientPubkey: [recipientPubkey, 0n],
+      inSenderPubkey: [senderPubkey, 0n],
+      inExpiry: [EXPIRY, 0n],
+      inHashlock: [hashlock, 0n],
+      inPreimage: [preimage, 0n],
+
+      outputCommitment: [hash(AMOUNT, outPubkey, outBlinding, MINT), hash(0n, outPubkey, 0n, MINT)],
+      outAmount: [AMOUNT, 0n],
+      outPubkey: [outPubkey, outPubkey],
+      outBlinding: [outBlinding, 0n],
+    };
+  }
+
+  async function expectWitness(input) {
+    const witness = await circuit.calculateWitness(input, true);
+    await circuit.checkConstraints(witness);
+  }
+
+  it("lets the recipient claim before expiry", async function () {
+    await expectWitness(
+      lockedSpend({ refund: false, spenderKey: recipientKey, minSlot: 4_000n, maxSlot: EXPIRY - 1n })
+    );
+  });
+
+  it("rejects a claim whose window reaches the expiry", async function () {
+    await expectWitnessFails(
+      circuit,
+      lockedSpend({ refund: false, spenderKey: recipientKey, minSlot: 4_000n, maxSlot: EXPIRY })
+    );
+  });
+
+  it("rejects a claim by the sender", async function () {
+    await expectWitnessFails(
+      circuit,
+      lockedSpend({ refund: false, spenderKey: senderKey, minSlot: 4_000n, maxSlot: EXPIRY - 1n })
+    );
+  });
+
+  it("lets the sender refund from expiry on", async function () {
+    await expectWitness(
+      lockedSpend({ refund: true, spenderKey: senderKey, minSlot: EXPIRY, maxSlot: 2n ** 64n - 1n })
+    );
+  });
+
+  it("rejects a refund whose window starts before expiry", async function () {
+    await expectWitnessFails(
+      circuit,
+      lockedSpend({ refund: true, spenderKey: senderKey, minSlot: EXPIRY - 1n, maxSlot: 2n ** 64n - 1n })
+    );
+  });
+
+  it("rejects a refund by the recipient", async function () {
+    await expectWitnessFails(
+      circuit,
+      lockedSpend({ refund: true, spenderKey: recipientKey, minSlot: EXPIRY, maxSlot: 2n ** 64n - 1n })
+    );
+  });
+
+  it("requires the hashlock preimage to claim", async function () {
+    const preimage = 999n;
+    const hashlock = hash(preimage);
+    const window = { minSlot: 4_000n, maxSlot: EXPIRY - 1n };
+
+    await expectWitness(
+      lockedSpend({ refund: false, spenderKey: recipientKey, hashlock, preimage, ...window })
+    );
+    await expectWitnessFails(
+      circuit,
+      lockedSpend({ refund: false, spenderKey: recipientKey, hashlock, preimage: 1n, ...window })
+    );
+  });
+
+  it("does not need the preimage to refund", async function () {
+    await expectWitness(
+      lockedSpend({
+        refund: true,
+        spenderKey: senderKey,
+        hashlock: hash(999n),
+        minSlot: EXPIRY,
+        maxSlot: 2n ** 64n - 1n,
+      })
+    );
+  });
+});
@@ -0,0 +1,134 @@
+const { MerkleTree, buildHasher, expectWitnessFails, loadCircuit, LEVELS } = require("./helpers");
+
+const MINT = 1n;
+const AMOUNT = 1_000_000n;
+
+describe("transactionAssociation2", function () {
+  let circuit;
+  let hash;
+
+  const depositorKey = 11n;
+  const recipientKey = 22n;
+  const dummyKeys = [33n, 44n];
+
+  before(async function () {
+    circuit = await loadCircuit("transactionAssociation2.circom");
+    hash = await buildHasher();
+  });
+
+  function dummyInput(key, blinding, label) {
+    const commitment = hash(0n, hash(key), blinding, MINT, label);
+    return { commitment, nullifier: hash(commitment, 0n, hash(key, commitment, 0n)) };
+  }
+
+  function outputs(label, amounts) {
+    const outPubkey = hash(recipientKey);
+    const outBlinding = [789n, 790n];
+    return {
+      outputCommitment: amounts.map((amount, i) => hash(amount, outPubkey, outBlinding[i], MINT, label)),
+      outAmount: amounts,
+      outPubkey: [outPubkey, outPubkey],
+      outBlinding,
+    };
+  }
+
+  // a deposit with two dummy inputs, starting the lineage `label`
+  function deposit({ label } = {}) {
+    const dummies = [dummyInput(dummyKeys[0], 1n, 0n), dummyInput(dummyKeys[1], 2n, 0n)];
+    const labelTree = new MerkleTree(hash);
+
+    return {
+      root: new MerkleTree(hash).root(),
+      publicAmount: AMOUNT,
+      extDataHash: 42n,
+      mintAddress: MINT,
+
+      label: label === undefined ? dummies[0].nullifier : label,
+      labelPathIndices: 0n,
+      labelPathElements: labelTree.path(0),
+
+      inputNullifier: dummies.map((dummy) => dummy.nullifier),
+      inAmount: [0n, 0n],
+      inPrivateKey: dummyKeys,
+      inBlinding: [1n, 2n],
+      inPathIndices: [0n, 0n],
+      inPathElements: [new Array(LEVELS).fill(0n), new Array(LEVELS).fill(0n)],
+
+      ...outputs(label === undefined ? dummies[0].nullifier : label, [AMOUNT, 0n]),
+
+      associationRoot: labelTree.root(),
+    };
+  }
+
+  // a transfer spending a note of lineage `label`, plus a zero-amount dummy input
+  function spend({ label, approved, commitment, publicAmount = 0n, outAmounts = [AMOUNT, 0n] }) {
+    const blinding = 123n;
+    const note = commitment || hash(AMOUNT, hash(depositorKey), blinding, MINT, label);
+
+    const tree = new MerkleTree(hash);
+    tree.insert(hash(7n, 7n)); // some earlier note
+    const index = tree.insert(note);
+
+    const labelTree = new MerkleTree(hash);
+    labelTree.insert(5n); // some other approved deposit
+    const labelIndex = labelTree.insert(approved);
+
+    const dummy = dummyInput(dummyKeys[0], 456n, label);
+    const signature = hash(depositorKey, note, BigInt(index));
+
+    return {
+      root: tree.root(),
+      publicAmount,
+      extDataHash: 42n,
+      mintAddress: MINT,
+
+      label,
+      labelPathIndices: BigInt(labelIndex),
+      labelPathElements: labelTree.path(labelIndex),
+
+      inputNullifier: [hash(note, BigInt(index), signature), dummy.nullifier],
+      inAmount: [AMOUNT, 0n],
+      inPrivateKey: [depositorKey, dummyKeys[0]],
+      inBlinding: [blinding, 456n],
+      inPathIndices: [BigInt(index), 0n],
+      inPathElements: [tree.path(index), new Array(LEVELS).fill(0n)],
+
+      ...outputs(label, outAmounts),
+
+      associationRoot: labelTree.root(),
+    };
+  }
+
+  async function expectWitness(input) {
+    const witness = await circuit.calculateWitness(input, true);
+    await circuit.checkConstraints(witness);
+  }
+
+  it("labels a deposit with its first input nullifier", async function () {
+    await expectWitness(deposit());
+  });
+
+  it("rejects a deposit with any other label", async function () {
+    await expectWitnessFails(circuit, deposit({ label: 5n }));
+  });
+
+  it("spends notes whose deposit label is approved", async function () {
+    await expectWitness(spend({ label: 99n, approved: 99n }));
+  });
+
+  it("rejects notes whose deposit label is not approved", async function () {
+    await expectWitnessFails(circuit, spend({ label: 99n, approved: 98n }));
+  });
+
+  it("rejects adding funds to a lineage", async function () {
+    await expectWitnessFails(
+      circuit,
+      spend({ label: 99n, approved: 99n, publicAmount: 1n, outAmounts: [AMOUNT, 1n] })
+    );
+  });
+
+  it("does not open notes created by other circuits", async function () {
+    const unlabelled = hash(AMOUNT, hash(depositorKey), 123n, MINT);
+    await expectWitnessFails(circuit, spend({ label: 99n, approved: 99n, commitment: unlabelled }));
+  });
+});
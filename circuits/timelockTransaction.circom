@@ -0,0 +1,218 @@
+pragma circom 2.0.0;
+
+include "circomlib/circuits/poseidon.circom";
+include "circomlib/circuits/comparators.circom";
+include "./merkleProof.circom";
+include "./keypair.circom";
+
+/*
+Time-locked notes:
+
+A locked note is a normal Utxo whose pubkey is the hash of its lock
+{
+    recipientPubkey,
+    senderPubkey,
+    expiry,    // slot at which the note switches from claimable to refundable
+    hashlock   // Poseidon(preimage) the recipient must also know, or 0 for none
+}
+
+lockHash = hash(recipientPubkey, senderPubkey, expiry, hashlock)
+commitment = hash(amount, lockHash, blinding, mintAddress)
+
+so any transaction can create one by using lockHash as outPubkey. Spending it
+needs this circuit: the recipient before expiry, the sender from expiry on.
+Time is proven against the public window [minSlot, maxSlot], which the program
+checks contains the current slot:
+- claim:  maxSlot < expiry
+- refund: minSlot >= expiry
+
+Both parties must derive the same nullifier, so for locked notes the signature
+is replaced by lockHash, which only the two parties know:
+nullifier = hash(commitment, merklePath, lockHash)
+*/
+template TimelockTransaction(levels, nIns, nOuts) {
+    signal input root;
+    signal input publicAmount;
+    signal input extDataHash;
+    signal input mintAddress;
+
+    // data for transaction inputs
+    signal input inputNullifier[nIns];
+    signal input inAmount[nIns];
+    signal input inPrivateKey[nIns];
+    signal input inBlinding[nIns];
+    signal input inPathIndices[nIns];
+    signal input inPathElements[nIns][levels];
+
+    // lock of each input; ignored when inLocked is 0
+    signal input inLocked[nIns];
+    signal input inRefund[nIns];
+    signal input inRecipientPubkey[nIns];
+    signal input inSenderPubkey[nIns];
+    signal input inExpiry[nIns];
+    signal input inHashlock[nIns];
+    signal input inPreimage[nIns];
+
+    // data for transaction outputs
+    signal input outputCommitment[nOuts];
+    signal input outAmount[nOuts];
+    signal input outPubkey[nOuts];
+    signal input outBlinding[nOuts];
+
+    // Public signals are ordered as declared: the slot window comes after the
+    // transaction's, in the order the program passes them to the verifier
+    signal input minSlot;
+    signal input maxSlot;
+
+    component minSlotCheck = Num2Bits(64);
+    minSlotCheck.in <== minSlot;
+    component maxSlotCheck = Num2Bits(64);
+    maxSlotCheck.in <== maxSlot;
+
+    component inKeypair[nIns];
+    component inSignature[nIns];
+    component inLockHasher[nIns];
+    component inCommitmentHasher[nIns];
+    component inNullifierHasher[nIns];
+    component inTree[nIns];
+    component inCheckRoot[nIns];
+    component inExpiryCheck[nIns];
+    component inBeforeExpiry[nIns];
+    component inAfterExpiry[nIns];
+    component inCheckSpender[nIns];
+    component inHashlockIsZero[nIns];
+    component inPreimageHasher[nIns];
+    component inCheckPreimage[nIns];
+    signal inOwner[nIns];
+    signal inNullifierKey[nIns];
+    signal inSpender[nIns];
+    signal inTimeOk[nIns];
+    signal inClaiming[nIns];
+    signal inNeedsPreimage[nIns];
+    var sumIns = 0;
+
+    // verify correctness of transaction inputs
+    for (var tx = 0; tx < nIns; tx++) {
+        inLocked[tx] * (inLocked[tx] - 1) === 0;
+        inRefund[tx] * (inRefund[tx] - 1) === 0;
+
+        inKeypair[tx] = Keypair();
+        inKeypair[tx].privateKey <== inPrivateKey[tx];
+
+        inLockHasher[tx] = Poseidon(4);
+        inLockHasher[tx].inputs[0] <== inRecipientPubkey[tx];
+        inLockHasher[tx].inputs[1] <== inSenderPubkey[tx];
+        inLockHasher[tx].inputs[2] <== inExpiry[tx];
+        inLockHasher[tx].inputs[3] <== inHashlock[tx];
+
+        // a locked note is owned by its lock hash instead of the spender's key
+        inOwner[tx] <== inKeypair[tx].publicKey + inLocked[tx] * (inLockHasher[tx].out - inKeypair[tx].publicKey);
+
+        inCommitmentHasher[tx] = Poseidon(4);
+        inCommitmentHasher[tx].inputs[0] <== inAmount[tx];
+        inCommitmentHasher[tx].inputs[1] <== inOwner[tx];
+        inCommitmentHasher[tx].inputs[2] <== inBlinding[tx];
+        inCommitmentHasher[tx].inputs[3] <== mintAddress;
+
+        inSignature[tx] = Signature();
+        inSignature[tx].privateKey <== inPrivateKey[tx];
+        inSignature[tx].commitment <== inCommitmentHasher[tx].out;
+        inSignature[tx].merklePath <== inPathIndices[tx];
+
+        inNullifierKey[tx] <== inSignature[tx].out + inLocked[tx] * (inLockHasher[tx].out - inSignature[tx].out);
+
+        inNullifierHasher[tx] = Poseidon(3);
+        inNullifierHasher[tx].inputs[0] <== inCommitmentHasher[tx].out;
+        inNullifierHasher[tx].inputs[1] <== inPathIndices[tx];
+        inNullifierHasher[tx].inputs[2] <== inNullifierKey[tx];
+        inNullifierHasher[tx].out === inputNullifier[tx];
+
+        // the spender is the recipient when claiming and the sender when refunding
+        inSpender[tx] <== inRecipientPubkey[tx] + inRefund[tx] * (inSenderPubkey[tx] - inRecipientPubkey[tx]);
+        inCheckSpender[tx] = ForceEqualIfEnabled();
+        inCheckSpender[tx].in[0] <== inKeypair[tx].publicKey;
+        inCheckSpender[tx].in[1] <== inSpender[tx];
+        inCheckSpender[tx].enabled <== inLocked[tx];
+
+        inExpiryCheck[tx] = Num2Bits(64);
+        inExpiryCheck[tx].in <== inExpiry[tx];
+
+        inBeforeExpiry[tx] = LessThan(64);
+        inBeforeExpiry[tx].in[0] <== maxSlot;
+        inBeforeExpiry[tx].in[1] <== inExpiry[tx];
+
+        inAfterExpiry[tx] = LessThan(64);
+        inAfterExpiry[tx].in[0] <== inExpiry[tx];
+        inAfterExpiry[tx].in[1] <== minSlot + 1;
+
+        inTimeOk[tx] <== inBeforeExpiry[tx].out + inRefund[tx] * (inAfterExpiry[tx].out - inBeforeExpiry[tx].out);
+        inLocked[tx] * (1 - inTimeOk[tx]) === 0;
+
+        // claiming a note with a hashlock also needs its preimage
+        inHashlockIsZero[tx] = IsZero();
+        inHashlockIsZero[tx].in <== inHashlock[tx];
+        inClaiming[tx] <== inLocked[tx] * (1 - inRefund[tx]);
+        inNeedsPreimage[tx] <== inClaiming[tx] * (1 - inHashlockIsZero[tx].out);
+
+        inPreimageHasher[tx] = Poseidon(1);
+        inPreimageHasher[tx].inputs[0] <== inPreimage[tx];
+        inCheckPreimage[tx] = ForceEqualIfEnabled();
+        inCheckPreimage[tx].in[0] <== inPreimageHasher[tx].out;
+        inCheckPreimage[tx].in[1] <== inHashlock[tx];
+        inCheckPreimage[tx].enabled <== inNeedsPreimage[tx];
+
+        inTree[tx] = MerkleProof(levels);
+        inTree[tx].leaf <== inCommitmentHasher[tx].out;
+        inTree[tx].pathIndices <== inPathIndices[tx];
+        for (var i = 0; i < levels; i++) {
+            inTree[tx].pathElements[i] <== inPathElements[tx][i];
+        }
+
+        // check merkle proof only if amount is non-zero
+        inCheckRoot[tx] = ForceEqualIfEnabled();
+        inCheckRoot[tx].in[0] <== root;
+        inCheckRoot[tx].in[1] <== inTree[tx].root;
+        inCheckRoot[tx].enabled <== inAmount[tx];
+
+        sumIns += inAmount[tx];
+    }
+
+    component outCommitmentHasher[nOuts];
+    component outAmountCheck[nOuts];
+    var sumOuts = 0;
+
+    // verify correctness of transaction outputs
+    for (var tx = 0; tx < nOuts; tx++) {
+        outCommitmentHasher[tx] = Poseidon(4);
+        outCommitmentHasher[tx].inputs[0] <== outAmount[tx];
+        outCommitmentHasher[tx].inputs[1] <== outPubkey[tx];
+        outCommitmentHasher[tx].inputs[2] <== outBlinding[tx];
+        outCommitmentHasher[tx].inputs[3] <== mintAddress;
+        outCommitmentHasher[tx].out === outputCommitment[tx];
+
+        // Check that amount fits into 248 bits to prevent overflow
+        outAmountCheck[tx] = Num2Bits(248);
+        outAmountCheck[tx].in <== outAmount[tx];
+
+        sumOuts += outAmount[tx];
+    }
+
+    // check that there are no same nullifiers among all inputs
+    component sameNullifiers[nIns * (nIns - 1) / 2];
+    var index = 0;
+    for (var i = 0; i < nIns - 1; i++) {
+      for (var j = i + 1; j < nIns; j++) {
+          sameNullifiers[index] = IsEqual();
+          sameNullifiers[index].in[0] <== inputNullifier[i];
+          sameNullifiers[index].in[1] <== inputNullifier[j];
+          sameNullifiers[index].out === 0;
+          index++;
+      }
+    }
+
+    // verify amount invariant
+    sumIns + publicAmount === sumOuts;
+
+    // optional safety constraint to make sure extDataHash cannot be changed
+    signal extDataSquare <== extDataHash * extDataHash;
+}
@@ -0,0 +1,6 @@
+pragma circom 2.0.0;
+
+include "./timelockTransaction.circom";
+
+// Entry point for spending time-locked notes: 26 levels, 2 inputs, 2 outputs.
+component main {public [root, publicAmount, extDataHash, inputNullifier, outputCommitment, minSlot, maxSlot]} = TimelockTransaction(26, 2, 2);
@@ -0,0 +1,179 @@
+pragma circom 2.0.0;
+
+include "circomlib/circuits/poseidon.circom";
+include "circomlib/circuits/comparators.circom";
+include "./merkleProof.circom";
+include "./keypair.circom";
+
+/*
+Association-set transaction (Privacy Pools style).
+
+Associated notes carry the label of the deposit they descend from:
+
+commitment = hash(amount, pubKey, blinding, mintAddress, label)
+
+Five inputs instead of four, so no other circuit can create or spend one. A
+deposit made through this circuit, with zero inputs, starts a new lineage
+labelled with its first input nullifier, which is public and can never be used
+again; its dummy inputs are hashed with label 0 so that nullifier doesn't
+depend on the label. Every other associated transaction spends notes of one label and passes
+it on to its outputs, so change and transfers stay in the lineage without any
+curator action.
+
+Curators approve deposits by adding their labels to the association tree, whose
+root is a public input. Spending non-zero inputs proves their label is in it, so
+a withdrawal can show its funds descend from an approved deposit and not from
+flagged ones. Funds from outside the lineage can't be mixed in: outputs may not
+exceed inputs unless the inputs are all zero.
+
+nullifier = hash(commitment, merklePath, sign(privKey, commitment, merklePath))
+*/
+template AssociationTransaction(levels, associationLevels, nIns, nOuts) {
+    signal input root;
+    signal input publicAmount;
+    signal input extDataHash;
+    signal input mintAddress;
+
+    // lineage of every input and output, and its place in the association tree
+    signal input label;
+    signal input labelPathIndices;
+    signal input labelPathElements[associationLevels];
+
+    // data for transaction inputs
+    signal input inputNullifier[nIns];
+    signal input inAmount[nIns];
+    signal input inPrivateKey[nIns];
+    signal input inBlinding[nIns];
+    signal input inPathIndices[nIns];
+    signal input inPathElements[nIns][levels];
+
+    // data for transaction outputs
+    signal input outputCommitment[nOuts];
+    signal input outAmount[nOuts];
+    signal input outPubkey[nOuts];
+    signal input outBlinding[nOuts];
+
+    // Public signals are ordered as declared: the association root comes after the
+    // transaction's, in the order the program passes them to the verifier
+    signal input associationRoot;
+
+    component inKeypair[nIns];
+    component inSignature[nIns];
+    component inCommitmentHasher[nIns];
+    component inNullifierHasher[nIns];
+    component inTree[nIns];
+    component inCheckRoot[nIns];
+    signal inLabel[nIns];
+    var sumIns = 0;
+
+    for (var tx = 0; tx < nIns; tx++) {
+        // Input amounts were range checked when they were created as outputs
+        sumIns += inAmount[tx];
+    }
+
+    component isDeposit = IsZero();
+    isDeposit.in <== sumIns;
+
+    // verify correctness of transaction inputs
+    for (var tx = 0; tx < nIns; tx++) {
+        inKeypair[tx] = Keypair();
+        inKeypair[tx].privateKey <== inPrivateKey[tx];
+
+        inCommitmentHasher[tx] = Poseidon(5);
+        inCommitmentHasher[tx].inputs[0] <== inAmount[tx];
+        inCommitmentHasher[tx].inputs[1] <== inKeypair[tx].publicKey;
+        inCommitmentHasher[tx].inputs[2] <== inBlinding[tx];
+        inCommitmentHasher[tx].inputs[3] <== mintAddress;
+        inLabel[tx] <== label * (1 - isDeposit.out);
+        inCommitmentHasher[tx].inputs[4] <== inLabel[tx];
+
+        inSignature[tx] = Signature();
+        inSignature[tx].privateKey <== inPrivateKey[tx];
+        inSignature[tx].commitment <== inCommitmentHasher[tx].out;
+        inSignature[tx].merklePath <== inPathIndices[tx];
+
+        inNullifierHasher[tx] = Poseidon(3);
+        inNullifierHasher[tx].inputs[0] <== inCommitmentHasher[tx].out;
+        inNullifierHasher[tx].inputs[1] <== inPathIndices[tx];
+        inNullifierHasher[tx].inputs[2] <== inSignature[tx].out;
+        inNullifierHasher[tx].out === inputNullifier[tx];
+
+        inTree[tx] = MerkleProof(levels);
+        inTree[tx].leaf <== inCommitmentHasher[tx].out;
+        inTree[tx].pathIndices <== inPathIndices[tx];
+        for (var i = 0; i < levels; i++) {
+            inTree[tx].pathElements[i] <== inPathElements[tx][i];
+        }
+
+        // check merkle proof only if amount is non-zero
+        inCheckRoot[tx] = ForceEqualIfEnabled();
+        inCheckRoot[tx].in[0] <== root;
+        inCheckRoot[tx].in[1] <== inTree[tx].root;
+        inCheckRoot[tx].enabled <== inAmount[tx];
+    }
+
+    component outCommitmentHasher[nOuts];
+    component outAmountCheck[nOuts];
+    var sumOuts = 0;
+
+    // verify correctness of transaction outputs; they inherit the inputs' label
+    for (var tx = 0; tx < nOuts; tx++) {
+        outCommitmentHasher[tx] = Poseidon(5);
+        outCommitmentHasher[tx].inputs[0] <== outAmount[tx];
+        outCommitmentHasher[tx].inputs[1] <== outPubkey[tx];
+        outCommitmentHasher[tx].inputs[2] <== outBlinding[tx];
+        outCommitmentHasher[tx].inputs[3] <== mintAddress;
+        outCommitmentHasher[tx].inputs[4] <== label;
+        outCommitmentHasher[tx].out === outputCommitment[tx];
+
+        // Check that amount fits into 248 bits to prevent overflow
+        outAmountCheck[tx] = Num2Bits(248);
+        outAmountCheck[tx].in <== outAmount[tx];
+
+        sumOuts += outAmount[tx];
+    }
+
+    // check that there are no same nullifiers among all inputs
+    component sameNullifiers[nIns * (nIns - 1) / 2];
+    var index = 0;
+    for (var i = 0; i < nIns - 1; i++) {
+      for (var j = i + 1; j < nIns; j++) {
+          sameNullifiers[index] = IsEqual();
+          sameNullifiers[index].in[0] <== inputNullifier[i];
+          sameNullifiers[index].in[1] <== inputNullifier[j];
+          sameNullifiers[index].out === 0;
+          index++;
+      }
+    }
+
+    // verify amount invariant
+    sumIns + publicAmount === sumOuts;
+
+    // a deposit starts a lineage named after its first input nullifier
+    component checkNewLabel = ForceEqualIfEnabled();
+    checkNewLabel.in[0] <== label;
+    checkNewLabel.in[1] <== inputNullifier[0];
+    checkNewLabel.enabled <== isDeposit.out;
+
+    // spending one needs its label in the association tree
+    component labelTree = MerkleProof(associationLevels);
+    labelTree.leaf <== label;
+    labelTree.pathIndices <== labelPathIndices;
+    for (var i = 0; i < associationLevels; i++) {
+        labelTree.pathElements[i] <== labelPathElements[i];
+    }
+
+    component checkAssociationRoot = ForceEqualIfEnabled();
+    checkAssociationRoot.in[0] <== associationRoot;
+    checkAssociationRoot.in[1] <== labelTree.root;
+    checkAssociationRoot.enabled <== 1 - isDeposit.out;
+
+    // and brings no funds in from outside the lineage
+    component noNewFunds = LessEqThan(250);
+    noNewFunds.in[0] <== sumOuts;
+    noNewFunds.in[1] <== sumIns;
+    (1 - isDeposit.out) * (1 - noNewFunds.out) === 0;
+
+    // optional safety constraint to make sure extDataHash cannot be changed
+    signal extDataSquare <== extDataHash * extDataHash;
+}
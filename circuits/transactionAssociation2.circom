@@ -0,0 +1,7 @@
+pragma circom 2.0.0;
+
+include "./transactionAssociation.circom";
+
+// Entry point for association-set transactions: 26 levels for both the pool tree and
+// the association tree, 2 inputs, 2 outputs.
+component main {public [root, publicAmount, extDataHash, inputNullifier, outputCommitment, associationRoot]} = AssociationTransaction(26, 26, 2, 2);
@@ -0,0 +1,319 @@
+use crate::crypto::{aead_decrypt, aead_encrypt, NONCE_LEN, TAG_LEN};
+use crate::error::FileError;
+use crate::file::{content_hash, FILE_KEY_LEN};
+use crate::manifest::{ChunkEntry, Manifest, MANIFEST_VERSION};
+use rand::{CryptoRng, RngCore};
+use std::io::{ErrorKind, Read, Write};
+
+/// Plaintext bytes per chunk unless the caller picks another size.
+pub const DEFAULT_CHUNK_SIZE: u32 = 8 * 1024 * 1024;
+
+/// Nonce of the encrypted manifest; chunk nonces never have these leading bytes.
+const MANIFEST_NONCE: [u8; NONCE_LEN] = [0xff; NONCE_LEN];
+
+/// A large file after its chunks have been encrypted and uploaded.
+pub struct ChunkedFile {
+    key: [u8; FILE_KEY_LEN],
+    manifest: Manifest,
+    manifest_blob: Vec<u8>,
+}
+
+impl ChunkedFile {
+    pub fn key(&self) -> &[u8; FILE_KEY_LEN] {
+        &self.key
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// The encrypted manifest, to upload after the chunks. Its Irys ID goes in the file note.
+    pub fn manifest_blob(&self) -> &[u8] {
+        &self.manifest_blob
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.manifest.root()
+    }
+}
+
+/// Encrypts `reader` under a fresh key one chunk at a time, passing each encrypted chunk
+/// to `upload` as soon as it is sealed, so at most one chunk is held in memory.
+///
+/// `upload` returns the Irys ID of the chunk it stored.
+pub fn encrypt_chunked<G, R, U>(
+    rng: &mut G,
+    mut reader: R,
+    chunk_size: u32,
+    mut upload: U,
+) -> Result<ChunkedFile, FileError>
+where
+    G: RngCore + CryptoRng,
+    R: Read,
+    U: FnMut(&[u8]) -> Result<[u8; 32], FileError>,
+{
+    if chunk_size == 0 {
+        return Err(FileError::InvalidManifest);
+    }
+
+    let mut key = [0u8; FILE_KEY_LEN];
+    rng.fill_bytes(&mut key);
+
+    let mut buffer = vec![0u8; chunk_size as usize];
+    let mut chunks = Vec::new();
+    let mut total_len = 0u64;
+
+    loop {
+        let read = read_chunk(&mut reader, &mut buffer)?;
+        // An empty file still gets one (empty) chunk so every manifest has a root
+        if read == 0 && !chunks.is_empty() {
+            break;
+        }
+
+        let index = u32::try_from(chunks.len()).map_err(|_| FileError::InvalidManifest)?;
+        let chunk = aead_encrypt(&key, &chunk_nonce(index), &buffer[..read])?;
+        let irys_id = upload(&chunk)?;
+
+        chunks.push(ChunkEntry {
+            irys_id,
+            len: chunk.len() as u32,
+            tag: chunk[chunk.len() - TAG_LEN..].try_into().expect("ciphertext ends with a tag"),
+            hash: content_hash(&chunk),
+        });
+        total_len += read as u64;
+
+        if read < buffer.len() {
+            break;
+        }
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        chunk_size,
+        total_len,
+        chunks,
+    };
+    let manifest_blob = aead_encrypt(&key, &MANIFEST_NONCE, &manifest.to_bytes())?;
+
+    Ok(ChunkedFile {
+        key,
+        manifest,
+        manifest_blob,
+    })
+}
+
+/// Decrypts a manifest blob and checks it against the root bound in the file note.
+pub fn decrypt_manifest(
+    key: &[u8; FILE_KEY_LEN],
+    manifest_blob: &[u8],
+    root: &[u8; 32],
+) -> Result<Manifest, FileError> {
+    let manifest = Manifest::from_bytes(&aead_decrypt(key, &MANIFEST_NONCE, manifest_blob)?)?;
+    if manifest.root() != *root {
+        return Err(FileError::ContentHashMismatch);
+    }
+    Ok(manifest)
+}
+
+/// Fetches, verifies and decrypts each chunk of `manifest` in order, streaming the
+/// plaintext into `writer`. Returns the number of bytes written.
+pub fn decrypt_chunked<F, W>(
+    key: &[u8; FILE_KEY_LEN],
+    manifest: &Manifest,
+    mut fetch: F,
+    mut writer: W,
+) -> Result<u64, FileError>
+where
+    F: FnMut(&[u8; 32]) -> Result<Vec<u8>, FileError>,
+    W: Write,
+{
+    let last = manifest.chunks.len().saturating_sub(1);
+    let mut written = 0u64;
+
+    for (position, entry) in manifest.chunks.iter().enumerate() {
+        let index = position as u32;
+        let chunk = fetch(&entry.irys_id)?;
+
+        if chunk.len() != entry.len as usize
+            || chunk.len() < TAG_LEN
+            || chunk[chunk.len() - TAG_LEN..] != entry.tag
+            || content_hash(&chunk) != entry.hash
+        {
+            return Err(FileError::ChunkMismatch(index));
+        }
+
+        let plaintext = aead_decrypt(key, &chunk_nonce(index), &chunk)?;
+        if plaintext.len() > manifest.chunk_size as usize
+            || (position != last && plaintext.len() != manifest.chunk_size as usize)
+        {
+            return Err(FileError::ChunkMismatch(index));
+        }
+
+        writer.write_all(&plaintext).map_err(|err| FileError::Io(err.to_string()))?;
+        written += plaintext.len() as u64;
+    }
+
+    if written != manifest.total_len {
+        return Err(FileError::InvalidManifest);
+    }
+    writer.flush().map_err(|err| FileError::Io(err.to_string()))?;
+    Ok(written)
+}
+
+fn chunk_nonce(index: u32) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - 4..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+/// Fills `buffer` from `reader`, stopping early only at end of input.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, FileError> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(FileError::Io(err.to_string())),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use std::collections::HashMap;
+
+    const CHUNK_SIZE: u32 = 1024;
+
+    /// Stand-in for Irys, storing chunks under their content hash.
+    #[derive(Default)]
+    struct Store(HashMap<[u8; 32], Vec<u8>>);
+
+    impl Store {
+        fn upload(&mut self, chunk: &[u8]) -> Result<[u8; 32], FileError> {
+            let id = content_hash(chunk);
+            self.0.insert(id, chunk.to_vec());
+            Ok(id)
+        }
+
+        fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, FileError> {
+            self.0.get(id).cloned().ok_or(FileError::Transport("not found".into()))
+        }
+    }
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn encrypt(store: &mut Store, plaintext: &[u8]) -> ChunkedFile {
+        encrypt_chunked(&mut OsRng, plaintext, CHUNK_SIZE, |chunk| store.upload(chunk)).unwrap()
+    }
+
+    fn decrypt(store: &Store, file: &ChunkedFile, manifest: &Manifest) -> Result<Vec<u8>, FileError> {
+        let mut plaintext = Vec::new();
+        decrypt_chunked(file.key(), manifest, |id| store.fetch(id), &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    // a file of four chunks, the last one short
+    fn four_chunk_file(store: &mut Store) -> ChunkedFile {
+        let file = encrypt(store, &plaintext(3 * CHUNK_SIZE as usize + 100));
+        assert_eq!(file.manifest().chunks.len(), 4);
+        file
+    }
+
+    #[test]
+    fn roundtrips_through_the_manifest() {
+        let sizes = [0, 1, CHUNK_SIZE as usize - 1, CHUNK_SIZE as usize, 2 * CHUNK_SIZE as usize, 5 * CHUNK_SIZE as usize + 17];
+        for len in sizes {
+            let mut store = Store::default();
+            let file = encrypt(&mut store, &plaintext(len));
+            assert_eq!(file.manifest().total_len, len as u64);
+
+            let manifest = decrypt_manifest(file.key(), file.manifest_blob(), &file.root()).unwrap();
+            assert_eq!(&manifest, file.manifest());
+            assert_eq!(decrypt(&store, &file, &manifest).unwrap(), plaintext(len));
+        }
+    }
+
+    #[test]
+    fn rejects_reordered_chunks() {
+        let mut store = Store::default();
+        let file = four_chunk_file(&mut store);
+
+        let mut reordered = file.manifest().clone();
+        reordered.chunks.swap(1, 2);
+        assert_ne!(reordered.root(), file.root());
+        // chunk nonces are their positions, so a reordered list doesn't decrypt even
+        // without the root check
+        assert_eq!(decrypt(&store, &file, &reordered), Err(FileError::DecryptionFailed));
+
+        // nor does a gateway that serves the chunks in the wrong order
+        let mut swapped = Store::default();
+        let chunks = &file.manifest().chunks;
+        swapped.0.insert(chunks[1].irys_id, store.fetch(&chunks[2].irys_id).unwrap());
+        swapped.0.insert(chunks[2].irys_id, store.fetch(&chunks[1].irys_id).unwrap());
+        for chunk in [&chunks[0], &chunks[3]] {
+            swapped.0.insert(chunk.irys_id, store.fetch(&chunk.irys_id).unwrap());
+        }
+        assert_eq!(decrypt(&swapped, &file, file.manifest()), Err(FileError::ChunkMismatch(1)));
+    }
+
+    #[test]
+    fn rejects_truncated_files_and_chunks() {
+        let mut store = Store::default();
+        let file = four_chunk_file(&mut store);
+
+        // dropping the last chunk changes the root, and the length no longer adds up
+        let mut truncated = file.manifest().clone();
+        truncated.chunks.pop();
+        assert_ne!(truncated.root(), file.root());
+        assert_eq!(decrypt(&store, &file, &truncated), Err(FileError::InvalidManifest));
+
+        // a chunk cut short on the gateway
+        let id = file.manifest().chunks[2].irys_id;
+        let mut chunk = store.fetch(&id).unwrap();
+        chunk.truncate(chunk.len() - 1);
+        store.0.insert(id, chunk);
+        assert_eq!(decrypt(&store, &file, file.manifest()), Err(FileError::ChunkMismatch(2)));
+    }
+
+    #[test]
+    fn rejects_duplicated_chunks() {
+        let mut store = Store::default();
+        let file = four_chunk_file(&mut store);
+
+        let mut duplicated = file.manifest().clone();
+        duplicated.chunks[1] = duplicated.chunks[0].clone();
+        assert_ne!(duplicated.root(), file.root());
+        assert_eq!(decrypt(&store, &file, &duplicated), Err(FileError::DecryptionFailed));
+
+        let mut appended = file.manifest().clone();
+        appended.chunks.push(appended.chunks[3].clone());
+        assert_ne!(appended.root(), file.root());
+        assert!(decrypt(&store, &file, &appended).is_err());
+    }
+
+    #[test]
+    fn manifest_is_bound_to_the_note_root() {
+        let mut store = Store::default();
+        let file = four_chunk_file(&mut store);
+        let other = four_chunk_file(&mut store);
+
+        // another file's root, or a tampered manifest blob, is rejected before any chunk is fetched
+        assert_eq!(
+            decrypt_manifest(file.key(), file.manifest_blob(), &other.root()),
+            Err(FileError::ContentHashMismatch)
+        );
+        let mut tampered = file.manifest_blob().to_vec();
+        tampered[20] ^= 1;
+        assert_eq!(decrypt_manifest(file.key(), &tampered, &file.root()), Err(FileError::DecryptionFailed));
+        assert_eq!(
+            decrypt_manifest(other.key(), file.manifest_blob(), &file.root()),
+            Err(FileError::DecryptionFailed)
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::FileError;
+use aes_gcm_siv::aead::Aead;
+use aes_gcm_siv::{Aes256GcmSiv, KeyInit, Nonce};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use hkdf::Hkdf;
+use rand::{CryptoRng, RngCore};
+use sha2::Sha256;
+
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Bytes [`seal`] adds to a plaintext: ephemeral public key, nonce and tag.
+pub const SEAL_OVERHEAD: usize = PUBLIC_KEY_LEN + NONCE_LEN + TAG_LEN;
+
+const NOTE_KEY_INFO: &[u8] = b"txnsfr-note-v1";
+
+/// X25519 keypair used to receive encrypted notes.
+///
+/// This is separate from the shielded (Poseidon) keypair that owns UTXOs, because
+/// Poseidon keys cannot be used for Diffie-Hellman.
+#[derive(Clone)]
+pub struct EncryptionKeypair {
+    secret: [u8; 32],
+    public: [u8; PUBLIC_KEY_LEN],
+}
+
+impl EncryptionKeypair {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+        Self::from_secret(secret)
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let public = MontgomeryPoint::mul_base_clamped(secret).to_bytes();
+        Self { secret, public }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.public
+    }
+
+    /// X25519 with `their_public`, or `None` if it is a low-order point: the shared
+    /// secret would then be all zeros, known to anyone.
+    fn shared_secret(&self, their_public: &[u8; PUBLIC_KEY_LEN]) -> Option<[u8; 32]> {
+        let shared_secret = MontgomeryPoint(*their_public).mul_clamped(self.secret).to_bytes();
+        (shared_secret != [0u8; 32]).then_some(shared_secret)
+    }
+}
+
+/// Encrypts `plaintext` to `recipient_public` with an ephemeral X25519 key.
+///
+/// Layout: `ephemeral_public (32) || nonce (12) || ciphertext || tag (16)`.
+pub fn seal<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    recipient_public: &[u8; PUBLIC_KEY_LEN],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, FileError> {
+    let ephemeral = EncryptionKeypair::generate(rng);
+    let shared_secret = ephemeral
+        .shared_secret(recipient_public)
+        .ok_or(FileError::InvalidPublicKey)?;
+    let key = derive_note_key(&shared_secret, &ephemeral.public, recipient_public);
+
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+    let ciphertext = aead_encrypt(&key, &nonce, plaintext)?;
+
+    Ok([&ephemeral.public[..], &nonce[..], &ciphertext[..]].concat())
+}
+
+/// Seals `plaintext` zero-padded so the ciphertext is exactly `sealed_len` bytes, so
+/// every note sealed to the same length looks alike. Whatever reads the plaintext
+/// must ignore the padding.
+pub fn seal_padded<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    recipient_public: &[u8; PUBLIC_KEY_LEN],
+    plaintext: &[u8],
+    sealed_len: usize,
+) -> Result<Vec<u8>, FileError> {
+    let padded_len = sealed_len
+        .checked_sub(SEAL_OVERHEAD)
+        .filter(|&padded_len| padded_len >= plaintext.len())
+        .ok_or(FileError::NoteTooLong)?;
+
+    let mut padded = plaintext.to_vec();
+    padded.resize(padded_len, 0);
+    seal(rng, recipient_public, &padded)
+}
+
+/// Opens a ciphertext produced by [`seal`] for `keypair`.
+pub fn open(keypair: &EncryptionKeypair, sealed: &[u8]) -> Result<Vec<u8>, FileError> {
+    if sealed.len() < PUBLIC_KEY_LEN + NONCE_LEN + TAG_LEN {
+        return Err(FileError::DecryptionFailed);
+    }
+
+    let (ephemeral_public, rest) = sealed.split_at(PUBLIC_KEY_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let ephemeral_public: [u8; PUBLIC_KEY_LEN] = ephemeral_public
+        .try_into()
+        .map_err(|_| FileError::DecryptionFailed)?;
+
+    let shared_secret = keypair
+        .shared_secret(&ephemeral_public)
+        .ok_or(FileError::DecryptionFailed)?;
+    let key = derive_note_key(&shared_secret, &ephemeral_public, &keypair.public);
+    aead_decrypt(&key, nonce, ciphertext)
+}
+
+pub fn aead_encrypt(key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, FileError> {
+    Aes256GcmSiv::new(key.into())
+        .encrypt(Nonce::from_slice(nonce), plaintext)
+        .map_err(|_| FileError::EncryptionFailed)
+}
+
+pub fn aead_decrypt(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, FileError> {
+    Aes256GcmSiv::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| FileError::DecryptionFailed)
+}
+
+/// HKDF-SHA256 over the X25519 shared secret, salted with both public keys.
+fn derive_note_key(
+    shared_secret: &[u8; 32],
+    ephemeral_public: &[u8; PUBLIC_KEY_LEN],
+    recipient_public: &[u8; PUBLIC_KEY_LEN],
+) -> [u8; 32] {
+    let salt = [&ephemeral_public[..], &recipient_public[..]].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(NOTE_KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn seal_open_roundtrip() {
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+        let other = EncryptionKeypair::generate(&mut OsRng);
+
+        for plaintext in [&b""[..], b"file note", &[7u8; 1000]] {
+            let sealed = seal(&mut OsRng, &recipient.public_key(), plaintext).unwrap();
+            assert_eq!(sealed.len(), SEAL_OVERHEAD + plaintext.len());
+            assert_eq!(open(&recipient, &sealed).unwrap(), plaintext);
+            assert_eq!(open(&other, &sealed), Err(FileError::DecryptionFailed));
+        }
+
+        // the same keypair rebuilt from its secret opens it too
+        let sealed = seal(&mut OsRng, &recipient.public_key(), b"file note").unwrap();
+        let restored = EncryptionKeypair::from_secret(recipient.secret);
+        assert_eq!(open(&restored, &sealed).unwrap(), b"file note");
+    }
+
+    #[test]
+    fn pads_to_the_sealed_length() {
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+        for plaintext in [&b""[..], b"file note", &[7u8; 256 - SEAL_OVERHEAD]] {
+            let sealed = seal_padded(&mut OsRng, &recipient.public_key(), plaintext, 256).unwrap();
+            assert_eq!(sealed.len(), 256);
+
+            let opened = open(&recipient, &sealed).unwrap();
+            assert_eq!(&opened[..plaintext.len()], plaintext);
+            assert!(opened[plaintext.len()..].iter().all(|&byte| byte == 0));
+        }
+
+        assert_eq!(
+            seal_padded(&mut OsRng, &recipient.public_key(), &[7u8; 256 - SEAL_OVERHEAD + 1], 256),
+            Err(FileError::NoteTooLong)
+        );
+        assert_eq!(
+            seal_padded(&mut OsRng, &recipient.public_key(), b"", SEAL_OVERHEAD - 1),
+            Err(FileError::NoteTooLong)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertexts() {
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+        let sealed = seal(&mut OsRng, &recipient.public_key(), b"file note").unwrap();
+
+        // ephemeral key, nonce, ciphertext and tag
+        for position in [0, PUBLIC_KEY_LEN, PUBLIC_KEY_LEN + NONCE_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[position] ^= 1;
+            assert_eq!(open(&recipient, &tampered), Err(FileError::DecryptionFailed));
+        }
+        for len in [0, SEAL_OVERHEAD - 1, sealed.len() - 1] {
+            assert_eq!(open(&recipient, &sealed[..len]), Err(FileError::DecryptionFailed));
+        }
+    }
+
+    #[test]
+    fn rejects_low_order_public_keys() {
+        // the all-zero point, the point of order 2 and one of order 8 all give an
+        // all-zero shared secret
+        let mut order_two = [0u8; 32];
+        order_two[0] = 1;
+        let mut order_eight = [0u8; 32];
+        order_eight[..4].copy_from_slice(&[0xe0, 0xeb, 0x7a, 0x7c]);
+        order_eight[4..].copy_from_slice(&[
+            0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4, 0x6a, 0xda, 0x09, 0x8d, 0xeb,
+            0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49, 0xb8, 0x00,
+        ]);
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+
+        for low_order in [[0u8; 32], order_two, order_eight] {
+            assert_eq!(seal(&mut OsRng, &low_order, b"file note"), Err(FileError::InvalidPublicKey));
+
+            // a sender can't force a known key on the recipient either
+            let mut sealed = seal(&mut OsRng, &recipient.public_key(), b"file note").unwrap();
+            sealed[..PUBLIC_KEY_LEN].copy_from_slice(&low_order);
+            assert_eq!(open(&recipient, &sealed), Err(FileError::DecryptionFailed));
+        }
+    }
+}
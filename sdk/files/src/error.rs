@@ -0,0 +1,37 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FileError {
+    #[error("Encryption failed")]
+    EncryptionFailed,
+
+    #[error("Decryption failed: wrong key or tampered ciphertext")]
+    DecryptionFailed,
+
+    #[error("Encrypted file blob is too short")]
+    InvalidBlob,
+
+    #[error("Recipient public key is a low-order point")]
+    InvalidPublicKey,
+
+    #[error("Note payload has an invalid length or kind")]
+    InvalidNote,
+
+    #[error("Note does not fit in the encrypted output length")]
+    NoteTooLong,
+
+    #[error("Downloaded file does not match the content hash")]
+    ContentHashMismatch,
+
+    #[error("Manifest is malformed or has an unsupported version")]
+    InvalidManifest,
+
+    #[error("Chunk {0} does not match the manifest")]
+    ChunkMismatch(u32),
+
+    #[error("I/O error: {0}")]
+    Io(String),
+
+    #[error("Transport error: {0}")]
+    Transport(String),
+}
@@ -0,0 +1,61 @@
+use crate::crypto::{aead_decrypt, aead_encrypt, NONCE_LEN};
+use crate::error::FileError;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+pub const FILE_KEY_LEN: usize = 32;
+
+/// A file encrypted under a fresh symmetric key, ready to upload to Irys.
+///
+/// The blob layout is `nonce (12) || ciphertext || tag (16)`, and the content hash
+/// is the SHA-256 of the whole blob, so a downloader can check it before decrypting.
+pub struct EncryptedFile {
+    key: [u8; FILE_KEY_LEN],
+    blob: Vec<u8>,
+    content_hash: [u8; 32],
+}
+
+impl EncryptedFile {
+    pub fn encrypt<R: RngCore + CryptoRng>(rng: &mut R, plaintext: &[u8]) -> Result<Self, FileError> {
+        let mut key = [0u8; FILE_KEY_LEN];
+        rng.fill_bytes(&mut key);
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let ciphertext = aead_encrypt(&key, &nonce, plaintext)?;
+        let blob = [&nonce[..], &ciphertext[..]].concat();
+        let content_hash = content_hash(&blob);
+
+        Ok(Self {
+            key,
+            blob,
+            content_hash,
+        })
+    }
+
+    pub fn key(&self) -> &[u8; FILE_KEY_LEN] {
+        &self.key
+    }
+
+    pub fn blob(&self) -> &[u8] {
+        &self.blob
+    }
+
+    pub fn content_hash(&self) -> [u8; 32] {
+        self.content_hash
+    }
+}
+
+pub fn content_hash(blob: &[u8]) -> [u8; 32] {
+    Sha256::digest(blob).into()
+}
+
+/// Decrypts a blob produced by [`EncryptedFile::encrypt`].
+pub fn decrypt_blob(key: &[u8; FILE_KEY_LEN], blob: &[u8]) -> Result<Vec<u8>, FileError> {
+    if blob.len() < NONCE_LEN {
+        return Err(FileError::InvalidBlob);
+    }
+
+    let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+    aead_decrypt(key, nonce, ciphertext)
+}
@@ -0,0 +1,49 @@
+//! Private file transfers over the txnsfr shielded pool.
+//!
+//! A file is encrypted under a fresh symmetric key and uploaded to Irys. The key,
+//! the Irys transaction ID and the content hash travel to the recipient inside a
+//! [`FileNote`], encrypted into the `encrypted_output` of a zero-value UTXO in a
+//! normal `transact`. The handoff therefore inherits the pool's unlinkability.
+//!
+//! ```rust,ignore
+//! // sender
+//! let encrypted = EncryptedFile::encrypt(&mut rng, &plaintext)?;
+//! let irys_id = upload(encrypted.blob())?;
+//! let note = FileNote::new(&mut rng, irys_id, &encrypted);
+//! let encrypted_output = note.encrypt(&mut rng, &recipient_encryption_key)?;
+//!
+//! // recipient
+//! for (index, note) in scan(&keypair, outputs) {
+//!     let blob = fetch(&note.irys_id)?;
+//!     let plaintext = note.decrypt_file(&blob)?;
+//! }
+//! ```
+//!
+//! Files too large for one upload are split with [`encrypt_chunked`]: each encrypted
+//! chunk is uploaded on its own and listed in a [`Manifest`], which is itself encrypted
+//! and uploaded. The note then carries the manifest's Irys ID and root instead.
+//!
+//! ```rust,ignore
+//! let file = encrypt_chunked(&mut rng, reader, DEFAULT_CHUNK_SIZE, |chunk| upload(chunk))?;
+//! let manifest_id = upload(file.manifest_blob())?;
+//! let note = FileNote::new_chunked(&mut rng, manifest_id, &file);
+//!
+//! let manifest = note.decrypt_manifest(&fetch(&note.irys_id)?)?;
+//! decrypt_chunked(&note.file_key, &manifest, |id| fetch(id), writer)?;
+//! ```
+
+pub mod chunked;
+pub mod crypto;
+pub mod error;
+pub mod file;
+pub mod manifest;
+pub mod note;
+pub mod scanner;
+
+pub use chunked::{decrypt_chunked, encrypt_chunked, ChunkedFile, DEFAULT_CHUNK_SIZE};
+pub use crypto::EncryptionKeypair;
+pub use error::FileError;
+pub use file::{EncryptedFile, FILE_KEY_LEN};
+pub use manifest::{ChunkEntry, Manifest, MANIFEST_VERSION};
+pub use note::{FileNote, ENCRYPTED_OUTPUT_LEN, NOTE_KIND_CHUNKED_FILE, NOTE_KIND_FILE};
+pub use scanner::scan;
@@ -0,0 +1,193 @@
+use crate::crypto::TAG_LEN;
+use crate::error::FileError;
+use sha2::{Digest, Sha256};
+
+pub const MANIFEST_VERSION: u8 = 1;
+
+/// version (1) || chunk_size (4) || total_len (8) || chunk count (4)
+const HEADER_LEN: usize = 1 + 4 + 8 + 4;
+
+/// irys_id (32) || len (4) || tag (16) || hash (32)
+const CHUNK_ENTRY_LEN: usize = 32 + 4 + TAG_LEN + 32;
+
+// Domain separators for the chunk Merkle tree and the manifest root
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const ROOT_PREFIX: u8 = 0x02;
+
+/// One encrypted chunk of a large file, stored as its own Irys upload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkEntry {
+    pub irys_id: [u8; 32],
+    pub len: u32,             // length of the uploaded chunk (ciphertext || tag)
+    pub tag: [u8; TAG_LEN],   // AEAD tag of the chunk
+    pub hash: [u8; 32],       // SHA-256 of the uploaded chunk
+}
+
+/// Lists the encrypted chunks of a file too large for a single Irys upload.
+///
+/// Chunks are encrypted under the file key with the chunk index as nonce, so they
+/// cannot be reordered. The manifest itself is encrypted under the same key and
+/// uploaded; [`Manifest::root`] is what a file note or the file registry binds to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: u8,
+    pub chunk_size: u32,      // plaintext bytes per chunk; only the last may be shorter
+    pub total_len: u64,       // plaintext length of the whole file
+    pub chunks: Vec<ChunkEntry>,
+}
+
+impl Manifest {
+    /// Merkle root over the chunk hashes, in chunk order.
+    pub fn chunk_root(&self) -> [u8; 32] {
+        let mut level: Vec<[u8; 32]> = self
+            .chunks
+            .iter()
+            .map(|chunk| hash_parts(&[&[LEAF_PREFIX], &chunk.hash]))
+            .collect();
+        if level.is_empty() {
+            return [0u8; 32];
+        }
+
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_parts(&[&[NODE_PREFIX], left, right]),
+                    [odd] => *odd,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    /// Commits to the header and the chunk root; this is the file's content hash.
+    pub fn root(&self) -> [u8; 32] {
+        hash_parts(&[
+            &[ROOT_PREFIX, self.version],
+            &self.chunk_size.to_le_bytes(),
+            &self.total_len.to_le_bytes(),
+            &(self.chunks.len() as u32).to_le_bytes(),
+            &self.chunk_root(),
+        ])
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.chunks.len() * CHUNK_ENTRY_LEN);
+        bytes.push(self.version);
+        bytes.extend_from_slice(&self.chunk_size.to_le_bytes());
+        bytes.extend_from_slice(&self.total_len.to_le_bytes());
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for chunk in &self.chunks {
+            bytes.extend_from_slice(&chunk.irys_id);
+            bytes.extend_from_slice(&chunk.len.to_le_bytes());
+            bytes.extend_from_slice(&chunk.tag);
+            bytes.extend_from_slice(&chunk.hash);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FileError> {
+        if bytes.len() < HEADER_LEN || bytes[0] != MANIFEST_VERSION {
+            return Err(FileError::InvalidManifest);
+        }
+
+        let chunk_size = u32::from_le_bytes(bytes[1..5].try_into().expect("range is 4 bytes"));
+        let total_len = u64::from_le_bytes(bytes[5..13].try_into().expect("range is 8 bytes"));
+        let count = u32::from_le_bytes(bytes[13..17].try_into().expect("range is 4 bytes")) as usize;
+
+        let entries = &bytes[HEADER_LEN..];
+        if chunk_size == 0 || count == 0 || entries.len() != count * CHUNK_ENTRY_LEN {
+            return Err(FileError::InvalidManifest);
+        }
+
+        let chunks = entries
+            .chunks_exact(CHUNK_ENTRY_LEN)
+            .map(|entry| ChunkEntry {
+                irys_id: entry[..32].try_into().expect("range is 32 bytes"),
+                len: u32::from_le_bytes(entry[32..36].try_into().expect("range is 4 bytes")),
+                tag: entry[36..36 + TAG_LEN].try_into().expect("range is a tag"),
+                hash: entry[36 + TAG_LEN..].try_into().expect("range is 32 bytes"),
+            })
+            .collect();
+
+        Ok(Self {
+            version: MANIFEST_VERSION,
+            chunk_size,
+            total_len,
+            chunks,
+        })
+    }
+}
+
+fn hash_parts(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(count: u8) -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            chunk_size: 1024,
+            total_len: 1024 * count as u64 - 10,
+            chunks: (0..count)
+                .map(|i| ChunkEntry {
+                    irys_id: [i; 32],
+                    len: 1024 + TAG_LEN as u32,
+                    tag: [i + 1; TAG_LEN],
+                    hash: [i + 2; 32],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        for count in [1, 2, 5] {
+            let manifest = manifest(count);
+            assert_eq!(Manifest::from_bytes(&manifest.to_bytes()), Ok(manifest));
+        }
+    }
+
+    #[test]
+    fn root_commits_to_every_chunk_and_the_header() {
+        // odd counts promote the last node, so check every position of a 5-chunk list
+        let root = manifest(5).root();
+        for index in 0..5 {
+            let mut changed = manifest(5);
+            changed.chunks[index].hash[0] ^= 1;
+            assert_ne!(changed.root(), root);
+        }
+
+        let mut total_len = manifest(5);
+        total_len.total_len += 1;
+        let mut chunk_size = manifest(5);
+        chunk_size.chunk_size += 1;
+        assert_ne!(total_len.root(), root);
+        assert_ne!(chunk_size.root(), root);
+    }
+
+    #[test]
+    fn rejects_malformed_manifests() {
+        let bytes = manifest(2).to_bytes();
+
+        let mut version = bytes.clone();
+        version[0] = MANIFEST_VERSION + 1;
+        let mut no_chunks = bytes[..HEADER_LEN].to_vec();
+        no_chunks[13..17].copy_from_slice(&[0; 4]);
+        let mut zero_chunk_size = bytes.clone();
+        zero_chunk_size[1..5].copy_from_slice(&[0; 4]);
+
+        for malformed in [&version, &no_chunks, &zero_chunk_size, &bytes[..bytes.len() - 1], &bytes[..HEADER_LEN - 1]] {
+            assert_eq!(Manifest::from_bytes(malformed), Err(FileError::InvalidManifest));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::chunked::{decrypt_manifest, ChunkedFile};
+use crate::crypto::{open, seal_padded, EncryptionKeypair, PUBLIC_KEY_LEN};
+use crate::error::FileError;
+use crate::file::{content_hash, decrypt_blob, EncryptedFile, FILE_KEY_LEN};
+use crate::manifest::Manifest;
+use rand::{CryptoRng, RngCore};
+
+/// First byte of a note payload, identifying what the note carries.
+pub const NOTE_KIND_FILE: u8 = 2;
+
+/// Note kind for a chunked file: `irys_id` points at its encrypted manifest and
+/// `content_hash` is the manifest root.
+pub const NOTE_KIND_CHUNKED_FILE: u8 = 3;
+
+/// kind (1) || blinding (32) || irys_id (32) || content_hash (32) || file_key (32)
+pub const FILE_NOTE_LEN: usize = 1 + 32 + 32 + 32 + FILE_KEY_LEN;
+
+/// The program's default encrypted output length (`DEFAULT_ENCRYPTED_OUTPUT_LEN`).
+/// Notes are padded to it so file notes and value notes look alike on-chain.
+pub const ENCRYPTED_OUTPUT_LEN: usize = 256;
+
+/// Note attached to a zero-value UTXO that hands a file to its recipient.
+///
+/// `blinding` is the blinding of the zero-value output commitment carrying the note,
+/// so the sender's SDK builds `Poseidon(0, recipientPubkey, blinding, mint)` from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileNote {
+    pub chunked: bool,
+    pub blinding: [u8; 32],
+    pub irys_id: [u8; 32],
+    pub content_hash: [u8; 32],
+    pub file_key: [u8; FILE_KEY_LEN],
+}
+
+impl FileNote {
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, irys_id: [u8; 32], file: &EncryptedFile) -> Self {
+        Self {
+            chunked: false,
+            blinding: random_blinding(rng),
+            irys_id,
+            content_hash: file.content_hash(),
+            file_key: *file.key(),
+        }
+    }
+
+    /// Builds a note for a chunked file from the Irys ID of its uploaded manifest.
+    pub fn new_chunked<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        manifest_irys_id: [u8; 32],
+        file: &ChunkedFile,
+    ) -> Self {
+        Self {
+            chunked: true,
+            blinding: random_blinding(rng),
+            irys_id: manifest_irys_id,
+            content_hash: file.root(),
+            file_key: *file.key(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; FILE_NOTE_LEN] {
+        let mut bytes = [0u8; FILE_NOTE_LEN];
+        bytes[0] = if self.chunked {
+            NOTE_KIND_CHUNKED_FILE
+        } else {
+            NOTE_KIND_FILE
+        };
+        bytes[1..33].copy_from_slice(&self.blinding);
+        bytes[33..65].copy_from_slice(&self.irys_id);
+        bytes[65..97].copy_from_slice(&self.content_hash);
+        bytes[97..].copy_from_slice(&self.file_key);
+        bytes
+    }
+
+    /// Parses a note payload, allowing the zero padding added by [`FileNote::encrypt`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FileError> {
+        if bytes.len() < FILE_NOTE_LEN
+            || bytes[FILE_NOTE_LEN..].iter().any(|&byte| byte != 0)
+            || (bytes[0] != NOTE_KIND_FILE && bytes[0] != NOTE_KIND_CHUNKED_FILE)
+        {
+            return Err(FileError::InvalidNote);
+        }
+
+        let field = |range: std::ops::Range<usize>| -> [u8; 32] {
+            bytes[range].try_into().expect("range is 32 bytes")
+        };
+
+        Ok(Self {
+            chunked: bytes[0] == NOTE_KIND_CHUNKED_FILE,
+            blinding: field(1..33),
+            irys_id: field(33..65),
+            content_hash: field(65..97),
+            file_key: field(97..FILE_NOTE_LEN),
+        })
+    }
+
+    /// Encrypts the note to the recipient, producing an `encrypted_output` for `transact`
+    /// of the default `ENCRYPTED_OUTPUT_LEN`.
+    pub fn encrypt<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        recipient_public: &[u8; PUBLIC_KEY_LEN],
+    ) -> Result<Vec<u8>, FileError> {
+        self.encrypt_padded(rng, recipient_public, ENCRYPTED_OUTPUT_LEN)
+    }
+
+    /// Same as [`FileNote::encrypt`], for a pool configured with another encrypted
+    /// output length.
+    pub fn encrypt_padded<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        recipient_public: &[u8; PUBLIC_KEY_LEN],
+        encrypted_output_len: usize,
+    ) -> Result<Vec<u8>, FileError> {
+        seal_padded(rng, recipient_public, &self.to_bytes(), encrypted_output_len)
+    }
+
+    /// Decrypts an `encrypted_output`. Fails if it is not a file note for `keypair`.
+    pub fn decrypt(keypair: &EncryptionKeypair, encrypted_output: &[u8]) -> Result<Self, FileError> {
+        Self::from_bytes(&open(keypair, encrypted_output)?)
+    }
+
+    /// Checks a blob downloaded from Irys against the note and decrypts it.
+    pub fn decrypt_file(&self, blob: &[u8]) -> Result<Vec<u8>, FileError> {
+        if self.chunked {
+            return Err(FileError::InvalidNote);
+        }
+        if content_hash(blob) != self.content_hash {
+            return Err(FileError::ContentHashMismatch);
+        }
+
+        decrypt_blob(&self.file_key, blob)
+    }
+
+    /// Decrypts the manifest of a chunked file and checks it against the note's root.
+    /// Pass the result to [`crate::chunked::decrypt_chunked`] to stream the file.
+    pub fn decrypt_manifest(&self, manifest_blob: &[u8]) -> Result<Manifest, FileError> {
+        if !self.chunked {
+            return Err(FileError::InvalidNote);
+        }
+
+        decrypt_manifest(&self.file_key, manifest_blob, &self.content_hash)
+    }
+}
+
+fn random_blinding<R: RngCore + CryptoRng>(rng: &mut R) -> [u8; 32] {
+    let mut blinding = [0u8; 32];
+    rng.fill_bytes(&mut blinding);
+    // Keep the blinding inside the BN254 scalar field
+    blinding[0] &= 0x1f;
+    blinding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SEAL_OVERHEAD;
+    use rand::rngs::OsRng;
+
+    fn note(chunked: bool) -> FileNote {
+        FileNote {
+            chunked,
+            blinding: random_blinding(&mut OsRng),
+            irys_id: [1u8; 32],
+            content_hash: [2u8; 32],
+            file_key: [3u8; FILE_KEY_LEN],
+        }
+    }
+
+    #[test]
+    fn every_note_encrypts_to_the_same_length() {
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+        for note in [note(false), note(true)] {
+            let encrypted_output = note.encrypt(&mut OsRng, &recipient.public_key()).unwrap();
+            assert_eq!(encrypted_output.len(), ENCRYPTED_OUTPUT_LEN);
+            assert_eq!(FileNote::decrypt(&recipient, &encrypted_output), Ok(note));
+        }
+
+        // a note needs at least its own length
+        assert_eq!(
+            note(false).encrypt_padded(&mut OsRng, &recipient.public_key(), SEAL_OVERHEAD + FILE_NOTE_LEN - 1),
+            Err(FileError::NoteTooLong)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        let note = note(false);
+        let bytes = note.to_bytes();
+        assert_eq!(FileNote::from_bytes(&bytes), Ok(note.clone()));
+
+        let mut padded = bytes.to_vec();
+        padded.extend_from_slice(&[0u8; 40]);
+        assert_eq!(FileNote::from_bytes(&padded), Ok(note));
+
+        let mut dirty_padding = padded.clone();
+        dirty_padding[FILE_NOTE_LEN + 5] = 1;
+        let mut unknown_kind = bytes;
+        unknown_kind[0] = 1;
+        for malformed in [&dirty_padding[..], &unknown_kind[..], &bytes[..FILE_NOTE_LEN - 1]] {
+            assert_eq!(FileNote::from_bytes(malformed), Err(FileError::InvalidNote));
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::crypto::EncryptionKeypair;
+use crate::note::FileNote;
+
+/// Scans `(leaf index, encrypted_output)` pairs, e.g. from `CommitmentData` events or
+/// note archive pages, and returns the file notes addressed to `keypair`.
+pub fn scan<'a, I>(keypair: &EncryptionKeypair, outputs: I) -> Vec<(u64, FileNote)>
+where
+    I: IntoIterator<Item = (u64, &'a [u8])>,
+{
+    outputs
+        .into_iter()
+        .filter_map(|(index, encrypted_output)| {
+            FileNote::decrypt(keypair, encrypted_output)
+                .ok()
+                .map(|note| (index, note))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::EncryptedFile;
+    use crate::note::ENCRYPTED_OUTPUT_LEN;
+    use rand::rngs::OsRng;
+    use rand::RngCore;
+
+    #[test]
+    fn finds_the_notes_addressed_to_the_keypair() {
+        let recipient = EncryptionKeypair::generate(&mut OsRng);
+        let bystander = EncryptionKeypair::generate(&mut OsRng);
+
+        let file = EncryptedFile::encrypt(&mut OsRng, b"quarterly report").unwrap();
+        let note = FileNote::new(&mut OsRng, [4u8; 32], &file);
+        let other = FileNote::new(&mut OsRng, [5u8; 32], &file);
+
+        // an unrelated ciphertext of the same length, as a value note would be padded to
+        let mut unrelated = vec![0u8; ENCRYPTED_OUTPUT_LEN];
+        OsRng.fill_bytes(&mut unrelated);
+
+        let outputs = [
+            (10, note.encrypt(&mut OsRng, &recipient.public_key()).unwrap()),
+            (11, other.encrypt(&mut OsRng, &bystander.public_key()).unwrap()),
+            (14, note.encrypt_padded(&mut OsRng, &recipient.public_key(), 512).unwrap()),
+            (12, unrelated),
+            (13, Vec::new()),
+        ];
+        let found = scan(&recipient, outputs.iter().map(|(index, output)| (*index, output.as_slice())));
+
+        assert_eq!(found, vec![(10, note.clone()), (14, note.clone())]);
+        assert_eq!(found[0].1.decrypt_file(file.blob()).unwrap(), b"quarterly report");
+        assert_eq!(scan(&bystander, outputs.iter().map(|(index, output)| (*index, output.as_slice()))), vec![(11, other)]);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::data_item::{DataItem, Tag};
+use crate::ed25519::{Ed25519Signer, PUBLIC_KEY_LEN};
+use crate::error::IrysError;
+use crate::receipt::Receipt;
+use rand::{CryptoRng, RngCore};
+
+/// Accepts serialized data items and returns a signed receipt.
+pub trait Bundler {
+    fn upload(&self, data_item: &[u8]) -> Result<Receipt, IrysError>;
+}
+
+/// Serves the data of a posted transaction by ID.
+pub trait Gateway {
+    fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, IrysError>;
+}
+
+impl<T: Bundler + ?Sized> Bundler for &T {
+    fn upload(&self, data_item: &[u8]) -> Result<Receipt, IrysError> {
+        (**self).upload(data_item)
+    }
+}
+
+impl<T: Gateway + ?Sized> Gateway for &T {
+    fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, IrysError> {
+        (**self).fetch(id)
+    }
+}
+
+pub struct IrysClient<B, G> {
+    bundler: B,
+    gateway: G,
+    signer: Ed25519Signer,
+    bundler_public_key: [u8; PUBLIC_KEY_LEN],
+}
+
+impl<B: Bundler, G: Gateway> IrysClient<B, G> {
+    /**
+     * @param bundler - Where data items are uploaded
+     * @param gateway - Where transaction data is fetched from
+     * @param signer - The uploader's Solana key
+     * @param bundler_public_key - The key receipts must be signed with
+     */
+    pub fn new(bundler: B, gateway: G, signer: Ed25519Signer, bundler_public_key: [u8; PUBLIC_KEY_LEN]) -> Self {
+        Self {
+            bundler,
+            gateway,
+            signer,
+            bundler_public_key,
+        }
+    }
+
+    /// Signs and uploads `data`, returning the verified receipt. `receipt.id` is the
+    /// value passed as `irys_tx_id` to `register_file`.
+    pub fn upload<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        data: &[u8],
+        tags: Vec<Tag>,
+    ) -> Result<Receipt, IrysError> {
+        let item = DataItem::sign(rng, &self.signer, data.to_vec(), tags);
+        let receipt = self.bundler.upload(&item.to_bytes())?;
+
+        receipt.verify(&self.bundler_public_key)?;
+        if receipt.id != item.id() {
+            return Err(IrysError::ReceiptMismatch);
+        }
+        Ok(receipt)
+    }
+
+    pub fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, IrysError> {
+        self.gateway.fetch(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockNode;
+    use rand::rngs::OsRng;
+
+    /// A bundler that answers every upload with a receipt for an earlier one.
+    struct ReplayingBundler<'a> {
+        node: &'a MockNode,
+        earlier: Receipt,
+    }
+
+    impl Bundler for ReplayingBundler<'_> {
+        fn upload(&self, data_item: &[u8]) -> Result<Receipt, IrysError> {
+            self.node.upload(data_item)?;
+            Ok(self.earlier.clone())
+        }
+    }
+
+    #[test]
+    fn uploads_and_fetches_through_the_mock_node() {
+        let node = MockNode::new(&mut OsRng);
+        let client = IrysClient::new(&node, &node, Ed25519Signer::generate(&mut OsRng), node.public_key());
+
+        // the same blob twice still gets two transaction IDs
+        let first = client.upload(&mut OsRng, b"blob", vec![]).unwrap();
+        let second = client.upload(&mut OsRng, b"blob", vec![]).unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(node.len(), 2);
+        assert_eq!(client.fetch(&first.id).unwrap(), b"blob");
+
+        node.remove(&first.id);
+        assert_eq!(client.fetch(&first.id), Err(IrysError::NotFound));
+    }
+
+    #[test]
+    fn rejects_a_receipt_for_another_item() {
+        let node = MockNode::new(&mut OsRng);
+        let signer = Ed25519Signer::generate(&mut OsRng);
+        let earlier = IrysClient::new(&node, &node, signer.clone(), node.public_key())
+            .upload(&mut OsRng, b"earlier", vec![])
+            .unwrap();
+
+        let bundler = ReplayingBundler { node: &node, earlier };
+        let client = IrysClient::new(bundler, &node, signer, node.public_key());
+        assert_eq!(client.upload(&mut OsRng, b"blob", vec![]), Err(IrysError::ReceiptMismatch));
+    }
+
+    #[test]
+    fn mock_node_rejects_unsigned_items() {
+        let node = MockNode::new(&mut OsRng);
+        let mut bytes = DataItem::sign(&mut OsRng, &Ed25519Signer::generate(&mut OsRng), b"blob".to_vec(), vec![]).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(node.upload(&bytes), Err(IrysError::InvalidSignature));
+        assert!(node.is_empty());
+    }
+}
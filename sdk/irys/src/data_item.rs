@@ -0,0 +1,341 @@
+//! ANS-104 data items signed with an ed25519 (Solana) key.
+//!
+//! Layout: signature type (u16 LE), signature, owner, target flag (+ target),
+//! anchor flag (+ anchor), tag count (u64 LE), tag bytes length (u64 LE),
+//! Avro-encoded tags, data.
+
+use crate::deep_hash::{deep_hash, DeepHashChunk};
+use crate::ed25519::{self, Ed25519Signer, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::error::IrysError;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+pub const SIGNATURE_TYPE_ED25519: u16 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub value: String,
+}
+
+impl Tag {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self { name: name.into(), value: value.into() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataItem {
+    signature: [u8; SIGNATURE_LEN],
+    owner: [u8; PUBLIC_KEY_LEN],
+    target: Option<[u8; 32]>,
+    anchor: Option<[u8; 32]>,
+    tags: Vec<Tag>,
+    data: Vec<u8>,
+}
+
+impl DataItem {
+    /// Signs `data` as a new data item. A random anchor keeps IDs unique when the
+    /// same blob is uploaded twice.
+    pub fn sign<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        signer: &Ed25519Signer,
+        data: Vec<u8>,
+        tags: Vec<Tag>,
+    ) -> Self {
+        let mut anchor = [0u8; 32];
+        rng.fill_bytes(&mut anchor);
+
+        let mut item = Self {
+            signature: [0u8; SIGNATURE_LEN],
+            owner: signer.public_key(),
+            target: None,
+            anchor: Some(anchor),
+            tags,
+            data,
+        };
+        item.signature = signer.sign(&item.signature_message());
+        item
+    }
+
+    /// The Irys transaction ID: SHA-256 of the signature.
+    pub fn id(&self) -> [u8; 32] {
+        Sha256::digest(self.signature).into()
+    }
+
+    pub fn owner(&self) -> &[u8; PUBLIC_KEY_LEN] {
+        &self.owner
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn verify(&self) -> bool {
+        ed25519::verify(&self.owner, &self.signature_message(), &self.signature)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let tags = encode_tags(&self.tags);
+
+        let mut bytes = Vec::with_capacity(2 + SIGNATURE_LEN + PUBLIC_KEY_LEN + 82 + tags.len() + self.data.len());
+        bytes.extend_from_slice(&SIGNATURE_TYPE_ED25519.to_le_bytes());
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&self.owner);
+        for optional in [&self.target, &self.anchor] {
+            match optional {
+                Some(value) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(value);
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes.extend_from_slice(&(self.tags.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&tags);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, IrysError> {
+        let mut reader = Reader(bytes);
+
+        let signature_type = u16::from_le_bytes(reader.array()?);
+        if signature_type != SIGNATURE_TYPE_ED25519 {
+            return Err(IrysError::InvalidDataItem);
+        }
+        let signature = reader.array()?;
+        let owner = reader.array()?;
+        let target = reader.optional()?;
+        let anchor = reader.optional()?;
+
+        let tag_count = u64::from_le_bytes(reader.array()?);
+        let tags_len = usize::try_from(u64::from_le_bytes(reader.array()?))
+            .map_err(|_| IrysError::InvalidDataItem)?;
+        let tags = decode_tags(reader.take(tags_len)?)?;
+        if tags.len() as u64 != tag_count {
+            return Err(IrysError::InvalidDataItem);
+        }
+
+        Ok(Self {
+            signature,
+            owner,
+            target,
+            anchor,
+            tags,
+            data: reader.0.to_vec(),
+        })
+    }
+
+    fn signature_message(&self) -> [u8; 48] {
+        let signature_type = SIGNATURE_TYPE_ED25519.to_string();
+        let tags = encode_tags(&self.tags);
+
+        deep_hash(&DeepHashChunk::List(vec![
+            DeepHashChunk::Blob(b"dataitem"),
+            DeepHashChunk::Blob(b"1"),
+            DeepHashChunk::Blob(signature_type.as_bytes()),
+            DeepHashChunk::Blob(&self.owner),
+            DeepHashChunk::Blob(self.target.as_ref().map_or(&[], |target| target.as_slice())),
+            DeepHashChunk::Blob(self.anchor.as_ref().map_or(&[], |anchor| anchor.as_slice())),
+            DeepHashChunk::Blob(&tags),
+            DeepHashChunk::Blob(&self.data),
+        ]))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], IrysError> {
+        if self.0.len() < len {
+            return Err(IrysError::InvalidDataItem);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], IrysError> {
+        Ok(self.take(N)?.try_into().expect("slice is N bytes"))
+    }
+
+    fn optional(&mut self) -> Result<Option<[u8; 32]>, IrysError> {
+        match self.array::<1>()?[0] {
+            0 => Ok(None),
+            1 => self.array().map(Some),
+            _ => Err(IrysError::InvalidDataItem),
+        }
+    }
+
+    fn varint(&mut self) -> Result<i64, IrysError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.array::<1>()?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(IrysError::InvalidDataItem)
+    }
+
+    fn string(&mut self) -> Result<String, IrysError> {
+        let len = usize::try_from(self.varint()?).map_err(|_| IrysError::InvalidDataItem)?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| IrysError::InvalidDataItem)
+    }
+}
+
+/// Encodes tags as an Avro array of `{ name: bytes, value: bytes }` records.
+fn encode_tags(tags: &[Tag]) -> Vec<u8> {
+    if tags.is_empty() {
+        return Vec::new();
+    }
+
+    let mut bytes = Vec::new();
+    write_varint(&mut bytes, tags.len() as i64);
+    for tag in tags {
+        for field in [&tag.name, &tag.value] {
+            write_varint(&mut bytes, field.len() as i64);
+            bytes.extend_from_slice(field.as_bytes());
+        }
+    }
+    write_varint(&mut bytes, 0);
+    bytes
+}
+
+fn decode_tags(bytes: &[u8]) -> Result<Vec<Tag>, IrysError> {
+    let mut reader = Reader(bytes);
+    let mut tags = Vec::new();
+
+    while !reader.0.is_empty() {
+        let mut count = reader.varint()?;
+        if count == 0 {
+            break;
+        }
+        // A negative block count is followed by the block's byte size.
+        if count < 0 {
+            count = count.checked_neg().ok_or(IrysError::InvalidDataItem)?;
+            reader.varint()?;
+        }
+        for _ in 0..count {
+            let name = reader.string()?;
+            let value = reader.string()?;
+            tags.push(Tag { name, value });
+        }
+    }
+
+    if !reader.0.is_empty() {
+        return Err(IrysError::InvalidDataItem);
+    }
+    Ok(tags)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        bytes.push((zigzag as u8 & 0x7f) | 0x80);
+        zigzag >>= 7;
+    }
+    bytes.push(zigzag as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    fn item(tags: Vec<Tag>) -> DataItem {
+        let signer = Ed25519Signer::from_seed([9u8; 32]);
+        DataItem::sign(&mut OsRng, &signer, b"encrypted blob".to_vec(), tags)
+    }
+
+    // serialized tags with the given Avro block count in front of one record
+    fn item_with_block_count(count: i64, with_size: bool) -> Vec<u8> {
+        let mut tags = Vec::new();
+        write_varint(&mut tags, count);
+        if with_size {
+            write_varint(&mut tags, 6);
+        }
+        for field in ["ab", "cd"] {
+            write_varint(&mut tags, field.len() as i64);
+            tags.extend_from_slice(field.as_bytes());
+        }
+        write_varint(&mut tags, 0);
+
+        let mut bytes = item(vec![]).to_bytes();
+        let tags_at = 2 + SIGNATURE_LEN + PUBLIC_KEY_LEN + 2 + 32;
+        bytes.truncate(tags_at);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&tags);
+        bytes
+    }
+
+    #[test]
+    fn roundtrips_and_verifies() {
+        for tags in [vec![], vec![Tag::new("Content-Type", "application/octet-stream"), Tag::new("App-Name", "txnsfr")]] {
+            let item = item(tags);
+            assert!(item.verify());
+
+            let decoded = DataItem::from_bytes(&item.to_bytes()).unwrap();
+            assert_eq!(decoded, item);
+            assert_eq!(decoded.id(), item.id());
+        }
+    }
+
+    #[test]
+    fn signature_covers_data_and_tags() {
+        let item = item(vec![Tag::new("App-Name", "txnsfr")]);
+
+        let mut data = item.clone();
+        data.data[0] ^= 1;
+        assert!(!data.verify());
+
+        let mut tags = item.clone();
+        tags.tags[0].value = "other".into();
+        assert!(!tags.verify());
+
+        let mut anchor = item;
+        anchor.anchor = None;
+        assert!(!anchor.verify());
+    }
+
+    #[test]
+    fn decodes_negative_block_counts() {
+        let item = DataItem::from_bytes(&item_with_block_count(-1, true)).unwrap();
+        assert_eq!(item.tags(), [Tag::new("ab", "cd")]);
+    }
+
+    #[test]
+    fn rejects_malformed_items() {
+        // a block count of i64::MIN has no positive counterpart
+        assert_eq!(
+            DataItem::from_bytes(&item_with_block_count(i64::MIN, true)),
+            Err(IrysError::InvalidDataItem)
+        );
+        // more records than the tag count says
+        assert_eq!(
+            DataItem::from_bytes(&item_with_block_count(2, false)),
+            Err(IrysError::InvalidDataItem)
+        );
+
+        let bytes = item(vec![Tag::new("App-Name", "txnsfr")]).to_bytes();
+        let mut wrong_type = bytes.clone();
+        wrong_type[0] = 1;
+        assert_eq!(DataItem::from_bytes(&wrong_type), Err(IrysError::InvalidDataItem));
+
+        let mut bad_flag = bytes.clone();
+        bad_flag[2 + SIGNATURE_LEN + PUBLIC_KEY_LEN] = 2;
+        assert_eq!(DataItem::from_bytes(&bad_flag), Err(IrysError::InvalidDataItem));
+
+        for len in [0, 10, 2 + SIGNATURE_LEN + PUBLIC_KEY_LEN + 2 + 32 + 20] {
+            assert_eq!(DataItem::from_bytes(&bytes[..len]), Err(IrysError::InvalidDataItem));
+        }
+    }
+}
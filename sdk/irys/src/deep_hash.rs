@@ -0,0 +1,29 @@
+//! Arweave deep hash (SHA-384), the message format ANS-104 data items and
+//! bundler receipts are signed over.
+
+use sha2::{Digest, Sha384};
+
+pub enum DeepHashChunk<'a> {
+    Blob(&'a [u8]),
+    List(Vec<DeepHashChunk<'a>>),
+}
+
+pub fn deep_hash(chunk: &DeepHashChunk) -> [u8; 48] {
+    match chunk {
+        DeepHashChunk::Blob(data) => {
+            let tag = sha384(&[b"blob", data.len().to_string().as_bytes()].concat());
+            sha384(&[tag, sha384(data)].concat())
+        }
+        DeepHashChunk::List(children) => {
+            let mut acc = sha384(&[b"list", children.len().to_string().as_bytes()].concat());
+            for child in children {
+                acc = sha384(&[acc, deep_hash(child)].concat());
+            }
+            acc
+        }
+    }
+}
+
+fn sha384(data: &[u8]) -> [u8; 48] {
+    Sha384::digest(data).into()
+}
@@ -0,0 +1,116 @@
+//! Ed25519 (RFC 8032) signing with Solana keypairs, the signer type Irys uses
+//! for Solana uploads.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+
+pub const PUBLIC_KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+pub const SIGNATURE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+#[derive(Clone)]
+pub struct Ed25519Signer {
+    key: SigningKey,
+}
+
+impl Ed25519Signer {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+
+    /// Builds a signer from a Solana keypair file's 64 bytes (seed followed by public key).
+    pub fn from_solana_keypair(keypair: &[u8; 64]) -> Self {
+        let seed: [u8; 32] = keypair[..32].try_into().expect("slice is 32 bytes");
+        Self::from_seed(seed)
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(&seed),
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LEN] {
+        self.key.sign(message).to_bytes()
+    }
+}
+
+/// Verifies an ed25519 signature, rejecting non-canonical `S` values and
+/// small-order keys.
+pub fn verify(public: &[u8; PUBLIC_KEY_LEN], message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool {
+    let Ok(public) = VerifyingKey::from_bytes(public) else {
+        return false;
+    };
+    public
+        .verify_strict(message, &Signature::from_bytes(signature))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(hex: &str) -> [u8; N] {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        bytes.try_into().unwrap()
+    }
+
+    #[test]
+    fn matches_rfc8032_test_vector() {
+        // RFC 8032, section 7.1, TEST 2
+        let signer = Ed25519Signer::from_seed(hex("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb"));
+        let public: [u8; 32] = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let signature: [u8; 64] = hex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+
+        assert_eq!(signer.public_key(), public);
+        assert_eq!(signer.sign(&[0x72]), signature);
+        assert!(verify(&public, &[0x72], &signature));
+        assert!(!verify(&public, &[0x73], &signature));
+    }
+
+    #[test]
+    fn solana_keypair_signs_with_its_seed() {
+        let signer = Ed25519Signer::from_seed([7u8; 32]);
+        let mut keypair = [0u8; 64];
+        keypair[..32].copy_from_slice(&[7u8; 32]);
+        keypair[32..].copy_from_slice(&signer.public_key());
+
+        let from_keypair = Ed25519Signer::from_solana_keypair(&keypair);
+        assert_eq!(from_keypair.public_key(), signer.public_key());
+        assert_eq!(from_keypair.sign(b"data item"), signer.sign(b"data item"));
+    }
+
+    #[test]
+    fn rejects_non_canonical_and_small_order_signatures() {
+        let signer = Ed25519Signer::from_seed([1u8; 32]);
+        let mut signature = signer.sign(b"message");
+
+        // S + l verifies under the group law, but is not the canonical encoding
+        let order: [u8; 32] = hex("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010");
+        let mut carry = 0u16;
+        for (s, l) in signature[32..].iter_mut().zip(order) {
+            let sum = *s as u16 + l as u16 + carry;
+            *s = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify(&signer.public_key(), b"message", &signature));
+
+        // the identity point is a small-order key that "verifies" a zero signature
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let mut zero_signature = [0u8; 64];
+        zero_signature[..32].copy_from_slice(&identity);
+        assert!(!verify(&identity, b"message", &zero_signature));
+    }
+}
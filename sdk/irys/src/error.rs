@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IrysError {
+    #[error("Invalid Irys transaction ID")]
+    InvalidId,
+
+    #[error("Malformed data item")]
+    InvalidDataItem,
+
+    #[error("Data item signature is invalid")]
+    InvalidSignature,
+
+    #[error("Receipt signature is invalid or not from the expected bundler")]
+    InvalidReceipt,
+
+    #[error("Receipt does not match the uploaded data item")]
+    ReceiptMismatch,
+
+    #[error("Transaction not found")]
+    NotFound,
+
+    #[error("Transport error: {0}")]
+    Transport(String),
+}
@@ -0,0 +1,202 @@
+//! Bundler and gateway clients for Irys nodes over HTTP.
+
+use crate::client::{Bundler, Gateway};
+use crate::encode_id;
+use crate::error::IrysError;
+use crate::receipt::Receipt;
+use std::io::Read;
+use std::time::Duration;
+
+pub const DEFAULT_BUNDLER_URL: &str = "https://node1.irys.xyz";
+pub const DEFAULT_GATEWAY_URL: &str = "https://gateway.irys.xyz";
+
+/// Largest transaction `HttpGateway::fetch` reads, so a misbehaving gateway can't
+/// exhaust memory. Chunked files keep each upload well below it.
+pub const MAX_FETCH_LEN: u64 = 1 << 30;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(TIMEOUT).build()
+}
+
+fn transport_error(err: ureq::Error) -> IrysError {
+    match err {
+        ureq::Error::Status(404, _) => IrysError::NotFound,
+        err => IrysError::Transport(err.to_string()),
+    }
+}
+
+/// Uploads data items to an Irys bundler node, paying with Solana.
+pub struct HttpBundler {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl HttpBundler {
+    /**
+     * @param url - Base URL of the bundler node, e.g. `DEFAULT_BUNDLER_URL`
+     */
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            agent: agent(),
+            url: url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Bundler for HttpBundler {
+    fn upload(&self, data_item: &[u8]) -> Result<Receipt, IrysError> {
+        let response = self
+            .agent
+            .post(&format!("{}/tx/solana", self.url))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(data_item)
+            .map_err(transport_error)?;
+        let body = response
+            .into_string()
+            .map_err(|err| IrysError::Transport(err.to_string()))?;
+        Receipt::from_json(&body)
+    }
+}
+
+/// Fetches transaction data from an Irys or Arweave gateway.
+pub struct HttpGateway {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl HttpGateway {
+    /**
+     * @param url - Base URL of the gateway, e.g. `DEFAULT_GATEWAY_URL`
+     */
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            agent: agent(),
+            url: url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Gateway for HttpGateway {
+    fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, IrysError> {
+        let response = self
+            .agent
+            .get(&format!("{}/{}", self.url, encode_id(id)))
+            .call()
+            .map_err(transport_error)?;
+
+        let mut data = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_FETCH_LEN + 1)
+            .read_to_end(&mut data)
+            .map_err(|err| IrysError::Transport(err.to_string()))?;
+        if data.len() as u64 > MAX_FETCH_LEN {
+            return Err(IrysError::Transport("transaction exceeds MAX_FETCH_LEN".to_string()));
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_id, Ed25519Signer, IrysClient, MockNode, Tag};
+    use rand::rngs::OsRng;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Serves a mock node over HTTP on a local port, the way an Irys node exposes
+    /// its bundler and gateway, and returns its base URL.
+    fn serve(node: Arc<MockNode>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut parts = request_line.split_whitespace();
+                let result = match (parts.next(), parts.next()) {
+                    (Some("POST"), Some("/tx/solana")) => node.upload(&body).map(|receipt| receipt.to_json().into_bytes()),
+                    (Some("GET"), Some(path)) => decode_id(&path[1..]).and_then(|id| node.fetch(&id)),
+                    _ => Err(IrysError::NotFound),
+                };
+                let (status, body) = match result {
+                    Ok(body) => ("200 OK", body),
+                    Err(IrysError::NotFound) => ("404 Not Found", Vec::new()),
+                    Err(err) => ("400 Bad Request", err.to_string().into_bytes()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn uploads_and_fetches_over_http() {
+        let node = Arc::new(MockNode::new(&mut OsRng));
+        let url = serve(node.clone());
+        let client = IrysClient::new(
+            HttpBundler::new(format!("{url}/")),
+            HttpGateway::new(&url),
+            Ed25519Signer::generate(&mut OsRng),
+            node.public_key(),
+        );
+
+        let blob = vec![0x5a; 100_000];
+        let receipt = client
+            .upload(&mut OsRng, &blob, vec![Tag::new("Content-Type", "application/octet-stream")])
+            .unwrap();
+        assert_eq!(client.fetch(&receipt.id).unwrap(), blob);
+        assert_eq!(client.fetch(&[0u8; 32]), Err(IrysError::NotFound));
+    }
+
+    #[test]
+    fn rejects_receipts_from_another_bundler() {
+        let node = Arc::new(MockNode::new(&mut OsRng));
+        let url = serve(node);
+        let client = IrysClient::new(
+            HttpBundler::new(&url),
+            HttpGateway::new(&url),
+            Ed25519Signer::generate(&mut OsRng),
+            Ed25519Signer::generate(&mut OsRng).public_key(),
+        );
+        assert_eq!(client.upload(&mut OsRng, b"blob", vec![]), Err(IrysError::InvalidReceipt));
+    }
+
+    #[test]
+    fn bundler_errors_are_transport_errors() {
+        let node = Arc::new(MockNode::new(&mut OsRng));
+        let bundler = HttpBundler::new(serve(node));
+        assert!(matches!(bundler.upload(b"not a data item"), Err(IrysError::Transport(_))));
+    }
+}
@@ -0,0 +1,56 @@
+//! Irys upload and retrieval client for txnsfr file transfers.
+//!
+//! Blobs are wrapped in ANS-104 data items signed with the uploader's Solana
+//! (ed25519) key, posted to a bundler, and fetched back from a gateway by
+//! transaction ID. Bundler receipts are verified offline against the bundler's
+//! public key.
+//!
+//! The network is abstracted behind the [`Bundler`] and [`Gateway`] traits.
+//! [`HttpBundler`] and [`HttpGateway`] talk to Irys nodes, and [`MockNode`] can
+//! stand in for both, so the whole file-transfer flow can be tested without
+//! network access.
+//!
+//! ```rust,ignore
+//! let client = IrysClient::new(
+//!     HttpBundler::new(DEFAULT_BUNDLER_URL),
+//!     HttpGateway::new(DEFAULT_GATEWAY_URL),
+//!     signer,
+//!     bundler_public_key,
+//! );
+//! let receipt = client.upload(&mut rng, encrypted.blob(), vec![])?;
+//! let blob = client.fetch(&receipt.id)?;
+//! ```
+
+pub mod client;
+pub mod data_item;
+pub mod deep_hash;
+pub mod ed25519;
+pub mod error;
+pub mod http;
+pub mod mock;
+pub mod receipt;
+
+pub use client::{Bundler, Gateway, IrysClient};
+pub use data_item::{DataItem, Tag};
+pub use ed25519::Ed25519Signer;
+pub use error::IrysError;
+pub use http::{HttpBundler, HttpGateway, DEFAULT_BUNDLER_URL, DEFAULT_GATEWAY_URL};
+pub use mock::MockNode;
+pub use receipt::Receipt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+/// Encodes a 32-byte Irys transaction ID in its base64url text form.
+pub fn encode_id(id: &[u8; 32]) -> String {
+    URL_SAFE_NO_PAD.encode(id)
+}
+
+/// Decodes the base64url text form of an Irys transaction ID.
+pub fn decode_id(id: &str) -> Result<[u8; 32], IrysError> {
+    URL_SAFE_NO_PAD
+        .decode(id)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(IrysError::InvalidId)
+}
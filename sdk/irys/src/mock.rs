@@ -0,0 +1,76 @@
+//! In-process bundler and gateway for tests and local development.
+
+use crate::client::{Bundler, Gateway};
+use crate::data_item::DataItem;
+use crate::ed25519::{Ed25519Signer, PUBLIC_KEY_LEN};
+use crate::error::IrysError;
+use crate::receipt::Receipt;
+use rand::{CryptoRng, RngCore};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many blocks past `height` receipts promise inclusion by.
+pub const RECEIPT_DEADLINE_BLOCKS: u64 = 200;
+
+pub struct MockNode {
+    signer: Ed25519Signer,
+    height: u64,
+    items: Mutex<HashMap<[u8; 32], DataItem>>,
+}
+
+impl MockNode {
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self {
+            signer: Ed25519Signer::generate(rng),
+            height: 0,
+            items: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.signer.public_key()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().expect("mock node lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops a stored item, e.g. to simulate data that never reached Arweave.
+    pub fn remove(&self, id: &[u8; 32]) -> Option<DataItem> {
+        self.items.lock().expect("mock node lock poisoned").remove(id)
+    }
+}
+
+impl Bundler for MockNode {
+    fn upload(&self, data_item: &[u8]) -> Result<Receipt, IrysError> {
+        let item = DataItem::from_bytes(data_item)?;
+        if !item.verify() {
+            return Err(IrysError::InvalidSignature);
+        }
+
+        let id = item.id();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| IrysError::Transport(err.to_string()))?
+            .as_millis() as u64;
+
+        self.items.lock().expect("mock node lock poisoned").insert(id, item);
+        Ok(Receipt::sign(&self.signer, id, timestamp, self.height + RECEIPT_DEADLINE_BLOCKS))
+    }
+}
+
+impl Gateway for MockNode {
+    fn fetch(&self, id: &[u8; 32]) -> Result<Vec<u8>, IrysError> {
+        self.items
+            .lock()
+            .expect("mock node lock poisoned")
+            .get(id)
+            .map(|item| item.data().to_vec())
+            .ok_or(IrysError::NotFound)
+    }
+}
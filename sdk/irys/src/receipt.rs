@@ -0,0 +1,155 @@
+//! Bundler upload receipts.
+//!
+//! A receipt is the bundler's signed promise to post a data item to Arweave
+//! before `deadline_height`. Only ed25519-signed receipts are supported; RSA
+//! (Arweave wallet) bundler keys are not.
+
+use crate::deep_hash::{deep_hash, DeepHashChunk};
+use crate::ed25519::{self, Ed25519Signer, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crate::error::IrysError;
+use crate::{decode_id, encode_id};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Value};
+
+pub const RECEIPT_VERSION: &str = "1.0.0";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    pub id: [u8; 32],
+    pub timestamp: u64,
+    pub version: String,
+    pub deadline_height: u64,
+    pub public: [u8; PUBLIC_KEY_LEN],
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl Receipt {
+    pub fn sign(signer: &Ed25519Signer, id: [u8; 32], timestamp: u64, deadline_height: u64) -> Self {
+        let version = RECEIPT_VERSION.to_string();
+        let message = message(&id, timestamp, &version, deadline_height);
+        Self {
+            id,
+            timestamp,
+            version,
+            deadline_height,
+            public: signer.public_key(),
+            signature: signer.sign(&message),
+        }
+    }
+
+    /// Checks the receipt was signed by `bundler`.
+    pub fn verify(&self, bundler: &[u8; PUBLIC_KEY_LEN]) -> Result<(), IrysError> {
+        let message = message(&self.id, self.timestamp, &self.version, self.deadline_height);
+        if self.public != *bundler || !ed25519::verify(&self.public, &message, &self.signature) {
+            return Err(IrysError::InvalidReceipt);
+        }
+        Ok(())
+    }
+
+    /// Parses the JSON receipt a bundler returns for an upload. Binary fields are base64url.
+    pub fn from_json(json: &str) -> Result<Self, IrysError> {
+        let value: Value = serde_json::from_str(json).map_err(|_| IrysError::InvalidReceipt)?;
+        let string = |field: &str| value[field].as_str().ok_or(IrysError::InvalidReceipt);
+        let number = |field: &str| value[field].as_u64().ok_or(IrysError::InvalidReceipt);
+        let bytes = |field: &str| {
+            URL_SAFE_NO_PAD
+                .decode(string(field)?)
+                .map_err(|_| IrysError::InvalidReceipt)
+        };
+
+        Ok(Self {
+            id: decode_id(string("id")?).map_err(|_| IrysError::InvalidReceipt)?,
+            timestamp: number("timestamp")?,
+            version: string("version")?.to_string(),
+            deadline_height: number("deadlineHeight")?,
+            public: bytes("public")?.try_into().map_err(|_| IrysError::InvalidReceipt)?,
+            signature: bytes("signature")?.try_into().map_err(|_| IrysError::InvalidReceipt)?,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "id": encode_id(&self.id),
+            "timestamp": self.timestamp,
+            "version": self.version,
+            "deadlineHeight": self.deadline_height,
+            "public": URL_SAFE_NO_PAD.encode(self.public),
+            "signature": URL_SAFE_NO_PAD.encode(self.signature),
+        })
+        .to_string()
+    }
+}
+
+fn message(id: &[u8; 32], timestamp: u64, version: &str, deadline_height: u64) -> [u8; 48] {
+    let id = encode_id(id);
+    let deadline_height = deadline_height.to_string();
+    let timestamp = timestamp.to_string();
+
+    deep_hash(&DeepHashChunk::List(vec![
+        DeepHashChunk::Blob(b"Bundlr"),
+        DeepHashChunk::Blob(version.as_bytes()),
+        DeepHashChunk::Blob(id.as_bytes()),
+        DeepHashChunk::Blob(deadline_height.as_bytes()),
+        DeepHashChunk::Blob(timestamp.as_bytes()),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(bundler: &Ed25519Signer) -> Receipt {
+        Receipt::sign(bundler, [3u8; 32], 1_700_000_000_000, 1_500_200)
+    }
+
+    #[test]
+    fn verifies_against_the_bundler_key_only() {
+        let bundler = Ed25519Signer::from_seed([1u8; 32]);
+        let impostor = Ed25519Signer::from_seed([2u8; 32]);
+        let receipt = receipt(&bundler);
+
+        assert_eq!(receipt.verify(&bundler.public_key()), Ok(()));
+        assert_eq!(receipt.verify(&impostor.public_key()), Err(IrysError::InvalidReceipt));
+
+        // a receipt re-signed by another key doesn't pass for the bundler's
+        let mut forged = receipt.clone();
+        forged.signature = impostor.sign(&message(&forged.id, forged.timestamp, &forged.version, forged.deadline_height));
+        assert_eq!(forged.verify(&bundler.public_key()), Err(IrysError::InvalidReceipt));
+    }
+
+    #[test]
+    fn signature_covers_every_field() {
+        let bundler = Ed25519Signer::from_seed([1u8; 32]);
+        let receipt = receipt(&bundler);
+        let tampered: [fn(&mut Receipt); 4] = [
+            |receipt| receipt.id[0] ^= 1,
+            |receipt| receipt.timestamp += 1,
+            |receipt| receipt.version = "2.0.0".into(),
+            |receipt| receipt.deadline_height += 1,
+        ];
+        for tamper in tampered {
+            let mut receipt = receipt.clone();
+            tamper(&mut receipt);
+            assert_eq!(receipt.verify(&bundler.public_key()), Err(IrysError::InvalidReceipt));
+        }
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let bundler = Ed25519Signer::from_seed([1u8; 32]);
+        let receipt = receipt(&bundler);
+        assert_eq!(Receipt::from_json(&receipt.to_json()), Ok(receipt));
+
+        // bundlers add fields of their own, which are ignored
+        let extended = r#"{"id":"AwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwM","timestamp":1,"version":"1.0.0",
+            "deadlineHeight":2,"block":3,"validatorSignatures":[],
+            "public":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE",
+            "signature":"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ"}"#;
+        assert_eq!(Receipt::from_json(extended).unwrap().id, [3u8; 32]);
+
+        for malformed in ["", "{}", r#"{"id":"AwMD","timestamp":1}"#] {
+            assert_eq!(Receipt::from_json(malformed), Err(IrysError::InvalidReceipt));
+        }
+    }
+}
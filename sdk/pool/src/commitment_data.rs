@@ -0,0 +1,209 @@
+use crate::error::PoolError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// Prefix of the log line `emit!` writes an event to.
+pub const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Tag that starts the instruction data of an `emit_cpi!` self-invocation.
+pub const EVENT_IX_TAG: [u8; 8] = 0x1d9a_cb51_2ea5_45e4u64.to_le_bytes();
+
+/// `sha256("event:CommitmentData")[..8]`
+pub const COMMITMENT_DATA_DISCRIMINATOR: [u8; 8] = [13, 110, 215, 127, 244, 62, 234, 34];
+
+/// `sha256("event:ShardCommitmentData")[..8]`
+pub const SHARD_COMMITMENT_DATA_DISCRIMINATOR: [u8; 8] = [184, 129, 136, 167, 197, 53, 50, 39];
+
+/// A new output and its encrypted note, from a `CommitmentData` or
+/// `ShardCommitmentData` event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentData {
+    pub shard_id: Option<u8>, // None for the main tree
+    pub index: u64,
+    pub commitment: [u8; 32],
+    pub encrypted_output: Vec<u8>,
+}
+
+impl CommitmentData {
+    /// Decodes an event, discriminator included. `None` if it is another event.
+    pub fn from_event(bytes: &[u8]) -> Result<Option<Self>, PoolError> {
+        if bytes.len() < 8 {
+            return Err(PoolError::InvalidEvent);
+        }
+        let (discriminator, fields) = bytes.split_at(8);
+        let (shard_id, fields) = if discriminator == COMMITMENT_DATA_DISCRIMINATOR {
+            (None, fields)
+        } else if discriminator == SHARD_COMMITMENT_DATA_DISCRIMINATOR {
+            let (&shard_id, fields) = fields.split_first().ok_or(PoolError::InvalidEvent)?;
+            (Some(shard_id), fields)
+        } else {
+            return Ok(None);
+        };
+
+        // index (8) || commitment (32) || encrypted_output length (4) || encrypted_output
+        if fields.len() < 44 {
+            return Err(PoolError::InvalidEvent);
+        }
+        let index = u64::from_le_bytes(fields[..8].try_into().expect("range is 8 bytes"));
+        let commitment = fields[8..40].try_into().expect("range is 32 bytes");
+        let output_len = u32::from_le_bytes(fields[40..44].try_into().expect("range is 4 bytes")) as usize;
+        let encrypted_output = &fields[44..];
+        if encrypted_output.len() != output_len {
+            return Err(PoolError::InvalidEvent);
+        }
+
+        Ok(Some(Self {
+            shard_id,
+            index,
+            commitment,
+            encrypted_output: encrypted_output.to_vec(),
+        }))
+    }
+
+    /// Decodes the data of an inner instruction that invoked the txnsfr program, as
+    /// `emit_cpi!` does. `None` if it is another instruction or event.
+    pub fn from_cpi_data(data: &[u8]) -> Result<Option<Self>, PoolError> {
+        match data.strip_prefix(EVENT_IX_TAG.as_slice()) {
+            Some(event) => Self::from_event(event),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes inner instruction data as reported by RPC nodes, base58 encoded.
+    pub fn from_cpi_base58(data: &str) -> Result<Option<Self>, PoolError> {
+        let bytes = bs58::decode(data).into_vec().map_err(|_| PoolError::InvalidEvent)?;
+        Self::from_cpi_data(&bytes)
+    }
+
+    /// Decodes a log line written by `emit!` in the txnsfr program. `None` if it is
+    /// another line or event.
+    pub fn from_log(line: &str) -> Result<Option<Self>, PoolError> {
+        let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) else {
+            return Ok(None);
+        };
+        let bytes = STANDARD.decode(data).map_err(|_| PoolError::InvalidEvent)?;
+        Self::from_event(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use sha2::{Digest, Sha256};
+
+    /// The program's events, as declared in the txnsfr program.
+    #[derive(BorshSerialize)]
+    struct ProgramCommitmentData {
+        index: u64,
+        commitment: [u8; 32],
+        encrypted_output: Vec<u8>,
+    }
+
+    #[derive(BorshSerialize)]
+    struct ProgramShardCommitmentData {
+        shard_id: u8,
+        index: u64,
+        commitment: [u8; 32],
+        encrypted_output: Vec<u8>,
+    }
+
+    fn event(discriminator: [u8; 8], fields: impl BorshSerialize) -> Vec<u8> {
+        [discriminator.as_slice(), &borsh::to_vec(&fields).unwrap()].concat()
+    }
+
+    fn commitment_data() -> Vec<u8> {
+        event(
+            COMMITMENT_DATA_DISCRIMINATOR,
+            ProgramCommitmentData {
+                index: 41,
+                commitment: [7; 32],
+                encrypted_output: vec![0xee; 256],
+            },
+        )
+    }
+
+    fn expected() -> CommitmentData {
+        CommitmentData {
+            shard_id: None,
+            index: 41,
+            commitment: [7; 32],
+            encrypted_output: vec![0xee; 256],
+        }
+    }
+
+    #[test]
+    fn discriminators_match_anchor() {
+        let discriminator = |name: &str| -> [u8; 8] { Sha256::digest(format!("event:{name}"))[..8].try_into().unwrap() };
+        assert_eq!(discriminator("CommitmentData"), COMMITMENT_DATA_DISCRIMINATOR);
+        assert_eq!(discriminator("ShardCommitmentData"), SHARD_COMMITMENT_DATA_DISCRIMINATOR);
+        // anchor_lang::event::EVENT_IX_TAG_LE
+        assert_eq!(EVENT_IX_TAG, [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d]);
+    }
+
+    #[test]
+    fn decodes_self_cpi_events() {
+        let data = [EVENT_IX_TAG.as_slice(), &commitment_data()].concat();
+        assert_eq!(CommitmentData::from_cpi_data(&data), Ok(Some(expected())));
+        assert_eq!(
+            CommitmentData::from_cpi_base58(&bs58::encode(&data).into_string()),
+            Ok(Some(expected()))
+        );
+
+        // the transact instruction itself, or any other inner instruction
+        assert_eq!(CommitmentData::from_cpi_data(&commitment_data()), Ok(None));
+        assert!(CommitmentData::from_cpi_base58("0OIl").is_err());
+    }
+
+    #[test]
+    fn decodes_log_events() {
+        let line = format!("{PROGRAM_DATA_PREFIX}{}", STANDARD.encode(commitment_data()));
+        assert_eq!(CommitmentData::from_log(&line), Ok(Some(expected())));
+
+        assert_eq!(CommitmentData::from_log("Program log: Instruction: Transact"), Ok(None));
+        assert!(CommitmentData::from_log("Program data: not base64!").is_err());
+    }
+
+    #[test]
+    fn decodes_both_formats_the_same() {
+        let event = commitment_data();
+        let line = format!("{PROGRAM_DATA_PREFIX}{}", STANDARD.encode(&event));
+        let cpi = [EVENT_IX_TAG.as_slice(), &event].concat();
+        assert_eq!(CommitmentData::from_log(&line), CommitmentData::from_cpi_data(&cpi));
+    }
+
+    #[test]
+    fn decodes_shard_events() {
+        let data = event(
+            SHARD_COMMITMENT_DATA_DISCRIMINATOR,
+            ProgramShardCommitmentData {
+                shard_id: 3,
+                index: 41,
+                commitment: [7; 32],
+                encrypted_output: vec![0xee; 256],
+            },
+        );
+        let decoded = CommitmentData::from_event(&data).unwrap().unwrap();
+        assert_eq!(decoded, CommitmentData { shard_id: Some(3), ..expected() });
+    }
+
+    #[test]
+    fn skips_other_events_and_rejects_malformed_ones() {
+        let mut other = commitment_data();
+        other[0] ^= 1;
+        assert_eq!(CommitmentData::from_event(&other), Ok(None));
+
+        let valid = commitment_data();
+        assert_eq!(CommitmentData::from_event(&valid[..4]), Err(PoolError::InvalidEvent));
+        assert_eq!(CommitmentData::from_event(&valid[..50]), Err(PoolError::InvalidEvent));
+        assert_eq!(CommitmentData::from_event(&valid[..valid.len() - 1]), Err(PoolError::InvalidEvent));
+        assert_eq!(
+            CommitmentData::from_event(&[valid.as_slice(), &[0]].concat()),
+            Err(PoolError::InvalidEvent)
+        );
+        assert_eq!(
+            CommitmentData::from_event(&SHARD_COMMITMENT_DATA_DISCRIMINATOR),
+            Err(PoolError::InvalidEvent)
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PoolError {
+    #[error("Malformed transact return data")]
+    InvalidReturnData,
+
+    #[error("Output index out of range")]
+    InvalidOutput,
+
+    #[error("Malformed CommitmentData event")]
+    InvalidEvent,
+}
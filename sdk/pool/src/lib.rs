@@ -0,0 +1,37 @@
+//! Client helpers for txnsfr shielded pool transactions.
+//!
+//! The transact instructions return where their two outputs were inserted: the leaf
+//! indices, the tree root after the insertion and the Merkle path above the pair. Trees
+//! that queue their outputs return the leaf indices only, and drop withdrawals whose
+//! outputs are both zero notes, returning no leaf indices at all.
+//! [`TransactResult`] decodes that return data, so a wallet can build the input of its
+//! next transaction from a note it just received without waiting for an indexer.
+//!
+//! ```rust,ignore
+//! // `returnData.data[0]` of the confirmed transaction, after checking that
+//! // `returnData.programId` is the txnsfr program
+//! let result = TransactResult::from_base64(&return_data)?;
+//! let path = result.merkle_path(0, output_commitments)?;
+//! // prove against `result.root` with `path.leaf_index` and `path.siblings`
+//! ```
+//!
+//! Each output's encrypted note comes in a `CommitmentData` event (`ShardCommitmentData`
+//! for tree shards). The program emits them through a self-CPI, so they are kept in the
+//! transaction's inner instructions rather than in logs that validators truncate and
+//! RPC nodes drop. Transactions from before that change carry them in `Program data:`
+//! log lines. [`CommitmentData`] decodes both.
+//!
+//! ```rust,ignore
+//! // for each inner instruction whose program is txnsfr
+//! if let Some(event) = CommitmentData::from_cpi_base58(&instruction.data)? { .. }
+//! // for each log line of older transactions
+//! if let Some(event) = CommitmentData::from_log(&line)? { .. }
+//! ```
+
+pub mod commitment_data;
+pub mod error;
+pub mod transact_result;
+
+pub use commitment_data::CommitmentData;
+pub use error::PoolError;
+pub use transact_result::{MerklePath, TransactResult, MERKLE_TREE_HEIGHT};
@@ -0,0 +1,303 @@
+use crate::error::PoolError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// Height of every pool tree, main tree and shards alike.
+pub const MERKLE_TREE_HEIGHT: usize = 26;
+
+/// Length of the return data of an inserted pair, the longest a transact returns.
+const MAX_RETURN_DATA_LEN: usize = 1 + 16 + 1 + 32 + 4 + (MERKLE_TREE_HEIGHT - 1) * 32;
+
+/// Where a transact instruction inserted its two outputs, as returned by the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactResult {
+    pub leaf_indices: Option<[u64; 2]>, // None if a queued tree dropped two zero notes
+    pub root: Option<[u8; 32]>, // None if the outputs were queued
+    pub path: Vec<[u8; 32]>,    // siblings above the output pair; empty if the outputs were queued
+}
+
+/// Merkle path of one output, bottom up, valid against [`TransactResult::root`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerklePath {
+    pub leaf_index: u64,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl TransactResult {
+    /// Decodes the Borsh-encoded return data of a transact instruction.
+    ///
+    /// The runtime strips trailing zero bytes from return data, so queued and dropped
+    /// results, and paths whose last sibling ends in zero, arrive cut short. Missing
+    /// bytes are read as zeros.
+    pub fn from_return_data(return_data: &[u8]) -> Result<Self, PoolError> {
+        if return_data.len() > MAX_RETURN_DATA_LEN {
+            return Err(PoolError::InvalidReturnData);
+        }
+        let mut padded = [0u8; MAX_RETURN_DATA_LEN];
+        padded[..return_data.len()].copy_from_slice(return_data);
+        let bytes = &padded[..];
+
+        // leaf_indices option tag (1) || leaf_indices (16, if set) || root option tag (1)
+        // || root (32, if set) || path length (4) || path
+        let (leaf_indices, rest) = match bytes.first() {
+            Some(0) => (None, &bytes[1..]),
+            Some(1) if bytes.len() >= 17 => {
+                let word = |range: std::ops::Range<usize>| -> u64 {
+                    u64::from_le_bytes(bytes[range].try_into().expect("range is 8 bytes"))
+                };
+                (Some([word(1..9), word(9..17)]), &bytes[17..])
+            }
+            _ => return Err(PoolError::InvalidReturnData),
+        };
+        let (root, rest) = match rest.first() {
+            Some(0) => (None, &rest[1..]),
+            Some(1) if rest.len() >= 33 => (
+                Some(rest[1..33].try_into().expect("range is 32 bytes")),
+                &rest[33..],
+            ),
+            _ => return Err(PoolError::InvalidReturnData),
+        };
+        if rest.len() < 4 {
+            return Err(PoolError::InvalidReturnData);
+        }
+        let path_len = u32::from_le_bytes(rest[..4].try_into().expect("range is 4 bytes")) as usize;
+
+        // Inserted outputs come with the root and the full path, queued ones with neither
+        // Dropped outputs have neither
+        let expected_path_len = if root.is_some() { MERKLE_TREE_HEIGHT - 1 } else { 0 };
+        let siblings = &rest[4..];
+        let encoded_len = MAX_RETURN_DATA_LEN - siblings.len() + path_len * 32;
+        let pair_aligned = match leaf_indices {
+            Some([first, second]) => first % 2 == 0 && second == first + 1,
+            None => root.is_none(),
+        };
+        if path_len != expected_path_len || return_data.len() > encoded_len || !pair_aligned {
+            return Err(PoolError::InvalidReturnData);
+        }
+
+        let path = siblings[..path_len * 32]
+            .chunks_exact(32)
+            .map(|sibling| sibling.try_into().expect("chunk is 32 bytes"))
+            .collect();
+
+        Ok(Self {
+            leaf_indices,
+            root,
+            path,
+        })
+    }
+
+    /// Decodes return data as reported by RPC nodes, base64 encoded.
+    pub fn from_base64(data: &str) -> Result<Self, PoolError> {
+        let bytes = STANDARD.decode(data).map_err(|_| PoolError::InvalidReturnData)?;
+        Self::from_return_data(&bytes)
+    }
+
+    /// Whether the outputs went into a commitment queue. Queued outputs have no path
+    /// yet and can only be spent once a batch has inserted them.
+    pub fn is_queued(&self) -> bool {
+        self.leaf_indices.is_some() && self.root.is_none()
+    }
+
+    /// Whether the outputs were two zero notes that a queued tree dropped instead of
+    /// queueing. There is nothing to spend.
+    pub fn is_dropped(&self) -> bool {
+        self.leaf_indices.is_none()
+    }
+
+    /// Merkle path of output `output` (0 or 1), given both output commitments in the
+    /// order they were passed to the instruction. `None` if the outputs were queued or
+    /// dropped.
+    pub fn merkle_path(
+        &self,
+        output: usize,
+        output_commitments: [[u8; 32]; 2],
+    ) -> Result<Option<MerklePath>, PoolError> {
+        if output > 1 {
+            return Err(PoolError::InvalidOutput);
+        }
+        let Some(leaf_indices) = self.leaf_indices else {
+            return Ok(None);
+        };
+        if self.is_queued() {
+            return Ok(None);
+        }
+
+        // The outputs are siblings, so each one's path starts with the other
+        let mut siblings = Vec::with_capacity(MERKLE_TREE_HEIGHT);
+        siblings.push(output_commitments[1 - output]);
+        siblings.extend_from_slice(&self.path);
+
+        Ok(Some(MerklePath {
+            leaf_index: leaf_indices[output],
+            siblings,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    /// The program's `TransactResult`, as declared in the txnsfr program.
+    #[derive(BorshSerialize)]
+    struct ProgramTransactResult {
+        leaf_indices: Option<[u64; 2]>,
+        root: Option<[u8; 32]>,
+        path: Vec<[u8; 32]>,
+    }
+
+    fn sibling(level: usize) -> [u8; 32] {
+        [level as u8 + 1; 32]
+    }
+
+    fn inserted(first_index: u64) -> ProgramTransactResult {
+        ProgramTransactResult {
+            leaf_indices: Some([first_index, first_index + 1]),
+            root: Some([0xaa; 32]),
+            path: (1..MERKLE_TREE_HEIGHT).map(sibling).collect(),
+        }
+    }
+
+    fn encode(value: &ProgramTransactResult) -> Vec<u8> {
+        borsh::to_vec(value).unwrap()
+    }
+
+    #[test]
+    fn decodes_inserted_outputs() {
+        let result = TransactResult::from_return_data(&encode(&inserted(6))).unwrap();
+
+        assert_eq!(result.leaf_indices, Some([6, 7]));
+        assert_eq!(result.root, Some([0xaa; 32]));
+        assert_eq!(result.path.len(), MERKLE_TREE_HEIGHT - 1);
+        assert!(!result.is_queued());
+
+        let commitments = [[1u8; 32], [2u8; 32]];
+        let second = result.merkle_path(1, commitments).unwrap().unwrap();
+        assert_eq!(second.leaf_index, 7);
+        assert_eq!(second.siblings.len(), MERKLE_TREE_HEIGHT);
+        assert_eq!(second.siblings[0], commitments[0]);
+        assert_eq!(second.siblings[1], sibling(1));
+        assert_eq!(result.merkle_path(0, commitments).unwrap().unwrap().siblings[0], commitments[1]);
+    }
+
+    #[test]
+    fn decodes_queued_outputs() {
+        let queued = ProgramTransactResult {
+            leaf_indices: Some([40, 41]),
+            root: None,
+            path: Vec::new(),
+        };
+        let result = TransactResult::from_return_data(&encode(&queued)).unwrap();
+
+        assert_eq!(result.leaf_indices, Some([40, 41]));
+        assert!(result.is_queued());
+        assert!(!result.is_dropped());
+        assert_eq!(result.merkle_path(0, [[1; 32], [2; 32]]), Ok(None));
+    }
+
+    #[test]
+    fn decodes_dropped_outputs() {
+        let dropped = ProgramTransactResult {
+            leaf_indices: None,
+            root: None,
+            path: Vec::new(),
+        };
+        let result = TransactResult::from_return_data(&encode(&dropped)).unwrap();
+
+        assert!(result.is_dropped());
+        assert!(!result.is_queued());
+        assert_eq!(result.merkle_path(1, [[1; 32], [2; 32]]), Ok(None));
+
+        // dropped outputs are never inserted, so they come without a root
+        let mut with_root = inserted(0);
+        with_root.leaf_indices = None;
+        assert!(TransactResult::from_return_data(&encode(&with_root)).is_err());
+    }
+
+    #[test]
+    fn decodes_base64_return_data() {
+        let bytes = encode(&inserted(0));
+        let result = TransactResult::from_base64(&STANDARD.encode(&bytes)).unwrap();
+        assert_eq!(result, TransactResult::from_return_data(&bytes).unwrap());
+        assert!(TransactResult::from_base64("not base64!").is_err());
+    }
+
+    /// Return data as the runtime records it, without its trailing zero bytes.
+    fn strip_trailing_zeros(mut bytes: Vec<u8>) -> Vec<u8> {
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_return_data_without_trailing_zeros() {
+        let queued = ProgramTransactResult {
+            leaf_indices: Some([40, 41]),
+            root: None,
+            path: Vec::new(),
+        };
+        let stripped = strip_trailing_zeros(encode(&queued));
+        assert_eq!(stripped.len(), 10);
+        let result = TransactResult::from_return_data(&stripped).unwrap();
+        assert_eq!(result.leaf_indices, Some([40, 41]));
+        assert!(result.is_queued());
+
+        let dropped = ProgramTransactResult {
+            leaf_indices: None,
+            root: None,
+            path: Vec::new(),
+        };
+        assert!(strip_trailing_zeros(encode(&dropped)).is_empty());
+        assert!(TransactResult::from_return_data(&[]).unwrap().is_dropped());
+
+        // a last sibling that ends in zero bytes
+        let mut inserted = inserted(4);
+        inserted.path[MERKLE_TREE_HEIGHT - 2][16..].fill(0);
+        let bytes = encode(&inserted);
+        let stripped = strip_trailing_zeros(bytes.clone());
+        assert_eq!(stripped.len(), bytes.len() - 16);
+        assert_eq!(
+            TransactResult::from_return_data(&stripped),
+            TransactResult::from_return_data(&bytes)
+        );
+        assert_eq!(TransactResult::from_return_data(&stripped).unwrap().path, inserted.path);
+
+        let stripped = STANDARD.encode(&stripped);
+        assert_eq!(TransactResult::from_base64(&stripped).unwrap().path, inserted.path);
+    }
+
+    #[test]
+    fn rejects_malformed_return_data() {
+        let valid = encode(&inserted(2));
+        assert!(TransactResult::from_return_data(&[valid.as_slice(), &[0]].concat()).is_err());
+        assert!(TransactResult::from_return_data(&[valid.as_slice(), &[1]].concat()).is_err());
+        assert!(TransactResult::from_return_data(&valid[..21]).is_err());
+
+        // a root without a path, a path without a root and odd first indices are not
+        // something the program returns
+        let mut root_only = inserted(2);
+        root_only.path.clear();
+        assert!(TransactResult::from_return_data(&encode(&root_only)).is_err());
+
+        let mut path_only = inserted(2);
+        path_only.root = None;
+        assert!(TransactResult::from_return_data(&encode(&path_only)).is_err());
+
+        assert!(TransactResult::from_return_data(&encode(&inserted(3))).is_err());
+
+        let mut bad_tag = valid.clone();
+        bad_tag[17] = 2;
+        assert!(TransactResult::from_return_data(&bad_tag).is_err());
+        let mut bad_tag = valid.clone();
+        bad_tag[0] = 2;
+        assert!(TransactResult::from_return_data(&bad_tag).is_err());
+
+        assert_eq!(
+            TransactResult::from_return_data(&encode(&inserted(0))).unwrap().merkle_path(2, [[0; 32]; 2]),
+            Err(PoolError::InvalidOutput)
+        );
+    }
+}